@@ -0,0 +1,472 @@
+//! OpenAI-compatible HTTP API.
+//!
+//! Serves `/v1/chat/completions` and `/v1/models` so OpenAI-compatible clients
+//! (editors, Open WebUI, shell tools) can talk to Cica. Bearer tokens are created
+//! per user with the `/token` chat command, so each client gets that user's
+//! identity, memories and skills.
+
+use std::convert::Infallible;
+
+use anyhow::{Context, Result};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::backends::{self, QueryOptions};
+use crate::channels::{get_channel_info, reindex_user_memories};
+use crate::config::{ApiConfig, Config};
+use crate::onboarding;
+use crate::pairing::PairingStore;
+
+/// Model ID advertised to clients
+const MODEL_ID: &str = "cica";
+
+/// Largest request body read, so no client can exhaust memory
+pub const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+// ============================================================================
+// Request Types
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionRequest {
+    model: Option<String>,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    role: String,
+    #[serde(default)]
+    content: Value,
+}
+
+impl ChatMessage {
+    /// Extract the text of a message. Content may be a plain string or an
+    /// array of content parts, of which only text parts are kept.
+    fn text(&self) -> String {
+        match &self.content {
+            Value::String(s) => s.clone(),
+            Value::Array(parts) => parts
+                .iter()
+                .filter(|p| p.get("type").and_then(Value::as_str) == Some("text"))
+                .filter_map(|p| p.get("text").and_then(Value::as_str))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        }
+    }
+}
+
+// ============================================================================
+// Server
+// ============================================================================
+
+/// Run the API server
+pub async fn run(config: ApiConfig) -> Result<()> {
+    let listener = TcpListener::bind((config.host.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind API server to {}:{}",
+                config.host, config.port
+            )
+        })?;
+
+    info!(
+        "OpenAI-compatible API listening on http://{}/v1",
+        listener.local_addr()?
+    );
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Failed to accept API connection: {}", e);
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            let io = TokioIo::new(stream);
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(io, service_fn(handle_request))
+                .await
+            {
+                warn!("API connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Route an incoming request
+async fn handle_request(req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/v1/models") => match authenticate(&req) {
+            Some(_) => json_response(StatusCode::OK, models_response()),
+            None => unauthorized(),
+        },
+        (&Method::POST, "/v1/chat/completions") => match authenticate(&req) {
+            Some((channel, user_id)) => chat_completions(req, &channel, &user_id).await,
+            None => unauthorized(),
+        },
+        _ => error_response(StatusCode::NOT_FOUND, "not_found", "Unknown endpoint"),
+    };
+
+    Ok(response)
+}
+
+/// Resolve the bearer token to an approved (channel, user_id)
+fn authenticate(req: &Request<Incoming>) -> Option<(String, String)> {
+    let token = req
+        .headers()
+        .get(hyper::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .trim();
+
+    PairingStore::load().ok()?.user_for_api_token(token)
+}
+
+/// Handle POST /v1/chat/completions
+async fn chat_completions(
+    req: Request<Incoming>,
+    channel: &str,
+    user_id: &str,
+) -> Response<Full<Bytes>> {
+    let body = match read_body(req.into_body()).await {
+        Ok(body) => body,
+        Err((status, message)) => {
+            return error_response(status, "invalid_request_error", &message);
+        }
+    };
+
+    let request: ChatCompletionRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                &e.to_string(),
+            );
+        }
+    };
+
+    let Some((prompt, client_instructions)) = build_prompt(&request.messages) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "messages must contain at least one user message",
+        );
+    };
+
+    info!("API request from {}:{}: {}", channel, user_id, prompt);
+
+    let mut store = match PairingStore::load() {
+        Ok(store) => store,
+        Err(e) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                &e.to_string(),
+            );
+        }
+    };
+
+    // API requests count against the same daily limit as chat messages
+    match use_message(&mut store, channel, user_id) {
+        Ok(true) => {}
+        Ok(false) => {
+            return error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_error",
+                "You've reached today's message limit. It resets at midnight UTC.",
+            );
+        }
+        Err(e) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                &e.to_string(),
+            );
+        }
+    }
+
+    // Persona, memories and skills come from the token owner's context
+    let channel_display = get_channel_info(channel).map(|c| c.display_name);
+    let mut context_prompt = match onboarding::build_context_prompt_for_user(
+        &store,
+        channel_display,
+        Some(channel),
+        Some(user_id),
+        Some(&prompt),
+    ) {
+        Ok(p) => p,
+        Err(e) => {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                &e.to_string(),
+            );
+        }
+    };
+
+    if let Some(instructions) = client_instructions {
+        context_prompt.push_str("\n\n## Client Instructions\n");
+        context_prompt.push_str(&instructions);
+    }
+
+    // OpenAI clients send the full history on every request, so no session is resumed
+    let result = backends::query_with_options(
+        &prompt,
+        QueryOptions {
+            system_prompt: Some(context_prompt),
+            ..QueryOptions::for_user(channel, user_id)
+        },
+    )
+    .await;
+
+    reindex_user_memories(&store, channel, user_id);
+
+    let content = match result {
+        Ok((response, _session_id)) => response,
+        Err(e) => {
+            warn!("API query failed: {}", e);
+            return error_response(StatusCode::BAD_GATEWAY, "server_error", &e.to_string());
+        }
+    };
+
+    let model = request.model.unwrap_or_else(|| MODEL_ID.to_string());
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+    let created = chrono::Utc::now().timestamp();
+
+    if request.stream {
+        sse_response(&id, created, &model, &content)
+    } else {
+        json_response(
+            StatusCode::OK,
+            json!({
+                "id": id,
+                "object": "chat.completion",
+                "created": created,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 }
+            }),
+        )
+    }
+}
+
+/// Count a request against the token owner's daily limit.
+/// Returns false if the limit has been reached.
+fn use_message(store: &mut PairingStore, channel: &str, user_id: &str) -> Result<bool> {
+    let config = Config::load()?;
+    let role = store.role(&config, channel, user_id);
+    store.use_message(channel, user_id, role.daily_messages)
+}
+
+/// Turn an OpenAI message list into a single prompt plus any client system instructions.
+///
+/// The last user message becomes the prompt; earlier turns are included as a
+/// transcript so the backend sees the conversation the client is holding.
+fn build_prompt(messages: &[ChatMessage]) -> Option<(String, Option<String>)> {
+    let system: Vec<String> = messages
+        .iter()
+        .filter(|m| m.role == "system" || m.role == "developer")
+        .map(|m| m.text())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let turns: Vec<&ChatMessage> = messages
+        .iter()
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .collect();
+
+    let last_user = turns.iter().rposition(|m| m.role == "user")?;
+    let current = turns[last_user].text();
+
+    let prompt = if last_user == 0 {
+        current
+    } else {
+        let mut transcript = String::from("Conversation so far:\n");
+        for turn in &turns[..last_user] {
+            let speaker = if turn.role == "user" {
+                "User"
+            } else {
+                "Assistant"
+            };
+            transcript.push_str(&format!("\n{}: {}\n", speaker, turn.text()));
+        }
+        format!("{}\nCurrent message:\n{}", transcript, current)
+    };
+
+    let instructions = (!system.is_empty()).then(|| system.join("\n\n"));
+
+    Some((prompt, instructions))
+}
+
+/// Body for GET /v1/models
+fn models_response() -> Value {
+    json!({
+        "object": "list",
+        "data": [{
+            "id": MODEL_ID,
+            "object": "model",
+            "created": 0,
+            "owned_by": "cica"
+        }]
+    })
+}
+
+// ============================================================================
+// Response Helpers
+// ============================================================================
+
+fn json_response(status: StatusCode, body: Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+/// Read a request body of up to `MAX_BODY_BYTES`. Fails with the status to
+/// answer with: 413 for a larger body, 400 when it can't be read.
+pub async fn read_body<B>(body: B) -> std::result::Result<Bytes, (StatusCode, String)>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    match Limited::new(body, MAX_BODY_BYTES).collect().await {
+        Ok(body) => Ok(body.to_bytes()),
+        Err(e) if e.downcast_ref::<LengthLimitError>().is_some() => Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Request body is larger than {} bytes", MAX_BODY_BYTES),
+        )),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+fn error_response(status: StatusCode, error_type: &str, message: &str) -> Response<Full<Bytes>> {
+    json_response(
+        status,
+        json!({ "error": { "message": message, "type": error_type, "code": null } }),
+    )
+}
+
+fn unauthorized() -> Response<Full<Bytes>> {
+    error_response(
+        StatusCode::UNAUTHORIZED,
+        "invalid_api_key",
+        "Invalid API token. Send /token to Cica in chat to get one.",
+    )
+}
+
+/// Stream the full response as a single chunk for clients that request `stream: true`
+fn sse_response(id: &str, created: i64, model: &str, content: &str) -> Response<Full<Bytes>> {
+    let chunk = |delta: Value, finish_reason: Value| {
+        json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+        })
+    };
+
+    let body = format!(
+        "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+        chunk(
+            json!({ "role": "assistant", "content": content }),
+            Value::Null
+        ),
+        chunk(json!({}), json!("stop")),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+        .header(hyper::header::CACHE_CONTROL, "no-cache")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: Value) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_message_text_parts() {
+        let msg = message(
+            "user",
+            json!([
+                { "type": "text", "text": "hello" },
+                { "type": "image_url", "image_url": { "url": "http://x" } },
+                { "type": "text", "text": "world" }
+            ]),
+        );
+        assert_eq!(msg.text(), "hello\nworld");
+    }
+
+    #[test]
+    fn test_build_prompt_single_message() {
+        let messages = vec![
+            message("system", json!("Be brief.")),
+            message("user", json!("What time is it?")),
+        ];
+        let (prompt, instructions) = build_prompt(&messages).unwrap();
+        assert_eq!(prompt, "What time is it?");
+        assert_eq!(instructions.as_deref(), Some("Be brief."));
+    }
+
+    #[test]
+    fn test_build_prompt_with_history() {
+        let messages = vec![
+            message("user", json!("Hi")),
+            message("assistant", json!("Hello!")),
+            message("user", json!("Remember me?")),
+        ];
+        let (prompt, instructions) = build_prompt(&messages).unwrap();
+        assert!(prompt.contains("User: Hi"));
+        assert!(prompt.contains("Assistant: Hello!"));
+        assert!(prompt.ends_with("Current message:\nRemember me?"));
+        assert!(instructions.is_none());
+    }
+
+    #[test]
+    fn test_build_prompt_requires_user_message() {
+        let messages = vec![message("system", json!("Be brief."))];
+        assert!(build_prompt(&messages).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_body_limit() {
+        let small = Full::new(Bytes::from_static(b"{}"));
+        assert_eq!(read_body(small).await.unwrap(), Bytes::from_static(b"{}"));
+
+        let large = Full::new(Bytes::from(vec![b' '; MAX_BODY_BYTES + 1]));
+        let (status, _) = read_body(large).await.unwrap_err();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -2,10 +2,10 @@
 
 use anyhow::{Result, anyhow, bail};
 use serde::Deserialize;
-use std::process::Stdio;
 use tokio::process::Command;
 use tracing::{debug, info, warn};
 
+use super::{Activity, ProgressSender, file_name, run_streaming, shorten};
 use crate::config::{self, Config};
 use crate::setup;
 
@@ -22,6 +22,25 @@ struct ClaudeResponse {
     result: Option<String>,
     session_id: Option<String>,
     duration_ms: Option<u64>,
+    message: Option<AssistantMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssistantMessage {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    ToolUse {
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Default)]
@@ -32,6 +51,12 @@ pub struct QueryOptions {
     pub skip_permissions: bool,
     /// Model alias ("sonnet", "opus") or full model ID (e.g. "claude-sonnet-4-5-20250929")
     pub model: Option<String>,
+    /// Tools Claude may not use (e.g. "Bash", "WebFetch")
+    pub disallowed_tools: Vec<String>,
+    /// Tools or permission rules Claude may use without asking
+    pub allowed_tools: Vec<String>,
+    /// Told about each tool Claude uses while answering
+    pub progress: Option<ProgressSender>,
 }
 
 #[allow(dead_code)]
@@ -70,9 +95,19 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
     debug!("Using claude_code: {:?}", claude_code);
 
     let mut cmd = Command::new(&bun);
-    cmd.arg("run")
-        .arg(&claude_code)
-        .args(["-p", "--output-format", "json"])
+    cmd.arg("run").arg(&claude_code);
+
+    // Takes several values, so keep it clear of the prompt at the end
+    if !options.disallowed_tools.is_empty() {
+        cmd.arg("--disallowedTools")
+            .arg(options.disallowed_tools.join(","));
+    }
+    if !options.allowed_tools.is_empty() {
+        cmd.arg("--allowedTools")
+            .arg(options.allowed_tools.join(","));
+    }
+
+    cmd.args(["-p", "--output-format", "stream-json", "--verbose"])
         .env("HOME", &paths.claude_home);
 
     if options.skip_permissions {
@@ -145,15 +180,18 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
         }
     }
 
-    let output = cmd
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await?;
+    let progress = options.progress;
+    let output = run_streaming(cmd, |line| {
+        if let Some(progress) = &progress {
+            for activity in tool_activities(line) {
+                let _ = progress.send(activity);
+            }
+        }
+    })
+    .await?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = &output.stdout;
+    let stderr = &output.stderr;
 
     if !output.status.success() {
         warn!("Claude CLI failed. stdout: {}", stdout);
@@ -162,7 +200,7 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
             "Claude CLI failed (exit {:?}): {}{}",
             output.status.code(),
             stderr,
-            if stderr.is_empty() { &stdout } else { "" }
+            if stderr.is_empty() { stdout } else { "" }
         );
     }
 
@@ -191,3 +229,73 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
 
     Err(anyhow!("No result found in Claude output"))
 }
+
+/// Describe the tools Claude starts using in one line of its output
+fn tool_activities(line: &str) -> Vec<Activity> {
+    let Ok(event) = serde_json::from_str::<ClaudeResponse>(line) else {
+        return Vec::new();
+    };
+    let Some(message) = event.message else {
+        return Vec::new();
+    };
+
+    message
+        .content
+        .into_iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { name, input } => Some(describe_tool_use(&name, &input)),
+            ContentBlock::Other => None,
+        })
+        .collect()
+}
+
+fn describe_tool_use(name: &str, input: &serde_json::Value) -> Activity {
+    let arg = |key: &str| input.get(key).and_then(|v| v.as_str());
+
+    let description = match (name, arg("command"), arg("file_path")) {
+        ("Bash", Some(command), _) => format!("running `{}`", shorten(command)),
+        ("Read", _, Some(path)) => return Activity::reading(path),
+        ("Write" | "Edit" | "MultiEdit", _, Some(path)) => format!("editing {}", file_name(path)),
+        _ => match (name, arg("query"), arg("url"), arg("pattern")) {
+            ("WebSearch", Some(query), _, _) => {
+                format!("searching the web for \"{}\"", shorten(query))
+            }
+            ("WebFetch", _, Some(url), _) => format!("fetching {}", shorten(url)),
+            ("Grep" | "Glob", _, _, Some(pattern)) => format!("searching for {}", shorten(pattern)),
+            _ => format!("using {}", name),
+        },
+    };
+    Activity::new(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_activities() {
+        let line = r#"{"type":"assistant","message":{"content":[
+            {"type":"text","text":"Let me check."},
+            {"type":"tool_use","id":"1","name":"Bash","input":{"command":"cargo test\necho done"}},
+            {"type":"tool_use","id":"2","name":"Read","input":{"file_path":"/home/cica/notes.md"}},
+            {"type":"tool_use","id":"3","name":"TodoWrite","input":{}}
+        ]}}"#;
+        let activities = tool_activities(line);
+        assert_eq!(
+            activities
+                .iter()
+                .map(|a| a.description.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "running `cargo test`",
+                "reading notes.md",
+                "using TodoWrite"
+            ]
+        );
+        assert_eq!(activities[1].reads.as_deref(), Some("/home/cica/notes.md"));
+        assert_eq!(activities[0].reads, None);
+
+        assert!(tool_activities(r#"{"type":"result","result":"Done"}"#).is_empty());
+        assert!(tool_activities("not json").is_empty());
+    }
+}
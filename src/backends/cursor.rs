@@ -7,6 +7,7 @@ use std::process::Stdio;
 use tokio::process::Command;
 use tracing::{debug, info, warn};
 
+use super::{Activity, ProgressSender, file_name, run_streaming, shorten};
 use crate::config::{self, Config};
 use crate::setup;
 
@@ -96,6 +97,9 @@ struct CursorEvent {
     session_id: Option<String>,
     duration_ms: Option<u64>,
     is_error: Option<bool>,
+    subtype: Option<String>,
+    /// Keyed by tool kind, e.g. `{"shellToolCall": {"args": {...}}}`
+    tool_call: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
 #[derive(Default)]
@@ -105,6 +109,8 @@ pub struct QueryOptions {
     pub cwd: Option<String>,
     pub model: Option<String>,
     pub force: bool,
+    /// Told about each tool Cursor uses while answering
+    pub progress: Option<ProgressSender>,
 }
 
 #[allow(dead_code)]
@@ -162,15 +168,18 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
 
     cmd.arg(&full_prompt);
 
-    let output = cmd
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await?;
+    let progress = options.progress;
+    let output = run_streaming(cmd, |line| {
+        if let Some(progress) = &progress
+            && let Some(activity) = tool_activity(line)
+        {
+            let _ = progress.send(activity);
+        }
+    })
+    .await?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = &output.stdout;
+    let stderr = &output.stderr;
 
     if !output.status.success() {
         warn!("Cursor CLI failed. stdout: {}", stdout);
@@ -179,7 +188,7 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
             "Cursor CLI failed (exit {:?}): {}{}",
             output.status.code(),
             stderr,
-            if stderr.is_empty() { &stdout } else { "" }
+            if stderr.is_empty() { stdout } else { "" }
         );
     }
 
@@ -221,6 +230,25 @@ pub async fn query_with_options(prompt: &str, options: QueryOptions) -> Result<(
     }
 }
 
+/// Describe the tool Cursor starts using in one line of its output
+fn tool_activity(line: &str) -> Option<Activity> {
+    let event = serde_json::from_str::<CursorEvent>(line).ok()?;
+    if event.event_type != "tool_call" || event.subtype.as_deref() != Some("started") {
+        return None;
+    }
+    let (kind, call) = event.tool_call?.into_iter().next()?;
+    let arg = |key: &str| call.get("args")?.get(key)?.as_str();
+
+    let description = match kind.as_str() {
+        "shellToolCall" => format!("running `{}`", shorten(arg("command")?)),
+        "readToolCall" => return Some(Activity::reading(arg("path")?)),
+        "writeToolCall" | "editToolCall" => format!("editing {}", file_name(arg("path")?)),
+        "grepToolCall" => format!("searching for {}", shorten(arg("pattern")?)),
+        _ => format!("using {}", kind.trim_end_matches("ToolCall")),
+    };
+    Some(Activity::new(description))
+}
+
 #[cfg(target_os = "macos")]
 async fn ensure_keychain(cursor_home: &Path) -> Result<()> {
     let keychain_dir = cursor_home.join("Library/Keychains");
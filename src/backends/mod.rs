@@ -3,9 +3,44 @@
 pub mod claude;
 pub mod cursor;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
 
-use crate::config::{AiBackend, Config};
+use crate::config::{AiBackend, Config, Role};
+use crate::pairing;
+use crate::skills;
+
+/// Receives each tool the backend starts using
+pub type ProgressSender = mpsc::UnboundedSender<Activity>;
+
+/// A tool the backend started using
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    /// Short description, e.g. "running `cargo test`"
+    pub description: String,
+    /// Path of the file the tool reads, if it reads one
+    pub reads: Option<String>,
+}
+
+impl Activity {
+    fn new(description: String) -> Self {
+        Self {
+            description,
+            reads: None,
+        }
+    }
+
+    fn reading(path: &str) -> Self {
+        Self {
+            description: format!("reading {}", file_name(path)),
+            reads: Some(path.to_string()),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct QueryOptions {
@@ -13,6 +48,53 @@ pub struct QueryOptions {
     pub resume_session: Option<String>,
     pub cwd: Option<String>,
     pub skip_permissions: bool,
+    pub progress: Option<ProgressSender>,
+    /// Overrides the backend's configured model
+    pub model: Option<String>,
+    pub disallowed_tools: Vec<String>,
+    /// Tools or permission rules allowed without asking, when permissions
+    /// aren't skipped (e.g. "Write(//path/**)")
+    pub allowed_tools: Vec<String>,
+}
+
+impl QueryOptions {
+    /// Options limited to what a user's role allows
+    pub fn for_user(channel: &str, user_id: &str) -> Self {
+        Self::for_role(pairing::role_for(channel, user_id))
+    }
+
+    /// Options limited to what a role allows
+    pub fn for_role(role: Role) -> Self {
+        let mut disallowed_tools = hidden_skill_rules(&role);
+        disallowed_tools.extend(role.disallowed_tools);
+        Self {
+            skip_permissions: role.full_access,
+            model: role.model,
+            disallowed_tools,
+            ..Default::default()
+        }
+    }
+}
+
+/// Rules keeping the backend out of the skills a role may not use. Skills
+/// are files the backend reads, so hiding them from the prompt isn't enough.
+fn hidden_skill_rules(role: &Role) -> Vec<String> {
+    if role.skills.is_none() {
+        return Vec::new();
+    }
+    skills::discover_skills()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|skill| !role.allows_skill(&skill.name))
+        .filter_map(|skill| skill.location.parent().map(path_rule_pattern))
+        .flat_map(|pattern| ["Read", "Edit"].map(|tool| format!("{}({})", tool, pattern)))
+        .collect()
+}
+
+/// Permission rule pattern for everything under an absolute directory
+pub fn path_rule_pattern(dir: &Path) -> String {
+    // A leading `//` makes the path absolute rather than project-relative
+    format!("/{}/**", dir.display())
 }
 
 /// Query the configured AI backend, returning (response, session_id).
@@ -35,7 +117,10 @@ async fn query_claude(
         resume_session: options.resume_session,
         cwd: options.cwd,
         skip_permissions: options.skip_permissions,
-        model: config.claude.model.clone(),
+        model: options.model.or_else(|| config.claude.model.clone()),
+        disallowed_tools: options.disallowed_tools,
+        allowed_tools: options.allowed_tools,
+        progress: options.progress,
     };
 
     claude::query_with_options(prompt, claude_options).await
@@ -51,12 +136,134 @@ async fn query_cursor(
         resume_session: options.resume_session,
         cwd: options.cwd,
         force: options.skip_permissions,
-        model: config.cursor.model.clone(),
+        model: options.model.or_else(|| config.cursor.model.clone()),
+        progress: options.progress,
     };
 
+    // Running without the limits would quietly give the user more than their role allows.
+    // Allowed tools only grant more, so leaving them out is safe.
+    if !options.disallowed_tools.is_empty() {
+        return Err(anyhow!(
+            "Your role limits the tools or skills you can use, which the Cursor backend \
+             can't enforce. Ask the owner to switch to the Claude backend or change your role."
+        ));
+    }
+
     cursor::query_with_options(prompt, cursor_options).await
 }
 
+/// Output of a finished backend process
+pub(crate) struct ProcessOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Kills a backend's process group when dropped before the backend has
+/// finished, so the commands it started (builds, test runs, servers) are
+/// stopped along with it
+struct ProcessGroupGuard {
+    pgid: Option<u32>,
+}
+
+impl ProcessGroupGuard {
+    /// The backend exited by itself; leave whatever it started running
+    fn disarm(mut self) {
+        self.pgid = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid {
+            use std::process::Command as StdCommand;
+            let _ = StdCommand::new("kill")
+                .args(["-KILL", "--", &format!("-{}", pgid)])
+                .status();
+        }
+    }
+}
+
+/// Run a backend CLI, passing each line of its output to `on_line` as it
+/// arrives. The process and everything it started are killed if the returned
+/// future is dropped, e.g. when the user stops the task waiting for it.
+pub(crate) async fn run_streaming(
+    mut cmd: Command,
+    mut on_line: impl FnMut(&str),
+) -> Result<ProcessOutput> {
+    // Its own process group, so its children can be killed with it
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let group = ProcessGroupGuard { pgid: child.id() };
+
+    let stdout_pipe = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture backend output"))?;
+    let mut stderr_pipe = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture backend errors"))?;
+
+    let read_stdout = async {
+        let mut reader = BufReader::new(stdout_pipe);
+        let mut stdout = String::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&line);
+            on_line(line.trim_end());
+            stdout.push_str(&line);
+        }
+        Ok::<_, std::io::Error>(stdout)
+    };
+    let read_stderr = async {
+        let mut stderr = Vec::new();
+        stderr_pipe.read_to_end(&mut stderr).await?;
+        Ok::<_, std::io::Error>(String::from_utf8_lossy(&stderr).into_owned())
+    };
+
+    let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+    let status = child.wait().await?;
+    group.disarm();
+
+    Ok(ProcessOutput {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Shorten a tool argument for a progress update
+pub(crate) fn shorten(text: &str) -> String {
+    const MAX_CHARS: usize = 60;
+    let text = text.lines().next().unwrap_or("").trim();
+    if text.chars().count() <= MAX_CHARS {
+        return text.to_string();
+    }
+    let truncated: String = text.chars().take(MAX_CHARS - 1).collect();
+    format!("{}…", truncated)
+}
+
+/// Last component of a path, for progress updates
+pub(crate) fn file_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| shorten(path))
+}
+
 #[allow(dead_code)]
 pub fn current_backend_name() -> Result<&'static str> {
     let config = Config::load()?;
@@ -65,3 +272,43 @@ pub fn current_backend_name() -> Result<&'static str> {
         AiBackend::Cursor => "Cursor CLI",
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_streaming_kills_process_group() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 30 & echo $!; wait"]);
+
+        // Stop waiting as soon as the backend has started a child of its own
+        let (pid_tx, pid_rx) = tokio::sync::oneshot::channel();
+        let mut pid_tx = Some(pid_tx);
+        let run = run_streaming(cmd, |line| {
+            if let Some(tx) = pid_tx.take() {
+                let _ = tx.send(line.to_string());
+            }
+        });
+        let pid = tokio::select! {
+            _ = run => panic!("the backend finished early"),
+            pid = pid_rx => pid.unwrap(),
+        };
+
+        // The orphaned sleep was killed with the group (it may linger as a
+        // zombie until it's reaped)
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let state = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", &pid])
+            .output()
+            .unwrap();
+        let state = String::from_utf8_lossy(&state.stdout);
+        assert!(
+            state.trim().is_empty() || state.starts_with('Z'),
+            "process {} outlived its backend ({})",
+            pid,
+            state.trim()
+        );
+    }
+}
@@ -0,0 +1,699 @@
+//! IRC channel implementation
+//!
+//! Answers private messages, and messages in joined channels that highlight the
+//! bot's nickname. Outgoing text is split into IRC-sized lines and throttled to
+//! stay under typical server flood limits.
+//!
+//! Anyone can take a free nick, so users are identified by the services
+//! account the server reports with each message (IRCv3 `account-tag`), and
+//! users who haven't identified aren't answered.
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use base64::Engine;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, sleep};
+use tracing::{debug, error, info, warn};
+
+use super::registry::ChannelRegistry;
+use super::{
+    Channel, MessageAction, SenderInfo, TypingGuard, UserTaskManager, build_text_with_images,
+    determine_action, execute_action, execute_claude_query, format_group_message, markdown,
+};
+use crate::config::IrcConfig;
+use crate::pairing::{self, PairingStore};
+
+/// Maximum bytes of message text per PRIVMSG.
+/// IRC lines are limited to 512 bytes including the prefix the server prepends
+/// when relaying, so leave generous room for it.
+const MAX_LINE_BYTES: usize = 400;
+
+/// Flood control: each line adds this much to the penalty timer
+const FLOOD_PENALTY: Duration = Duration::from_secs(2);
+
+/// Flood control: lines are sent immediately while the timer is within this window
+const FLOOD_WINDOW: Duration = Duration::from_secs(10);
+
+/// Delay before reconnecting after the connection drops
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+// ============================================================================
+// Channel Implementation
+// ============================================================================
+
+/// Nick each account last used, keyed by user id (the lowercased account)
+type Nicks = Arc<Mutex<HashMap<String, String>>>;
+
+/// Where to message a pairing id: a group's channel, or the nick the user
+/// was last seen with, falling back to their account name
+fn nick_for(nicks: &Nicks, user_id: &str) -> String {
+    if let Some(channel) = pairing::group_id(user_id) {
+        return channel.to_string();
+    }
+    nicks
+        .lock()
+        .ok()
+        .and_then(|nicks| nicks.get(user_id).cloned())
+        .unwrap_or_else(|| user_id.to_string())
+}
+
+/// IRC channel implementation
+pub struct IrcChannel {
+    outgoing: mpsc::UnboundedSender<String>,
+    nicks: Nicks,
+    /// Nick (private message) or #channel (highlight) to reply to
+    target: String,
+    /// Nick to address when replying in a channel
+    addressee: Option<String>,
+}
+
+impl IrcChannel {
+    pub fn new(
+        outgoing: mpsc::UnboundedSender<String>,
+        nicks: Nicks,
+        target: String,
+        addressee: Option<String>,
+    ) -> Self {
+        Self {
+            outgoing,
+            nicks,
+            target,
+            addressee,
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for IrcChannel {
+    fn name(&self) -> &'static str {
+        "irc"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "IRC"
+    }
+
+    async fn send_message(&self, message: &str) -> Result<()> {
+        let prefix = self
+            .addressee
+            .as_ref()
+            .map(|nick| format!("{}: ", nick))
+            .unwrap_or_default();
+
+        let message = markdown::to_plain(message);
+        for line in split_message(&message, MAX_LINE_BYTES - prefix.len()) {
+            self.outgoing
+                .send(format!("PRIVMSG {} :{}{}", self.target, prefix, line))
+                .map_err(|_| anyhow!("IRC connection is closed"))?;
+        }
+
+        Ok(())
+    }
+
+    fn reply_address(&self) -> Option<String> {
+        is_channel_name(&self.target).then(|| self.target.clone())
+    }
+
+    fn for_user(&self, user_id: &str) -> Option<Arc<dyn Channel>> {
+        Some(Arc::new(IrcChannel::new(
+            self.outgoing.clone(),
+            self.nicks.clone(),
+            nick_for(&self.nicks, user_id),
+            None,
+        )))
+    }
+
+    fn start_typing(&self) -> TypingGuard {
+        // IRC has no widely supported typing indicator
+        TypingGuard::noop()
+    }
+}
+
+// ============================================================================
+// Protocol
+// ============================================================================
+
+/// A parsed IRC protocol line
+#[derive(Debug, PartialEq)]
+struct IrcMessage {
+    /// Source nickname (from the `:nick!user@host` prefix)
+    nick: Option<String>,
+    /// Services account the sender is logged in to (from the `account` tag)
+    account: Option<String>,
+    command: String,
+    params: Vec<String>,
+}
+
+/// Parse a raw IRC line (without CRLF)
+fn parse_line(line: &str) -> Option<IrcMessage> {
+    let mut rest = line;
+
+    // IRCv3 message tags; only the sender's account is used
+    let mut account = None;
+    if let Some(stripped) = rest.strip_prefix('@') {
+        let (tags, remainder) = stripped.split_once(' ')?;
+        rest = remainder;
+        account = tags
+            .split(';')
+            .find_map(|tag| tag.strip_prefix("account="))
+            .filter(|account| !account.is_empty() && *account != "*")
+            .map(str::to_string);
+    }
+
+    let nick = if let Some(stripped) = rest.strip_prefix(':') {
+        let (prefix, remainder) = stripped.split_once(' ')?;
+        rest = remainder;
+        Some(prefix.split('!').next().unwrap_or(prefix).to_string())
+    } else {
+        None
+    };
+
+    let (head, trailing) = match rest.split_once(" :") {
+        Some((head, trailing)) => (head, Some(trailing)),
+        None => (rest, None),
+    };
+
+    let mut parts = head.split_whitespace();
+    let command = parts.next()?.to_uppercase();
+    let mut params: Vec<String> = parts.map(|s| s.to_string()).collect();
+    if let Some(trailing) = trailing {
+        params.push(trailing.to_string());
+    }
+
+    Some(IrcMessage {
+        nick,
+        account,
+        command,
+        params,
+    })
+}
+
+/// If `text` addresses `nick` (e.g. "cica: hi", "cica, hi", "hey cica"),
+/// return the text with a leading address removed.
+fn strip_highlight(text: &str, nick: &str) -> Option<String> {
+    // Nicks compare case-insensitively, but only in ASCII
+    if let Some(prefix) = text.get(..nick.len())
+        && prefix.eq_ignore_ascii_case(nick)
+    {
+        let rest = &text[nick.len()..];
+        if rest.is_empty() || rest.starts_with([':', ',', ' ']) {
+            return Some(rest.trim_start_matches([':', ',']).trim().to_string());
+        }
+    }
+
+    let mentioned = text
+        .split(|c: char| !(c.is_alphanumeric() || "-_[]\\`^{}|".contains(c)))
+        .any(|word| word.eq_ignore_ascii_case(nick));
+
+    mentioned.then(|| text.trim().to_string())
+}
+
+/// Split a message into lines that fit in a single PRIVMSG.
+///
+/// Splits on newlines first, then on whitespace for long lines, never breaking
+/// inside a UTF-8 character. Blank lines are dropped since IRC cannot send them.
+fn split_message(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        let mut rest = line.trim_end();
+
+        while rest.len() > max_bytes {
+            let mut cut = max_bytes;
+            while !rest.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            if cut == 0 {
+                cut = rest
+                    .chars()
+                    .next()
+                    .map(char::len_utf8)
+                    .unwrap_or(rest.len());
+            }
+            // Prefer breaking at the last space before the limit
+            if rest[cut..].starts_with(' ') {
+                // Already at a word boundary
+            } else if let Some(space) = rest[..cut].rfind(' ')
+                && space > 0
+            {
+                cut = space;
+            }
+            lines.push(rest[..cut].trim_end().to_string());
+            rest = rest[cut..].trim_start();
+        }
+
+        if !rest.trim().is_empty() {
+            lines.push(rest.to_string());
+        }
+    }
+
+    lines
+}
+
+// ============================================================================
+// Connection
+// ============================================================================
+
+/// Run the IRC bot, reconnecting whenever the connection drops
+pub async fn run(config: IrcConfig, registry: Arc<ChannelRegistry>) -> Result<()> {
+    info!(
+        "Starting IRC bot as {} on {}:{}...",
+        config.nickname, config.server, config.port
+    );
+
+    // Shared across reconnects so pending batches survive a dropped connection
+    let task_manager = UserTaskManager::for_channel("irc");
+
+    loop {
+        let result = if config.tls {
+            match connect_tls(&config).await {
+                Ok(stream) => run_connection(stream, &config, &task_manager, &registry).await,
+                Err(e) => Err(e),
+            }
+        } else {
+            match TcpStream::connect((config.server.as_str(), config.port)).await {
+                Ok(stream) => run_connection(stream, &config, &task_manager, &registry).await,
+                Err(e) => Err(e.into()),
+            }
+        };
+
+        if let Err(e) = result {
+            error!("IRC connection error: {:#}", e);
+        }
+
+        info!("Reconnecting to IRC in {}s...", RECONNECT_DELAY.as_secs());
+        sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Open a TLS connection using the system's root certificates
+async fn connect_tls(config: &IrcConfig) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    // Ensure rustls crypto provider is installed
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs().certs {
+        let _ = roots.add(cert);
+    }
+
+    let tls_config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config));
+
+    let server_name = rustls::pki_types::ServerName::try_from(config.server.clone())
+        .context("Invalid IRC server name")?;
+    let tcp = TcpStream::connect((config.server.as_str(), config.port)).await?;
+
+    Ok(connector.connect(server_name, tcp).await?)
+}
+
+/// Register with the server and process messages until the connection closes
+async fn run_connection<S>(
+    stream: S,
+    config: &IrcConfig,
+    task_manager: &Arc<UserTaskManager>,
+    registry: &ChannelRegistry,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+    let (outgoing, outgoing_rx) = mpsc::unbounded_channel::<String>();
+    let writer_task = tokio::spawn(write_loop(writer, outgoing_rx));
+
+    // Proactive messages go to users as private messages, sent to the nick
+    // they last used (user ids are account names)
+    let nicks = Nicks::default();
+    let registry_outgoing = outgoing.clone();
+    let registry_nicks = nicks.clone();
+    registry.register("irc", move |user_id| {
+        Some(Arc::new(IrcChannel::new(
+            registry_outgoing.clone(),
+            registry_nicks.clone(),
+            nick_for(&registry_nicks, user_id),
+            None,
+        )) as Arc<dyn Channel>)
+    });
+
+    let use_sasl = config.sasl_password.is_some();
+    let mut nick = config.nickname.clone();
+
+    // Registration. account-tag is requested first so its reply arrives
+    // before SASL's, which ends capability negotiation when SASL is used.
+    outgoing.send("CAP REQ :account-tag".to_string())?;
+    if use_sasl {
+        outgoing.send("CAP REQ :sasl".to_string())?;
+    }
+    if let Some(password) = &config.password {
+        outgoing.send(format!("PASS {}", password))?;
+    }
+    outgoing.send(format!("NICK {}", nick))?;
+    outgoing.send(format!("USER {} 0 * :Cica", config.nickname))?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Some(msg) = parse_line(&line) else {
+            continue;
+        };
+
+        match msg.command.as_str() {
+            "PING" => {
+                let token = msg.params.first().cloned().unwrap_or_default();
+                outgoing.send(format!("PONG :{}", token))?;
+            }
+            "CAP" => {
+                let subcommand = msg.params.get(1).map(|s| s.as_str()).unwrap_or("");
+                let caps = msg.params.last().map(|s| s.as_str()).unwrap_or("");
+                let is_sasl = caps.split_whitespace().any(|c| c == "sasl");
+                match subcommand {
+                    "ACK" if is_sasl => {
+                        outgoing.send("AUTHENTICATE PLAIN".to_string())?;
+                    }
+                    "NAK" if is_sasl => {
+                        warn!("IRC server does not support SASL");
+                        outgoing.send("CAP END".to_string())?;
+                    }
+                    "NAK" => {
+                        warn!(
+                            "IRC server does not support account-tag, so no one can be identified"
+                        );
+                        if !use_sasl {
+                            outgoing.send("CAP END".to_string())?;
+                        }
+                    }
+                    "ACK" if !use_sasl => {
+                        outgoing.send("CAP END".to_string())?;
+                    }
+                    _ => {}
+                }
+            }
+            "AUTHENTICATE" if msg.params.first().map(|s| s.as_str()) == Some("+") => {
+                let account = config.sasl_username.as_deref().unwrap_or(&config.nickname);
+                let password = config.sasl_password.as_deref().unwrap_or_default();
+                let payload = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}\0{}\0{}", account, account, password));
+                outgoing.send(format!("AUTHENTICATE {}", payload))?;
+            }
+            "903" => {
+                info!("IRC SASL authentication succeeded");
+                outgoing.send("CAP END".to_string())?;
+            }
+            "902" | "904" | "905" | "906" => {
+                outgoing.send("CAP END".to_string())?;
+                bail!("IRC SASL authentication failed: {}", msg.params.join(" "));
+            }
+            "433" => {
+                // Nickname in use
+                nick.push('_');
+                warn!("IRC nickname in use, trying {}", nick);
+                outgoing.send(format!("NICK {}", nick))?;
+            }
+            "001" => {
+                if let Some(assigned) = msg.params.first() {
+                    nick = assigned.clone();
+                }
+                info!("Connected to IRC as {}", nick);
+                for channel in &config.channels {
+                    outgoing.send(format!("JOIN {}", channel))?;
+                }
+            }
+            "NICK" if msg.nick.as_deref() == Some(nick.as_str()) => {
+                if let Some(new_nick) = msg.params.first() {
+                    nick = new_nick.clone();
+                }
+            }
+            "ERROR" => {
+                bail!("IRC server closed the connection: {}", msg.params.join(" "));
+            }
+            "PRIVMSG" => {
+                if let Err(e) = handle_privmsg(msg, &nick, &outgoing, &nicks, task_manager).await {
+                    warn!("Error handling IRC message: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    writer_task.abort();
+    bail!("IRC connection closed")
+}
+
+/// Write queued lines to the server with RFC 1459 style flood control
+async fn write_loop<W>(mut writer: W, mut outgoing: mpsc::UnboundedReceiver<String>)
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut timer = Instant::now();
+
+    while let Some(line) = outgoing.recv().await {
+        let now = Instant::now();
+        if timer < now {
+            timer = now;
+        }
+        if timer > now + FLOOD_WINDOW {
+            sleep(timer - now - FLOOD_WINDOW).await;
+        }
+        timer += FLOOD_PENALTY;
+
+        // Never let a message smuggle in extra protocol lines
+        let line = line.replace(['\r', '\n'], " ");
+        debug!("IRC >> {}", line);
+        if let Err(e) = writer.write_all(format!("{}\r\n", line).as_bytes()).await {
+            warn!("Failed to write to IRC server: {}", e);
+            break;
+        }
+        let _ = writer.flush().await;
+    }
+}
+
+// ============================================================================
+// Message Handling
+// ============================================================================
+
+/// Whether a message target is a channel rather than a nick
+fn is_channel_name(target: &str) -> bool {
+    target.starts_with(['#', '&', '+', '!'])
+}
+
+/// Handle a PRIVMSG addressed to us directly or highlighting us in a channel
+async fn handle_privmsg(
+    msg: IrcMessage,
+    own_nick: &str,
+    outgoing: &mpsc::UnboundedSender<String>,
+    nicks: &Nicks,
+    task_manager: &Arc<UserTaskManager>,
+) -> Result<()> {
+    let Some(sender) = msg.nick else {
+        return Ok(());
+    };
+    let (Some(target), Some(text)) = (msg.params.first(), msg.params.get(1)) else {
+        return Ok(());
+    };
+
+    // Ignore CTCP (ACTION, VERSION, ...)
+    if text.starts_with('\x01') {
+        return Ok(());
+    }
+
+    let is_channel = is_channel_name(target);
+    let (text, channel) = if is_channel {
+        let Some(text) = strip_highlight(text, own_nick) else {
+            return Ok(());
+        };
+        let channel = IrcChannel::new(
+            outgoing.clone(),
+            nicks.clone(),
+            target.clone(),
+            Some(sender.clone()),
+        );
+        (text, channel)
+    } else {
+        let channel = IrcChannel::new(outgoing.clone(), nicks.clone(), sender.clone(), None);
+        (text.clone(), channel)
+    };
+
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    // Nicks can be taken by anyone, so only identified users are answered
+    let Some(account) = msg.account else {
+        debug!("Ignoring {}, who isn't identified with services", sender);
+        outgoing.send(format!(
+            "NOTICE {} :Please identify with services (e.g. NickServ) before talking to me.",
+            sender
+        ))?;
+        return Ok(());
+    };
+
+    // Account names are case-insensitive on IRC
+    let user_id = account.to_ascii_lowercase();
+    if let Ok(mut nicks) = nicks.lock() {
+        nicks.insert(user_id.clone(), sender.clone());
+    }
+
+    info!(
+        "Message from {} ({}) (via {}): {}",
+        sender, user_id, target, text
+    );
+
+    let channel: Arc<dyn Channel> = Arc::new(channel);
+
+    // A channel is a group chat: approved as a whole and sharing one
+    // conversation, so nothing personal to one member is answered there
+    let member = SenderInfo::new(Some(account), Some(sender.clone()));
+    let (pairing_id, sender_info) = if is_channel {
+        (
+            channel_pairing_id(target),
+            SenderInfo::new(None, Some(target.clone())).with_member(user_id.clone(), member),
+        )
+    } else {
+        (user_id.clone(), member)
+    };
+
+    // Determine what action to take
+    let mut store = PairingStore::load()?;
+    let action = determine_action(
+        channel.name(),
+        &pairing_id,
+        &text,
+        &[],
+        &mut store,
+        sender_info,
+    )?;
+
+    // Pairing codes are only ever sent privately
+    let channel: Arc<dyn Channel> =
+        if is_channel && matches!(action, MessageAction::NeedsPairing { .. }) {
+            Arc::new(IrcChannel::new(
+                outgoing.clone(),
+                nicks.clone(),
+                sender.clone(),
+                None,
+            ))
+        } else {
+            channel
+        };
+
+    // Execute the action
+    if let Some(query_text) =
+        execute_action(channel.as_ref(), &pairing_id, action, task_manager).await?
+    {
+        // QueryClaude action - queue with task manager for debouncing
+        let query_text = if is_channel {
+            format_group_message(&sender, &query_text)
+        } else {
+            query_text
+        };
+        let text = build_text_with_images(&query_text, &[], &[]);
+        let user_key = format!("{}:{}", channel.name(), pairing_id);
+        let channel_clone = channel.clone();
+
+        task_manager
+            .process_message(user_key, text, move |messages, steering| async move {
+                execute_claude_query(channel_clone, &pairing_id, &pairing_id, messages, steering)
+                    .await;
+            })
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Pairing id of an IRC channel; channel names are case-insensitive
+fn channel_pairing_id(channel: &str) -> String {
+    pairing::group_user_id(&channel.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::{CommandResult, process_command_as};
+    use crate::config::Role;
+
+    #[test]
+    fn test_parse_line() {
+        let msg = parse_line(":alice!a@host PRIVMSG #ops :cica: status?").unwrap();
+        assert_eq!(msg.nick.as_deref(), Some("alice"));
+        assert_eq!(msg.command, "PRIVMSG");
+        assert_eq!(msg.params, vec!["#ops", "cica: status?"]);
+
+        let ping = parse_line("PING :irc.example.org").unwrap();
+        assert_eq!(ping.nick, None);
+        assert_eq!(ping.params, vec!["irc.example.org"]);
+
+        let tagged = parse_line("@time=2024-01-01T00:00:00Z :srv 001 cica :Welcome").unwrap();
+        assert_eq!(tagged.command, "001");
+        assert_eq!(tagged.params, vec!["cica", "Welcome"]);
+        assert_eq!(tagged.account, None);
+
+        let identified =
+            parse_line("@account=Alice;time=2024-01-01T00:00:00Z :al!a@host PRIVMSG cica :hi")
+                .unwrap();
+        assert_eq!(identified.nick.as_deref(), Some("al"));
+        assert_eq!(identified.account.as_deref(), Some("Alice"));
+
+        let anonymous = parse_line("@account=* :al!a@host PRIVMSG cica :hi").unwrap();
+        assert_eq!(anonymous.account, None);
+    }
+
+    #[test]
+    fn test_strip_highlight() {
+        assert_eq!(strip_highlight("cica: hi", "cica").as_deref(), Some("hi"));
+        assert_eq!(strip_highlight("Cica, hi", "cica").as_deref(), Some("hi"));
+        assert_eq!(
+            strip_highlight("ask cica later", "cica").as_deref(),
+            Some("ask cica later")
+        );
+        assert_eq!(strip_highlight("cicada season", "cica"), None);
+        assert_eq!(strip_highlight("hello all", "cica"), None);
+
+        // The Kelvin sign lowercases to a one-byte "k"
+        assert_eq!(strip_highlight("\u{212A}c: hi", "kc"), None);
+        assert_eq!(strip_highlight("é cica", "cica").as_deref(), Some("é cica"));
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("one\n\ntwo", 400), vec!["one", "two"]);
+
+        let lines = split_message("aaaa bbbb cccc", 9);
+        assert_eq!(lines, vec!["aaaa bbbb", "cccc"]);
+
+        // Never split inside a multi-byte character
+        let lines = split_message("ééééé", 3);
+        assert!(lines.iter().all(|l| l.len() <= 3));
+        assert_eq!(lines.concat(), "ééééé");
+    }
+
+    #[test]
+    fn test_channel_highlight_is_group() {
+        let pairing_id = channel_pairing_id("#Ops");
+        assert_eq!(pairing_id, "group:#ops");
+        let nicks: Nicks = Arc::default();
+        assert_eq!(nick_for(&nicks, &pairing_id), "#ops");
+
+        // Personal commands never answer in the channel
+        let mut store = PairingStore::default();
+        let result = process_command_as(
+            &mut store,
+            &Role::owner(),
+            "irc",
+            &pairing_id,
+            "/token",
+            true,
+        )
+        .unwrap();
+        let CommandResult::Response(response) = result else {
+            panic!("/token wasn't answered");
+        };
+        assert!(response.contains("direct message"));
+        assert!(store.api_tokens.is_empty());
+    }
+}
@@ -0,0 +1,693 @@
+//! Markdown rendering for messaging channels.
+//!
+//! The assistant always writes Markdown. Each channel renders it into what its
+//! transport understands: HTML for Telegram, mrkdwn for Slack, and plain text
+//! with style ranges for Signal.
+
+// ============================================================================
+// Syntax Tree
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Strike(Vec<Inline>),
+    Code(String),
+    Link { text: Vec<Inline>, url: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Paragraph(Vec<Inline>),
+    Heading(Vec<Inline>),
+    Code { lang: Option<String>, code: String },
+    List(Vec<ListItem>),
+    Quote(Vec<Vec<Inline>>),
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ListItem {
+    depth: usize,
+    marker: String,
+    content: Vec<Inline>,
+}
+
+// ============================================================================
+// Block Parsing
+// ============================================================================
+
+fn parse_blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut lines = text.lines().peekable();
+
+    fn flush(paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>) {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(parse_inlines(&paragraph.join("\n"))));
+            paragraph.clear();
+        }
+    }
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        if let Some(info) = trimmed.strip_prefix("```") {
+            flush(&mut paragraph, &mut blocks);
+            let lang = info.split_whitespace().next().map(|l| l.to_string());
+            let mut code = Vec::new();
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+                code.push(line);
+            }
+            blocks.push(Block::Code {
+                lang,
+                code: code.join("\n"),
+            });
+        } else if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+        } else if let Some(heading) = heading_text(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Heading(parse_inlines(heading)));
+        } else if is_rule(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Rule);
+        } else if let Some(quoted) = trimmed.strip_prefix('>') {
+            flush(&mut paragraph, &mut blocks);
+            let mut quote = vec![parse_inlines(quoted.trim_start())];
+            while let Some(next) = lines.peek().and_then(|l| l.trim_start().strip_prefix('>')) {
+                quote.push(parse_inlines(next.trim_start()));
+                lines.next();
+            }
+            blocks.push(Block::Quote(quote));
+        } else if let Some(item) = list_item(line) {
+            flush(&mut paragraph, &mut blocks);
+            match blocks.last_mut() {
+                Some(Block::List(items)) => items.push(item),
+                _ => blocks.push(Block::List(vec![item])),
+            }
+        } else {
+            paragraph.push(line.trim_end());
+        }
+    }
+
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+/// Text of an ATX heading (`## Title`)
+fn heading_text(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if rest.is_empty() || rest.starts_with(' ') {
+        Some(rest.trim().trim_end_matches('#').trim_end())
+    } else {
+        None
+    }
+}
+
+/// Thematic break: three or more `-`, `*` or `_`
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && matches!(chars[0], '-' | '*' | '_') && chars.iter().all(|c| *c == chars[0])
+}
+
+/// Bullet (`- `, `* `, `+ `) or numbered (`1. `, `1) `) list item
+fn list_item(line: &str) -> Option<ListItem> {
+    let indent: usize = line
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    let rest = line.trim_start();
+
+    let (marker, content) = if let Some(content) = rest
+        .strip_prefix("- ")
+        .or_else(|| rest.strip_prefix("* "))
+        .or_else(|| rest.strip_prefix("+ "))
+    {
+        ("•".to_string(), content)
+    } else {
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 || digits > 9 {
+            return None;
+        }
+        let after = &rest[digits..];
+        let content = after
+            .strip_prefix(". ")
+            .or_else(|| after.strip_prefix(") "))?;
+        (format!("{}.", &rest[..digits]), content)
+    };
+
+    Some(ListItem {
+        depth: indent / 2,
+        marker,
+        content: parse_inlines(content.trim()),
+    })
+}
+
+// ============================================================================
+// Inline Parsing
+// ============================================================================
+
+fn parse_inlines(text: &str) -> Vec<Inline> {
+    let mut out = Vec::new();
+    let mut buf = String::new();
+    let mut i = 0;
+
+    fn flush(buf: &mut String, out: &mut Vec<Inline>) {
+        if !buf.is_empty() {
+            out.push(Inline::Text(std::mem::take(buf)));
+        }
+    }
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap_or_default();
+
+        let parsed = match c {
+            '\\' => {
+                let escaped = rest[1..]
+                    .chars()
+                    .next()
+                    .filter(|c| c.is_ascii_punctuation());
+                if let Some(escaped) = escaped {
+                    buf.push(escaped);
+                    i += 1 + escaped.len_utf8();
+                    continue;
+                }
+                None
+            }
+            '`' => parse_code_span(rest),
+            '[' => parse_link(rest),
+            '<' => parse_autolink(rest),
+            '*' | '_' | '~' => parse_emphasis(text, i),
+            _ => None,
+        };
+
+        match parsed {
+            Some((inline, len)) => {
+                flush(&mut buf, &mut out);
+                out.push(inline);
+                i += len;
+            }
+            None => {
+                buf.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    flush(&mut buf, &mut out);
+    out
+}
+
+/// `` `code` `` with any number of backticks
+fn parse_code_span(rest: &str) -> Option<(Inline, usize)> {
+    let ticks = rest.chars().take_while(|c| *c == '`').count();
+    let fence = &rest[..ticks];
+    let end = rest[ticks..].find(fence)?;
+    let code = &rest[ticks..ticks + end];
+    if code.is_empty() {
+        return None;
+    }
+    let code = if code.len() > 2 && code.starts_with(' ') && code.ends_with(' ') {
+        &code[1..code.len() - 1]
+    } else {
+        code
+    };
+    Some((Inline::Code(code.to_string()), ticks + end + ticks))
+}
+
+/// `[text](url)`
+fn parse_link(rest: &str) -> Option<(Inline, usize)> {
+    let mut depth = 0;
+    let mut label_end = None;
+    for (j, c) in rest.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    label_end = Some(j);
+                    break;
+                }
+            }
+            '\n' => return None,
+            _ => {}
+        }
+    }
+    let label_end = label_end?;
+    let after = &rest[label_end + 1..];
+    if !after.starts_with('(') {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (j, c) in after.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    let url = after[1..j].split_whitespace().next()?.to_string();
+                    let text = parse_inlines(&rest[1..label_end]);
+                    return Some((Inline::Link { text, url }, label_end + 1 + j + 1));
+                }
+            }
+            '\n' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `<https://example.com>`
+fn parse_autolink(rest: &str) -> Option<(Inline, usize)> {
+    let end = rest.find('>')?;
+    let url = &rest[1..end];
+    if !(url.starts_with("http://") || url.starts_with("https://"))
+        || url.contains(char::is_whitespace)
+    {
+        return None;
+    }
+    let link = Inline::Link {
+        text: vec![Inline::Text(url.to_string())],
+        url: url.to_string(),
+    };
+    Some((link, end + 1))
+}
+
+/// `**bold**`, `__bold__`, `*italic*`, `_italic_` and `~~strike~~`
+fn parse_emphasis(text: &str, i: usize) -> Option<(Inline, usize)> {
+    let rest = &text[i..];
+    let c = rest.chars().next()?;
+    let run = rest.chars().take_while(|ch| *ch == c).count();
+
+    let delim_len = match (c, run) {
+        ('~', n) if n >= 2 => 2,
+        ('~', _) => return None,
+        (_, n) if n >= 2 => 2,
+        _ => 1,
+    };
+    let delim = &rest[..delim_len];
+    let after = &rest[delim_len..];
+
+    // Opening delimiter must be followed by text, and `_` must not be inside a word
+    if after.chars().next().is_none_or(char::is_whitespace) {
+        return None;
+    }
+    if c == '_' && text[..i].chars().last().is_some_and(char::is_alphanumeric) {
+        return None;
+    }
+
+    let mut search = 0;
+    while let Some(found) = after[search..].find(delim) {
+        let mut j = search + found;
+
+        // Single delimiters skip over runs (`*a **b** c*`); doubled ones close
+        // at the end of a run (`***x***`)
+        let run_len = after[j..].chars().take_while(|ch| *ch == c).count();
+        if delim_len == 1 && run_len > 1 {
+            search = j + run_len;
+            continue;
+        }
+        if delim_len == 2 && run_len > 2 {
+            j += run_len - 2;
+        }
+
+        let inner = &after[..j];
+        let before_close = inner.chars().last();
+        let after_close = after[j + delim_len..].chars().next();
+        let intraword = c == '_' && after_close.is_some_and(char::is_alphanumeric);
+        let valid = !inner.is_empty() && !before_close.is_some_and(char::is_whitespace);
+
+        if valid && !intraword {
+            let children = parse_inlines(inner);
+            let inline = match (c, delim_len) {
+                ('~', _) => Inline::Strike(children),
+                (_, 2) => Inline::Bold(children),
+                _ => Inline::Italic(children),
+            };
+            return Some((inline, delim_len + j + delim_len));
+        }
+        search = j + delim_len;
+    }
+
+    None
+}
+
+// ============================================================================
+// Rendering
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    TelegramHtml,
+    SlackMrkdwn,
+    Styled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Bold,
+    Italic,
+    Strike,
+    Monospace,
+}
+
+impl Style {
+    fn signal_name(self) -> &'static str {
+        match self {
+            Style::Bold => "BOLD",
+            Style::Italic => "ITALIC",
+            Style::Strike => "STRIKETHROUGH",
+            Style::Monospace => "MONOSPACE",
+        }
+    }
+
+    fn html_tag(self) -> &'static str {
+        match self {
+            Style::Bold => "b",
+            Style::Italic => "i",
+            Style::Strike => "s",
+            Style::Monospace => "code",
+        }
+    }
+
+    fn slack_marker(self) -> &'static str {
+        match self {
+            Style::Bold => "*",
+            Style::Italic => "_",
+            Style::Strike => "~",
+            Style::Monospace => "`",
+        }
+    }
+}
+
+/// Text with style ranges, as Signal expects them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyledText {
+    pub text: String,
+    /// Ranges as `start:length:STYLE`, in UTF-16 code units
+    pub styles: Vec<String>,
+}
+
+struct Renderer {
+    format: Format,
+    out: String,
+    /// Length of `out` in UTF-16 code units (for Signal style ranges)
+    utf16_len: usize,
+    styles: Vec<String>,
+}
+
+impl Renderer {
+    fn new(format: Format) -> Self {
+        Self {
+            format,
+            out: String::new(),
+            utf16_len: 0,
+            styles: Vec::new(),
+        }
+    }
+
+    /// Append markup that should not be escaped
+    fn raw(&mut self, s: &str) {
+        self.utf16_len += s.encode_utf16().count();
+        self.out.push_str(s);
+    }
+
+    /// Append text, escaping it for the target format
+    fn text(&mut self, s: &str) {
+        match self.format {
+            Format::TelegramHtml | Format::SlackMrkdwn => {
+                let escaped = s
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                self.raw(&escaped);
+            }
+            Format::Styled => self.raw(s),
+        }
+    }
+
+    fn styled(&mut self, style: Style, body: impl FnOnce(&mut Self)) {
+        let start = self.utf16_len;
+        match self.format {
+            Format::TelegramHtml => self.raw(&format!("<{}>", style.html_tag())),
+            Format::SlackMrkdwn => self.raw(style.slack_marker()),
+            Format::Styled => {}
+        }
+        body(self);
+        match self.format {
+            Format::TelegramHtml => self.raw(&format!("</{}>", style.html_tag())),
+            Format::SlackMrkdwn => self.raw(style.slack_marker()),
+            Format::Styled => {
+                let len = self.utf16_len - start;
+                if len > 0 {
+                    self.styles
+                        .push(format!("{}:{}:{}", start, len, style.signal_name()));
+                }
+            }
+        }
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => self.text(text),
+                Inline::Bold(children) => self.styled(Style::Bold, |r| r.inlines(children)),
+                Inline::Italic(children) => self.styled(Style::Italic, |r| r.inlines(children)),
+                Inline::Strike(children) => self.styled(Style::Strike, |r| r.inlines(children)),
+                Inline::Code(code) => self.styled(Style::Monospace, |r| r.text(code)),
+                Inline::Link { text, url } => self.link(text, url),
+            }
+        }
+    }
+
+    fn link(&mut self, text: &[Inline], url: &str) {
+        let label = plain_text(text);
+        match self.format {
+            Format::TelegramHtml => {
+                let href = url
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+                    .replace('"', "&quot;");
+                self.raw(&format!("<a href=\"{}\">", href));
+                self.inlines(text);
+                self.raw("</a>");
+            }
+            Format::SlackMrkdwn => {
+                // `|` and `>` would end the link early
+                let url_escaped = url
+                    .replace('&', "&amp;")
+                    .replace('<', "%3C")
+                    .replace('>', "%3E")
+                    .replace('|', "%7C");
+                if label == url {
+                    self.raw(&format!("<{}>", url_escaped));
+                } else {
+                    let label = label.replace('|', "¦");
+                    self.raw(&format!("<{}|", url_escaped));
+                    self.text(&label);
+                    self.raw(">");
+                }
+            }
+            Format::Styled => {
+                self.inlines(text);
+                if label != url {
+                    self.raw(&format!(" ({})", url));
+                }
+            }
+        }
+    }
+
+    fn code_block(&mut self, lang: Option<&str>, code: &str) {
+        match self.format {
+            Format::TelegramHtml => {
+                match lang {
+                    Some(lang) => self.raw(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        lang.replace('"', "")
+                    )),
+                    None => self.raw("<pre>"),
+                }
+                self.text(code);
+                self.raw(if lang.is_some() {
+                    "</code></pre>"
+                } else {
+                    "</pre>"
+                });
+            }
+            Format::SlackMrkdwn => {
+                self.raw("```\n");
+                self.text(code);
+                self.raw("\n```");
+            }
+            Format::Styled => self.styled(Style::Monospace, |r| r.text(code)),
+        }
+    }
+
+    fn blocks(&mut self, blocks: &[Block]) {
+        for (i, block) in blocks.iter().enumerate() {
+            if i > 0 {
+                self.raw("\n\n");
+            }
+            match block {
+                Block::Paragraph(inlines) => self.inlines(inlines),
+                Block::Heading(inlines) => self.styled(Style::Bold, |r| r.inlines(inlines)),
+                Block::Code { lang, code } => self.code_block(lang.as_deref(), code),
+                Block::List(items) => {
+                    for (j, item) in items.iter().enumerate() {
+                        if j > 0 {
+                            self.raw("\n");
+                        }
+                        self.raw(&format!("{}{} ", "  ".repeat(item.depth), item.marker));
+                        self.inlines(&item.content);
+                    }
+                }
+                Block::Quote(lines) => {
+                    if self.format == Format::TelegramHtml {
+                        self.raw("<blockquote>");
+                    }
+                    for (j, line) in lines.iter().enumerate() {
+                        if j > 0 {
+                            self.raw("\n");
+                        }
+                        if self.format != Format::TelegramHtml {
+                            self.raw("> ");
+                        }
+                        self.inlines(line);
+                    }
+                    if self.format == Format::TelegramHtml {
+                        self.raw("</blockquote>");
+                    }
+                }
+                Block::Rule => self.raw("──────────"),
+            }
+        }
+    }
+}
+
+/// Text content of inlines without any formatting
+fn plain_text(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) | Inline::Code(text) => text.clone(),
+            Inline::Bold(children) | Inline::Italic(children) | Inline::Strike(children) => {
+                plain_text(children)
+            }
+            Inline::Link { text, .. } => plain_text(text),
+        })
+        .collect()
+}
+
+fn render(markdown: &str, format: Format) -> Renderer {
+    let mut renderer = Renderer::new(format);
+    renderer.blocks(&parse_blocks(markdown));
+    renderer
+}
+
+// ============================================================================
+// Public API
+// ============================================================================
+
+/// Render Markdown as Telegram HTML (for `parse_mode = HTML`)
+pub fn to_telegram_html(markdown: &str) -> String {
+    render(markdown, Format::TelegramHtml).out
+}
+
+/// Render Markdown as Slack mrkdwn
+pub fn to_slack_mrkdwn(markdown: &str) -> String {
+    render(markdown, Format::SlackMrkdwn).out
+}
+
+/// Render Markdown as plain text with Signal style ranges
+pub fn to_signal(markdown: &str) -> StyledText {
+    let renderer = render(markdown, Format::Styled);
+    StyledText {
+        text: renderer.out,
+        styles: renderer.styles,
+    }
+}
+
+/// Render Markdown as plain text, for channels without formatting
+pub fn to_plain(markdown: &str) -> String {
+    render(markdown, Format::Styled).out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_telegram_html() {
+        assert_eq!(
+            to_telegram_html("**Hi** _there_, see [docs](https://x.io?a=1&b=2) & `a<b`"),
+            "<b>Hi</b> <i>there</i>, see <a href=\"https://x.io?a=1&amp;b=2\">docs</a> &amp; <code>a&lt;b</code>"
+        );
+        assert_eq!(
+            to_telegram_html("# Title\n\n```rust\nfn main() {}\n```"),
+            "<b>Title</b>\n\n<pre><code class=\"language-rust\">fn main() {}</code></pre>"
+        );
+        assert_eq!(
+            to_telegram_html("> quoted\n> text"),
+            "<blockquote>quoted\ntext</blockquote>"
+        );
+        assert_eq!(
+            to_telegram_html("[x](https://x.io/?q=\"<b>\")"),
+            "<a href=\"https://x.io/?q=&quot;&lt;b&gt;&quot;\">x</a>"
+        );
+    }
+
+    #[test]
+    fn test_slack_mrkdwn() {
+        assert_eq!(
+            to_slack_mrkdwn("**bold** and *italic* and ~~gone~~"),
+            "*bold* and _italic_ and ~gone~"
+        );
+        assert_eq!(
+            to_slack_mrkdwn("[Cica](https://github.com/oxideai/cica) <https://x.io>"),
+            "<https://github.com/oxideai/cica|Cica> <https://x.io>"
+        );
+        assert_eq!(
+            to_slack_mrkdwn("[a|b](https://x.io/?q=a|b&c=d>e)"),
+            "<https://x.io/?q=a%7Cb&amp;c=d%3Ee|a¦b>"
+        );
+        assert_eq!(
+            to_slack_mrkdwn("- one\n- two\n  1. nested"),
+            "• one\n• two\n  1. nested"
+        );
+    }
+
+    #[test]
+    fn test_signal_styles() {
+        let styled = to_signal("Say **hello** to `cica` 👋 *now*");
+        assert_eq!(styled.text, "Say hello to cica 👋 now");
+        assert_eq!(
+            styled.styles,
+            vec!["4:5:BOLD", "13:4:MONOSPACE", "21:3:ITALIC"]
+        );
+    }
+
+    #[test]
+    fn test_emphasis_edge_cases() {
+        // Identifiers and arithmetic are left alone
+        assert_eq!(
+            to_plain("snake_case_name and 2 * 3 * 4"),
+            "snake_case_name and 2 * 3 * 4"
+        );
+        assert_eq!(to_slack_mrkdwn("***both***"), "*_both_*");
+        assert_eq!(to_slack_mrkdwn("*a **b** c*"), "_a *b* c_");
+        assert_eq!(to_plain("not \\*emphasis\\*"), "not *emphasis*");
+    }
+}
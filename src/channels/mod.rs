@@ -1,25 +1,38 @@
+pub mod irc;
+pub mod markdown;
+pub mod outbox;
+pub mod plugin;
+pub mod registry;
 pub mod signal;
 pub mod slack;
 pub mod telegram;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
-use tokio::sync::{Mutex, oneshot};
+use tokio::sync::{Mutex, Notify, mpsc, oneshot};
 use tokio::task::JoinHandle;
-use tracing::{debug, warn};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
 
-use crate::backends::{self, QueryOptions};
+use crate::backends::{self, Activity, ProgressSender, QueryOptions};
+use crate::config::{InterruptionPolicy, Role};
 use crate::cron::{
     self, CronSchedule, CronStore, format_timestamp, parse_add_command, truncate_for_name,
 };
+use crate::feedback;
 use crate::memory::MemoryIndex;
 use crate::onboarding;
-use crate::pairing::PairingStore;
+use crate::pairing::{self, PairingStore};
 use crate::skills;
+use crate::transcribe;
 
 // ============================================================================
 // Channel Abstraction
@@ -51,6 +64,80 @@ pub trait Channel: Send + Sync + 'static {
         self.send_message(message).await
     }
 
+    /// Send a message with rows of buttons underneath
+    async fn send_message_with_buttons(
+        &self,
+        message: &str,
+        _buttons: &[Vec<Button>],
+    ) -> Result<()> {
+        // Default implementation: just send the text message (ignore buttons)
+        // Channels with interactive messages should override this
+        self.send_message(message).await
+    }
+
+    /// Longest text message the transport accepts, in UTF-16 code units
+    /// (what Telegram counts, and never fewer than the characters).
+    /// `None` means there is no practical limit or the channel splits itself.
+    fn max_message_length(&self) -> Option<usize> {
+        None
+    }
+
+    /// Send a reply of any length, with optional buttons under its last part.
+    ///
+    /// Replies over `max_message_length` are split on paragraph and code-fence
+    /// boundaries; very long ones are sent as a Markdown document instead.
+    async fn send_reply(&self, message: &str, buttons: &[Vec<Button>]) -> Result<()> {
+        let Some(chunks) = reply_parts(self.max_message_length(), message) else {
+            let path = write_reply_document(message)?;
+            let sent = self
+                .send_message_with_attachments(LONG_REPLY_CAPTION, std::slice::from_ref(&path))
+                .await;
+            let _ = std::fs::remove_file(&path);
+            sent?;
+            if !buttons.is_empty() {
+                self.send_message_with_buttons(BUTTONS_PROMPT, buttons)
+                    .await?;
+            }
+            return Ok(());
+        };
+
+        let last = chunks.len().saturating_sub(1);
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i == last && !buttons.is_empty() {
+                self.send_message_with_buttons(chunk, buttons).await?;
+            } else {
+                self.send_message(chunk).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Where this conversation is, if it isn't the user's direct messages
+    /// (e.g. a thread). The channel registry accepts it in place of a user id,
+    /// so a reply that failed can be retried in the same place.
+    fn reply_address(&self) -> Option<String> {
+        None
+    }
+
+    /// Get a channel to another user on the same transport (e.g. to notify an
+    /// admin). Returns `None` if this channel can't start conversations.
+    fn for_user(&self, _user_id: &str) -> Option<Arc<dyn Channel>> {
+        None
+    }
+
+    /// Ids of the messages sent through this channel since the last call, so
+    /// reactions to a reply can be traced back to it. Channels that can't
+    /// receive reactions return nothing.
+    fn take_sent_message_ids(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// In a group chat, the member whose message is being answered. Buttons
+    /// that act for them only accept their taps.
+    fn member(&self) -> Option<String> {
+        None
+    }
+
     /// Start a typing indicator. Returns a guard that stops the indicator when dropped.
     fn start_typing(&self) -> TypingGuard;
 }
@@ -97,6 +184,12 @@ pub enum MessageAction {
     /// Send a simple response (command output, error message, etc.)
     SendResponse(String),
 
+    /// Send a response with buttons underneath
+    SendButtons {
+        text: String,
+        buttons: Vec<Vec<Button>>,
+    },
+
     /// Execute a cron job immediately
     ExecuteCronJob { job_id: String },
 
@@ -106,13 +199,63 @@ pub enum MessageAction {
     /// Query Claude with the user's message
     QueryClaude { text: String },
 
-    /// User not approved - send pairing instructions
-    NeedsPairing { code: String },
+    /// Cancel whatever the user has running or waiting
+    StopTask,
+
+    /// User not approved - send pairing instructions (and tell admins if new)
+    NeedsPairing { code: String, is_new: bool },
 
     /// No action needed (empty message, /start after onboarding, etc.)
     Ignore,
 }
 
+/// What a channel knows about who sent a message
+#[derive(Debug, Clone, Default)]
+pub struct SenderInfo {
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    /// Other ids allow and block lists can match, like a Signal UUID, a Slack
+    /// team as `team:T0123` or an email address
+    pub aliases: Vec<String>,
+    /// In a group, the user id of the person who sent the message and what's
+    /// known about them. Blocking them silences them in groups too.
+    pub member: Option<(String, Box<SenderInfo>)>,
+}
+
+impl SenderInfo {
+    pub fn new(username: Option<String>, display_name: Option<String>) -> Self {
+        Self {
+            username,
+            display_name,
+            aliases: Vec::new(),
+            member: None,
+        }
+    }
+
+    /// Note who in a group sent the message
+    pub fn with_member(mut self, user_id: String, member: SenderInfo) -> Self {
+        self.member = Some((user_id, Box::new(member)));
+        self
+    }
+
+    /// Ids allow lists are matched against. Usernames (and IRC nicks) can be
+    /// changed and then taken by someone else, so they never let anyone in.
+    fn stable_identifiers(&self, user_id: &str) -> Vec<String> {
+        let mut ids = vec![user_id.to_string()];
+        ids.extend(self.aliases.iter().cloned());
+        ids
+    }
+
+    /// Ids block lists are matched against, including the username
+    fn identifiers(&self, user_id: &str) -> Vec<String> {
+        let mut ids = self.stable_identifiers(user_id);
+        if let Some(username) = &self.username {
+            ids.push(format!("@{}", username.trim_start_matches('@')));
+        }
+        ids
+    }
+}
+
 /// Determine what action to take for an incoming message.
 ///
 /// This is a pure function with no side effects - it only reads state and
@@ -121,44 +264,88 @@ pub fn determine_action(
     channel: &str,
     user_id: &str,
     text: &str,
-    _image_paths: &[PathBuf],
+    attachment_paths: &[PathBuf],
     store: &mut PairingStore,
-    username: Option<String>,
-    display_name: Option<String>,
+    sender: SenderInfo,
 ) -> Result<MessageAction> {
-    let text = text.trim();
+    let settings = crate::config::Config::load()
+        .map(|c: crate::config::Config| c.channel_settings(channel))
+        .unwrap_or_default();
+    determine_action_with(
+        &settings,
+        channel,
+        user_id,
+        text,
+        attachment_paths,
+        store,
+        sender,
+    )
+}
 
-    // Check if user is approved
-    if !store.is_approved(channel, user_id) {
-        let settings = crate::config::Config::load()
-            .map(|c: crate::config::Config| c.channel_settings(channel))
-            .unwrap_or_default();
+/// `determine_action` with the channel's settings
+fn determine_action_with(
+    settings: &crate::config::ChannelSettings,
+    channel: &str,
+    user_id: &str,
+    text: &str,
+    attachment_paths: &[PathBuf],
+    store: &mut PairingStore,
+    sender: SenderInfo,
+) -> Result<MessageAction> {
+    let mut text = text.trim();
+    // Blocked users get no reply at all, not even in an approved group
+    if is_blocked_sender(store, settings, channel, user_id, &sender) {
+        return Ok(MessageAction::Ignore);
+    }
 
-        if settings.auto_approve {
-            store.auto_approve(channel, user_id, username, display_name)?;
+    // Check if user is approved, skipping pairing for those with an invite
+    // or on the allow list. Invites are personal, so they don't work in groups.
+    if !store.is_approved(channel, user_id) {
+        if let Some(token) = invite_token(text)
+            && !pairing::is_group(user_id)
+            && store.redeem_invite(channel, user_id, token)?
+        {
+            info!("{} user {} joined with an invite", channel, user_id);
+            // Start onboarding as if they'd said hello
+            text = "/start";
+        } else if settings.auto_approve || settings.is_allowed(&sender.stable_identifiers(user_id))
+        {
+            store.auto_approve(channel, user_id, sender.username, sender.display_name)?;
         } else {
-            let (code, _is_new) =
-                store.get_or_create_pending(channel, user_id, username, display_name)?;
-            return Ok(MessageAction::NeedsPairing { code });
+            let (code, is_new) = store.get_or_create_pending(
+                channel,
+                user_id,
+                sender.username,
+                sender.display_name,
+            )?;
+            return Ok(MessageAction::NeedsPairing { code, is_new });
         }
     }
 
-    // Check if onboarding is complete
-    let onboarding_complete = onboarding::is_complete_for_user(channel, user_id)?;
+    // Check if onboarding is complete (groups skip the per-user interview)
+    let onboarding_complete =
+        pairing::is_group(user_id) || onboarding::is_complete_for_user(store, channel, user_id)?;
 
     // Process commands (work even during onboarding)
     match process_command(store, channel, user_id, text, onboarding_complete)? {
         CommandResult::Response(response) => {
             return Ok(MessageAction::SendResponse(response));
         }
+        CommandResult::Buttons(text, buttons) => {
+            return Ok(MessageAction::SendButtons { text, buttons });
+        }
         CommandResult::CronRun(job_id) => {
             return Ok(MessageAction::ExecuteCronJob { job_id });
         }
+        CommandResult::Stop => return Ok(MessageAction::StopTask),
         CommandResult::NotACommand => {}
     }
 
     // Handle onboarding if not complete
     if !onboarding_complete {
+        if !use_daily_message(store, channel, user_id)? {
+            return Ok(MessageAction::SendResponse(DAILY_LIMIT_REACHED.to_string()));
+        }
         // Treat /start as "hi" for onboarding
         let message = if text == "/start" { "hi" } else { text };
         return Ok(MessageAction::Onboarding {
@@ -171,21 +358,66 @@ pub fn determine_action(
         return Ok(MessageAction::Ignore);
     }
 
-    // Empty message with no images - ignore
-    if text.is_empty() {
+    // Empty message with no images or files - ignore
+    if text.is_empty() && attachment_paths.is_empty() {
         return Ok(MessageAction::Ignore);
     }
 
+    if !use_daily_message(store, channel, user_id)? {
+        return Ok(MessageAction::SendResponse(DAILY_LIMIT_REACHED.to_string()));
+    }
+
     // Normal message - query Claude
     Ok(MessageAction::QueryClaude {
         text: text.to_string(),
     })
 }
 
-/// Build a message combining text and image paths.
+const DAILY_LIMIT_REACHED: &str =
+    "You've reached today's message limit. It resets at midnight UTC.";
+
+/// Count a message against the role's daily limit; false once it's used up
+fn use_daily_message(store: &mut PairingStore, channel: &str, user_id: &str) -> Result<bool> {
+    let role = user_role(store, channel, user_id);
+    store.use_message(channel, user_id, role.daily_messages)
+}
+
+/// Whether the sender, or in a group the member who wrote, is blocked
+fn is_blocked_sender(
+    store: &PairingStore,
+    settings: &crate::config::ChannelSettings,
+    channel: &str,
+    user_id: &str,
+    sender: &SenderInfo,
+) -> bool {
+    let member_blocked = sender.member.as_ref().is_some_and(|(member_id, member)| {
+        store.is_blocked(channel, member_id) || settings.is_blocked(&member.identifiers(member_id))
+    });
+    store.is_blocked(channel, user_id)
+        || settings.is_blocked(&sender.identifiers(user_id))
+        || member_blocked
+}
+
+/// The invite token in a first message: the whole message, or what follows
+/// /start when someone opens a Telegram invite link
+fn invite_token(text: &str) -> Option<&str> {
+    let token = text.strip_prefix("/start").unwrap_or(text).trim();
+    let is_token = !token.is_empty()
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    is_token.then_some(token)
+}
+
+/// Build a message combining text, image paths and document paths.
 ///
 /// Images are referenced using @path syntax which Claude Code understands.
-pub fn build_text_with_images(text: &str, image_paths: &[PathBuf]) -> String {
+/// Documents are listed by path so they can be read on demand.
+pub fn build_text_with_images(
+    text: &str,
+    image_paths: &[PathBuf],
+    document_paths: &[PathBuf],
+) -> String {
     let mut result = text.to_string();
 
     for (i, path) in image_paths.iter().enumerate() {
@@ -200,9 +432,161 @@ pub fn build_text_with_images(text: &str, image_paths: &[PathBuf]) -> String {
         }
     }
 
+    if !document_paths.is_empty() {
+        let mut files = String::from("Files sent by the user (saved in their inbox):");
+        for path in document_paths {
+            files.push_str(&format!("\n- {}", path.display()));
+        }
+        result = if result.is_empty() {
+            files
+        } else {
+            format!("{}\n\n{}", result, files)
+        };
+    }
+
     result
 }
 
+// ============================================================================
+// Shared Locations and Contacts
+// ============================================================================
+
+/// A place the user shared
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Name of the place, for venues
+    pub title: Option<String>,
+    pub address: Option<String>,
+}
+
+impl SharedLocation {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            title: None,
+            address: None,
+        }
+    }
+
+    /// Find coordinates in a map link, which is how Signal apps share a location
+    pub fn from_map_link(text: &str) -> Option<Self> {
+        text.split_whitespace().find_map(|word| {
+            let coordinates = if let Some(rest) = word.strip_prefix("geo:") {
+                rest
+            } else if word.contains("maps.google.")
+                || word.contains("google.com/maps")
+                || word.contains("maps.apple.com")
+            {
+                let (_, query) = word.split_once('?')?;
+                query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("q=").or(param.strip_prefix("ll=")))?
+            } else {
+                return None;
+            };
+
+            let coordinates = coordinates.replace("%2C", ",").replace("%2c", ",");
+            let (latitude, rest) = coordinates.split_once(',')?;
+            let longitude = rest.split([',', ';', '&', '?']).next()?;
+            let latitude: f64 = latitude.trim().parse().ok()?;
+            let longitude: f64 = longitude.trim().parse().ok()?;
+            ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+                .then(|| Self::new(latitude, longitude))
+        })
+    }
+
+    /// Name, address and coordinates, one per line
+    pub fn details(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(title) = &self.title {
+            lines.push(format!("Place: {}", title));
+        }
+        if let Some(address) = &self.address {
+            lines.push(format!("Address: {}", address));
+        }
+        lines.push(format!(
+            "Coordinates: {:.6}, {:.6}",
+            self.latitude, self.longitude
+        ));
+        lines.push(format!(
+            "Map: https://www.openstreetmap.org/?mlat={:.6}&mlon={:.6}",
+            self.latitude, self.longitude
+        ));
+        lines.join("\n")
+    }
+}
+
+/// A contact card the user shared
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SharedContact {
+    pub name: String,
+    pub phone_numbers: Vec<String>,
+    pub emails: Vec<String>,
+    pub organization: Option<String>,
+}
+
+impl SharedContact {
+    fn details(&self) -> String {
+        let mut lines = vec![format!("Name: {}", self.name)];
+        if let Some(organization) = &self.organization {
+            lines.push(format!("Organization: {}", organization));
+        }
+        for phone in &self.phone_numbers {
+            lines.push(format!("Phone: {}", phone));
+        }
+        for email in &self.emails {
+            lines.push(format!("Email: {}", email));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Add a shared location and contact cards to a message, so requests like
+/// "find a coffee shop near here" have something to go on
+pub fn add_shared_context(
+    text: &str,
+    location: Option<&SharedLocation>,
+    contacts: &[SharedContact],
+) -> String {
+    let mut parts = Vec::new();
+    if !text.is_empty() {
+        parts.push(text.to_string());
+    }
+    if let Some(location) = location {
+        parts.push(format!(
+            "[Location shared by the user]\n{}",
+            location.details()
+        ));
+    }
+    for contact in contacts {
+        parts.push(format!(
+            "[Contact card shared by the user]\n{}",
+            contact.details()
+        ));
+    }
+    parts.join("\n\n")
+}
+
+/// Keep the latest location a user shared in their profile, if enabled
+pub fn remember_location(channel: &str, user_id: &str, location: &SharedLocation) {
+    let enabled = crate::config::Config::load()
+        .map(|c| c.remember_location)
+        .unwrap_or(false);
+    // A group's location says nothing about where any one person is
+    if !enabled || pairing::is_group(user_id) {
+        return;
+    }
+    let saved = PairingStore::load().and_then(|store| {
+        onboarding::save_last_location(&store, channel, user_id, &location.details())
+    });
+    if let Err(e) = saved {
+        warn!("Failed to save location: {}", e);
+    }
+}
+
 /// Execute an action that doesn't require the task manager.
 ///
 /// Returns `Some(text)` if the action is QueryClaude (needs task_manager handling),
@@ -211,22 +595,36 @@ pub async fn execute_action(
     channel: &dyn Channel,
     user_id: &str,
     action: MessageAction,
+    task_manager: &UserTaskManager,
 ) -> Result<Option<String>> {
     match action {
         MessageAction::SendResponse(response) => {
-            channel.send_message(&response).await?;
+            send_or_queue(channel, user_id, &response, &[]).await;
+            Ok(None)
+        }
+
+        MessageAction::SendButtons { text, buttons } => {
+            send_or_queue(channel, user_id, &text, &buttons).await;
             Ok(None)
         }
 
-        MessageAction::NeedsPairing { code } => {
+        MessageAction::NeedsPairing { code, is_new } => {
+            let greeting = if pairing::is_group(user_id) {
+                "Hi! This group hasn't been approved yet."
+            } else {
+                "Hi! I don't recognize you yet."
+            };
             let response = format!(
-                "Hi! I don't recognize you yet.\n\n\
+                "{}\n\n\
                  Pairing code: {}\n\n\
                  Ask the owner to run:\n\
                  cica approve {}",
-                code, code
+                greeting, code, code
             );
             channel.send_message(&response).await?;
+            if is_new {
+                notify_admins(channel, &code).await;
+            }
             Ok(None)
         }
 
@@ -235,14 +633,14 @@ pub async fn execute_action(
             let _typing = channel.start_typing();
             let result = execute_cron_job(&job_id, channel.name(), user_id).await;
             let response = result.unwrap_or_else(|e| format!("Job failed: {}", e));
-            channel.send_message(&response).await?;
+            send_or_queue(channel, user_id, &response, &[]).await;
             Ok(None)
         }
 
         MessageAction::Onboarding { message } => {
             let _typing = channel.start_typing();
             let response = handle_onboarding(channel.name(), user_id, &message).await?;
-            channel.send_message(&response).await?;
+            send_or_queue(channel, user_id, &response, &[]).await;
             Ok(None)
         }
 
@@ -251,6 +649,16 @@ pub async fn execute_action(
             Ok(Some(text))
         }
 
+        MessageAction::StopTask => {
+            let response = if task_manager.stop(channel.name(), user_id).await {
+                "Stopped."
+            } else {
+                "I'm not working on anything right now."
+            };
+            channel.send_message(response).await?;
+            Ok(None)
+        }
+
         MessageAction::Ignore => Ok(None),
     }
 }
@@ -319,13 +727,35 @@ fn remove_file_path_lines(response: &str) -> String {
 /// Execute a Claude query for the user.
 ///
 /// This is called from within the task_manager callback after messages
-/// have been debounced and batched.
-pub async fn execute_claude_query(channel: Arc<dyn Channel>, user_id: &str, messages: Vec<String>) {
-    let combined_text = messages.join("\n\n");
+/// have been debounced and batched. `session_user_id` is the id used for the
+/// conversation, which may differ from `user_id` (e.g. Slack threads); the
+/// user's role, files and memories always come from `user_id`.
+pub async fn execute_claude_query(
+    channel: Arc<dyn Channel>,
+    user_id: &str,
+    session_user_id: &str,
+    messages: Vec<String>,
+    steering: Steering,
+) {
+    let mut combined_text = messages.join("\n\n");
     let _typing = channel.start_typing();
+    let (progress_updates, progress) = ProgressUpdates::start(channel.clone());
+
+    // Load pairing store for the user's files and session
+    let mut store = match PairingStore::load() {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to load pairing store: {}", e);
+            let _ = channel
+                .send_message(&format!("Sorry, I encountered an error: {}", e))
+                .await;
+            return;
+        }
+    };
 
     // Build context prompt
     let context_prompt = match onboarding::build_context_prompt_for_user(
+        &store,
         Some(channel.display_name()),
         Some(channel.name()),
         Some(user_id),
@@ -341,154 +771,1417 @@ pub async fn execute_claude_query(channel: Arc<dyn Channel>, user_id: &str, mess
         }
     };
 
-    // Load pairing store for session management
-    let mut store = match PairingStore::load() {
-        Ok(s) => s,
-        Err(e) => {
-            warn!("Failed to load pairing store: {}", e);
-            let _ = channel
-                .send_message(&format!("Sorry, I encountered an error: {}", e))
-                .await;
-            return;
+    // Query AI backend with session. Under the steer policy, messages sent
+    // in the meantime stop the backend, and the conversation carries on with
+    // them from where it was cut off.
+    let mut prompt = combined_text.clone();
+    let response = loop {
+        let query = query_ai_with_session(
+            &mut store,
+            channel.name(),
+            user_id,
+            session_user_id,
+            &prompt,
+            context_prompt.clone(),
+            Some(progress.clone()),
+        );
+        tokio::select! {
+            result = query => match result {
+                Ok((response, _session_id)) => break response,
+                Err(e) => {
+                    warn!("AI query failed: {}", e);
+                    let _ = channel
+                        .send_message(&format!("Sorry, I encountered an error: {}", e))
+                        .await;
+                    return;
+                }
+            },
+            () = steering.interrupted() => {}
         }
+
+        let follow_ups = steering.take().await;
+        info!("Steering answer with {} follow-up(s)", follow_ups.len());
+        let follow_up_text = follow_ups.join("\n\n");
+        combined_text.push_str("\n\n");
+        combined_text.push_str(&follow_up_text);
+
+        // Without a session to resume, the interrupted work is gone
+        prompt = if has_session(&store, channel.name(), user_id, session_user_id) {
+            format!("{}\n\n{}", STEERING_PROMPT, follow_up_text)
+        } else {
+            combined_text.clone()
+        };
     };
 
-    // Query AI backend with session
-    let (response, _session_id) = match query_ai_with_session(
-        &mut store,
+    // No more "still working" updates once the answer is in
+    let files_read = progress_updates.finish(progress).await;
+
+    // The assistant may ask for a tap to confirm before doing something destructive
+    let (response, needs_confirmation) = split_confirmation(&response);
+
+    // Only the messages sent from here on belong to the reply
+    channel.take_sent_message_ids();
+
+    let buttons = if needs_confirmation {
+        confirmation_buttons()
+    } else {
+        Vec::new()
+    };
+    send_or_queue(channel.as_ref(), user_id, &response, &buttons).await;
+
+    // Log the reply so reactions to it count as feedback
+    let message_ids = channel.take_sent_message_ids();
+    let role_model = crate::config::Config::load()
+        .ok()
+        .and_then(|config| store.role(&config, channel.name(), user_id).model);
+    if let Err(e) = feedback::record_reply(
         channel.name(),
         user_id,
+        message_ids,
         &combined_text,
-        context_prompt,
+        &response,
+        role_model.as_deref(),
+        &files_read,
+    ) {
+        warn!("Failed to log reply: {}", e);
+    }
+
+    // Re-index memories in case Claude saved new ones
+    reindex_user_memories(&store, channel.name(), user_id);
+}
+
+/// Sends "still working" updates while a long answer is being prepared,
+/// naming the tool the backend started most recently. Stops when dropped.
+struct ProgressUpdates {
+    handle: JoinHandle<()>,
+    files_read: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl ProgressUpdates {
+    /// Start waiting to report progress; pass the sender to the backend
+    fn start(channel: Arc<dyn Channel>) -> (Self, ProgressSender) {
+        let (sender, mut activities) = mpsc::unbounded_channel::<Activity>();
+        let files_read = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reads = files_read.clone();
+        let handle = tokio::spawn(async move {
+            let mut current = None;
+            let mut next_update = Instant::now() + PROGRESS_AFTER;
+            loop {
+                tokio::select! {
+                    activity = activities.recv() => match activity {
+                        Some(activity) => {
+                            if let Some(path) = activity.reads {
+                                reads.lock().unwrap().push(path);
+                            }
+                            current = Some(activity.description);
+                        }
+                        None => return,
+                    },
+                    _ = tokio::time::sleep_until(next_update) => {
+                        let message = progress_message(current.as_deref());
+                        if let Err(e) = channel.send_message(&message).await {
+                            debug!("Failed to send progress update: {}", e);
+                        }
+                        next_update += PROGRESS_INTERVAL;
+                    }
+                }
+            }
+        });
+        (Self { handle, files_read }, sender)
+    }
+
+    /// Stop reporting progress and return the files the backend read,
+    /// once it has handled the activities already sent
+    async fn finish(mut self, sender: ProgressSender) -> Vec<String> {
+        drop(sender);
+        let _ = tokio::time::timeout(Duration::from_secs(1), &mut self.handle).await;
+        std::mem::take(&mut *self.files_read.lock().unwrap())
+    }
+}
+
+impl Drop for ProgressUpdates {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn progress_message(activity: Option<&str>) -> String {
+    match activity {
+        Some(activity) => format!(
+            "Still working on it, currently {}. Send /stop to cancel.",
+            activity
+        ),
+        None => "Still working on it. Send /stop to cancel.".to_string(),
+    }
+}
+
+/// Send an assistant response: media files it mentions are attached, and
+/// otherwise the text is split to fit the channel. The parts that went out
+/// are counted in `parts_sent`, so a failed send can be resumed.
+pub async fn send_response(
+    channel: &dyn Channel,
+    response: &str,
+    buttons: &[Vec<Button>],
+    parts_sent: &mut usize,
+) -> Result<()> {
+    let attachments = extract_media_attachments(response);
+    if attachments.is_empty() {
+        return outbox::send_remaining(channel, response, &[], buttons, parts_sent).await;
+    }
+
+    debug!("Sending response with {} attachment(s)", attachments.len());
+
+    // Clean up the response text - remove lines that mention the file paths
+    let cleaned_response = remove_file_path_lines(response);
+    outbox::send_remaining(
+        channel,
+        &cleaned_response,
+        &attachments,
+        buttons,
+        parts_sent,
     )
     .await
-    {
-        Ok(r) => r,
-        Err(e) => {
-            warn!("AI query failed: {}", e);
-            let _ = channel
-                .send_message(&format!("Sorry, I encountered an error: {}", e))
-                .await;
-            return;
-        }
-    };
+}
 
-    // Extract any media attachments (images, videos) from the response
-    let attachments = extract_media_attachments(&response);
+/// Send a response, queueing what didn't go out to be retried later
+pub async fn send_or_queue(
+    channel: &dyn Channel,
+    user_id: &str,
+    response: &str,
+    buttons: &[Vec<Button>],
+) {
+    let buttons = &restrict_to_member(buttons, channel.member().as_deref());
+    let mut parts_sent = 0;
+    if let Err(e) = send_response(channel, response, buttons, &mut parts_sent).await {
+        warn!("Failed to send message, queueing it for retry: {}", e);
+        let message = outbox::OutboundMessage::new(channel.name(), user_id, response)
+            .with_address(channel.reply_address())
+            .with_buttons(buttons.to_vec())
+            .with_parts_sent(parts_sent);
+        if let Err(e) = outbox::enqueue(message).await {
+            warn!("Failed to queue message: {}", e);
+        }
+    }
+}
 
-    // Send response with attachments if any
-    if !attachments.is_empty() {
-        debug!("Sending response with {} attachment(s)", attachments.len());
+// ============================================================================
+// Buttons
+// ============================================================================
 
-        // Clean up the response text - remove lines that mention the file paths
-        let cleaned_response = remove_file_path_lines(&response);
+/// A button shown under a message. When tapped, `action` is passed back to
+/// `determine_button_action`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Button {
+    pub label: String,
+    pub action: String,
+}
 
-        if let Err(e) = channel
-            .send_message_with_attachments(&cleaned_response, &attachments)
-            .await
-        {
-            warn!("Failed to send message with attachments: {}", e);
-        }
-    } else {
-        // Send regular text message
-        if let Err(e) = channel.send_message(&response).await {
-            warn!("Failed to send message: {}", e);
+impl Button {
+    pub fn new(label: impl Into<String>, action: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            action: action.into(),
         }
     }
+}
 
-    // Re-index memories in case Claude saved new ones
-    reindex_user_memories(channel.name(), user_id);
+/// Marker the assistant ends a reply with to ask for a tap to confirm
+const CONFIRM_MARKER: &str = "[[confirm]]";
+
+/// Buttons for managing one cron job
+fn cron_job_buttons(job: &cron::CronJob) -> Vec<Button> {
+    let id = job.short_id();
+    let toggle = if job.enabled {
+        Button::new(format!("Pause {}", id), format!("cron:pause:{}", id))
+    } else {
+        Button::new(format!("Resume {}", id), format!("cron:resume:{}", id))
+    };
+    vec![
+        toggle,
+        Button::new("Run", format!("cron:run:{}", id)),
+        Button::new("Delete", format!("cron:delete:{}", id)),
+    ]
 }
 
-// ============================================================================
-// Task Manager
-// ============================================================================
+/// Buttons that act for one person: answering what the assistant asked them,
+/// or deleting a job
+fn is_personal_action(action: &str) -> bool {
+    action.starts_with("confirm:") || action.starts_with("cron:delete")
+}
 
-/// Debounce duration for batching rapid messages
-const DEBOUNCE_MS: u64 = 200;
+/// Mark personal buttons as only for a group member, by their id after an `@`
+fn restrict_to_member(buttons: &[Vec<Button>], member: Option<&str>) -> Vec<Vec<Button>> {
+    let Some(member) = member else {
+        return buttons.to_vec();
+    };
+    buttons
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|button| {
+                    if is_personal_action(&button.action) {
+                        Button::new(&button.label, format!("{}@{}", button.action, member))
+                    } else {
+                        button.clone()
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
 
-/// Active task for a user
-struct ActiveTask {
-    handle: JoinHandle<()>,
+/// Split a button action into the action and the member it's for, if any
+pub fn button_member(action: &str) -> (&str, Option<&str>) {
+    match action.rsplit_once('@') {
+        Some((action, member)) => (action, Some(member)),
+        None => (action, None),
+    }
 }
 
-/// Manages per-user message processing with debouncing and interruption
-pub struct UserTaskManager {
-    tasks: Mutex<HashMap<String, ActiveTask>>,
-    pending: Mutex<HashMap<String, Vec<String>>>,
+/// Confirm/Cancel buttons for an assistant reply that asks before acting
+fn confirmation_buttons() -> Vec<Vec<Button>> {
+    vec![vec![
+        Button::new("Confirm", "confirm:yes"),
+        Button::new("Cancel", "confirm:no"),
+    ]]
+}
+
+/// Strip the confirmation marker from a reply.
+/// Returns the cleaned reply and whether confirmation was requested.
+fn split_confirmation(response: &str) -> (String, bool) {
+    if !response.contains(CONFIRM_MARKER) {
+        return (response.to_string(), false);
+    }
+    (
+        response.replace(CONFIRM_MARKER, "").trim().to_string(),
+        true,
+    )
+}
+
+/// Determine what to do when a user taps a button.
+///
+/// In a group, `sender` names the member who tapped: blocked members are
+/// ignored, and personal buttons only work for the member they were sent to.
+pub fn determine_button_action(
+    channel: &str,
+    user_id: &str,
+    action: &str,
+    store: &mut PairingStore,
+    sender: SenderInfo,
+) -> Result<MessageAction> {
+    let settings = crate::config::Config::load()
+        .map(|c| c.channel_settings(channel))
+        .unwrap_or_default();
+    determine_button_action_with(&settings, channel, user_id, action, store, sender)
 }
 
-impl UserTaskManager {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self {
-            tasks: Mutex::new(HashMap::new()),
-            pending: Mutex::new(HashMap::new()),
-        })
+/// `determine_button_action` with the channel's settings
+fn determine_button_action_with(
+    settings: &crate::config::ChannelSettings,
+    channel: &str,
+    user_id: &str,
+    action: &str,
+    store: &mut PairingStore,
+    sender: SenderInfo,
+) -> Result<MessageAction> {
+    if is_blocked_sender(store, settings, channel, user_id, &sender) {
+        return Ok(MessageAction::Ignore);
+    }
+
+    let (action, for_member) = button_member(action);
+    let tapped_by = sender
+        .member
+        .as_ref()
+        .map(|(member_id, _)| member_id.as_str());
+    if pairing::is_group(user_id) && is_personal_action(action) && for_member != tapped_by {
+        return Ok(MessageAction::Ignore);
+    }
+
+    let parts: Vec<&str> = action.splitn(3, ':').collect();
+
+    // Pairing decisions come from admins, everything else from approved users
+    if let ["pair", decision, code] = parts.as_slice() {
+        if !is_admin(store, channel, user_id) {
+            return Ok(MessageAction::Ignore);
+        }
+        let result = match *decision {
+            "approve" => store.approve(code).map(|r| ("Approved", r)),
+            "deny" => store.deny(code).map(|r| ("Denied", r)),
+            _ => return Ok(MessageAction::Ignore),
+        };
+        let response = match result {
+            Ok((verb, request)) => format!("{} {}.", verb, describe_request(&request)),
+            Err(_) => "That request has already been handled or has expired.".to_string(),
+        };
+        return Ok(MessageAction::SendResponse(response));
+    }
+
+    if !store.is_approved(channel, user_id) {
+        return Ok(MessageAction::Ignore);
+    }
+
+    let command = match parts.as_slice() {
+        ["cron", "delete", id] => {
+            return Ok(MessageAction::SendButtons {
+                text: format!("Delete job [{}]? This can't be undone.", id),
+                buttons: vec![vec![
+                    Button::new("Delete", format!("cron:delete-confirmed:{}", id)),
+                    Button::new("Cancel", "cancel"),
+                ]],
+            });
+        }
+        ["cron", "delete-confirmed", id] => format!("/cron remove {}", id),
+        ["cron", subcommand @ ("pause" | "resume" | "run"), id] => {
+            format!("/cron {} {}", subcommand, id)
+        }
+        ["confirm", "yes"] => {
+            return Ok(MessageAction::QueryClaude {
+                text: "Confirmed, go ahead.".to_string(),
+            });
+        }
+        ["confirm", "no"] => {
+            return Ok(MessageAction::QueryClaude {
+                text: "Cancelled, don't do it.".to_string(),
+            });
+        }
+        ["cancel"] => return Ok(MessageAction::SendResponse("Cancelled.".to_string())),
+        _ => {
+            debug!("Unknown button action: {}", action);
+            return Ok(MessageAction::Ignore);
+        }
+    };
+
+    let onboarding_complete =
+        pairing::is_group(user_id) || onboarding::is_complete_for_user(store, channel, user_id)?;
+    match process_command(store, channel, user_id, &command, onboarding_complete)? {
+        CommandResult::Response(response) => Ok(MessageAction::SendResponse(response)),
+        CommandResult::Buttons(text, buttons) => Ok(MessageAction::SendButtons { text, buttons }),
+        CommandResult::CronRun(job_id) => Ok(MessageAction::ExecuteCronJob { job_id }),
+        CommandResult::Stop => Ok(MessageAction::StopTask),
+        CommandResult::NotACommand => Ok(MessageAction::Ignore),
+    }
+}
+
+/// Handle a button tap: run its action and, for confirmations, pass the answer
+/// on to the assistant through the task manager.
+///
+/// `session_user_id` is the id used for the conversation, which may differ
+/// from `user_id` (e.g. Slack threads).
+pub async fn handle_button(
+    channel: Arc<dyn Channel>,
+    user_id: &str,
+    session_user_id: &str,
+    action: &str,
+    sender: SenderInfo,
+    task_manager: &Arc<UserTaskManager>,
+) -> Result<()> {
+    let mut store = PairingStore::load()?;
+    let action = determine_button_action(channel.name(), user_id, action, &mut store, sender)?;
+
+    if let Some(text) = execute_action(channel.as_ref(), user_id, action, task_manager).await? {
+        let user_key = format!("{}:{}", channel.name(), session_user_id);
+        let user_id = user_id.to_string();
+        let session_user_id = session_user_id.to_string();
+        task_manager
+            .process_message(user_key, text, move |messages, steering| async move {
+                execute_claude_query(channel, &user_id, &session_user_id, messages, steering).await;
+            })
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Name, username or id of whoever made a pairing request
+fn describe_request(request: &pairing::PendingRequest) -> String {
+    request
+        .display_name
+        .as_ref()
+        .or(request.username.as_ref())
+        .cloned()
+        .unwrap_or_else(|| request.user_id.clone())
+}
+
+/// Tell the admins about a new pairing request. Admins on the same channel
+/// get buttons to approve or deny it; those on other channels hear about it
+/// through the outbox and can reply with /approve or /deny.
+async fn notify_admins(channel: &dyn Channel, code: &str) {
+    let admins = crate::config::Config::load()
+        .map(|c| c.admins)
+        .unwrap_or_default();
+    if admins.is_empty() {
+        return;
+    }
+
+    let Some(request) = PairingStore::load()
+        .ok()
+        .and_then(|s| s.pending.into_iter().find(|r| r.code == code))
+    else {
+        return;
+    };
+
+    let kind = if pairing::is_group(&request.user_id) {
+        "group"
+    } else {
+        "user"
+    };
+    let message = format!(
+        "New {} {} wants to talk to me: {}\n\n\
+         Reply /approve {} or /deny {}\n\
+         Or run: cica approve {}",
+        channel.display_name(),
+        kind,
+        describe_request(&request),
+        code,
+        code,
+        code
+    );
+    let buttons = vec![vec![
+        Button::new("Approve", format!("pair:approve:{}", code)),
+        Button::new("Deny", format!("pair:deny:{}", code)),
+    ]];
+
+    for admin in admins {
+        let Some((admin_channel, admin_id)) = admin.split_once(':') else {
+            continue;
+        };
+
+        if admin_channel != channel.name() {
+            let notification = outbox::OutboundMessage::new(admin_channel, admin_id, &message);
+            if let Err(e) = outbox::enqueue(notification).await {
+                warn!("Failed to notify admin {}: {}", admin, e);
+            }
+            continue;
+        }
+
+        let Some(target) = channel.for_user(admin_id) else {
+            continue;
+        };
+        if let Err(e) = target.send_message_with_buttons(&message, &buttons).await {
+            warn!("Failed to notify admin {}: {}", admin, e);
+        }
+    }
+}
+
+// ============================================================================
+// Message Splitting
+// ============================================================================
+
+/// Replies that would take more messages than this are sent as a document
+const MAX_REPLY_PARTS: usize = 5;
+
+/// Caption for replies sent as a document
+const LONG_REPLY_CAPTION: &str = "That's a long one, so here it is as a file.";
+
+/// Sent with the buttons of a reply that went out as a file
+const BUTTONS_PROMPT: &str = "What would you like to do?";
+
+/// The messages a reply is sent as, given the channel's length limit.
+/// `None` means it's too long for that and goes out as a document instead.
+pub fn reply_parts(limit: Option<usize>, message: &str) -> Option<Vec<String>> {
+    let parts = match limit {
+        Some(limit) => split_message(message, limit),
+        None => vec![message.to_string()],
+    };
+    (parts.len() <= MAX_REPLY_PARTS).then_some(parts)
+}
+
+/// Length of text in UTF-16 code units, the unit message limits are given in
+fn text_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Split a message into parts of at most `limit` UTF-16 code units.
+///
+/// Paragraphs are kept together where possible and code fences are never
+/// split across parts without being closed and reopened.
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let text = text.trim();
+    if text_len(text) <= limit {
+        return vec![text.to_string()];
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for block in split_blocks(text) {
+        let pieces = if text_len(&block) > limit {
+            split_block(&block, limit)
+        } else {
+            vec![block]
+        };
+
+        for piece in pieces {
+            let joined_len = text_len(&current) + 2 + text_len(&piece);
+            if current.is_empty() {
+                current = piece;
+            } else if joined_len <= limit {
+                current.push_str("\n\n");
+                current.push_str(&piece);
+            } else {
+                parts.push(std::mem::replace(&mut current, piece));
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Split text into paragraphs, keeping each code fence as a single block
+fn split_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            if !in_fence && !current.is_empty() {
+                blocks.push(current.join("\n"));
+                current.clear();
+            }
+            current.push(line);
+            if in_fence {
+                blocks.push(current.join("\n"));
+                current.clear();
+            }
+            in_fence = !in_fence;
+        } else if line.trim().is_empty() && !in_fence {
+            if !current.is_empty() {
+                blocks.push(current.join("\n"));
+                current.clear();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+
+    if !current.is_empty() {
+        blocks.push(current.join("\n"));
+    }
+    blocks
+}
+
+/// Split a single oversized block by lines (and by characters for very long
+/// lines). Code fences are closed at the end of each part and reopened at
+/// the start of the next.
+fn split_block(block: &str, limit: usize) -> Vec<String> {
+    let mut lines: Vec<&str> = block.lines().collect();
+    let fence = lines
+        .first()
+        .filter(|l| l.trim_start().starts_with("```"))
+        .map(|l| l.trim().to_string());
+    if fence.is_some() {
+        lines.remove(0);
+        if lines
+            .last()
+            .is_some_and(|l| l.trim_start().starts_with("```"))
+        {
+            lines.pop();
+        }
+    }
+
+    let (open, close) = match &fence {
+        Some(open) => (format!("{}\n", open), "\n```".to_string()),
+        None => (String::new(), String::new()),
+    };
+    let room = limit
+        .saturating_sub(text_len(&open) + text_len(&close))
+        .max(1);
+
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        for piece in split_chars(line, room) {
+            let joined_len = text_len(&current) + 1 + text_len(&piece);
+            if current.is_empty() {
+                current = piece;
+            } else if joined_len <= room {
+                current.push('\n');
+                current.push_str(&piece);
+            } else {
+                parts.push(format!("{}{}{}", open, current, close));
+                current = piece;
+            }
+        }
+    }
+    if !current.is_empty() {
+        parts.push(format!("{}{}{}", open, current, close));
+    }
+    parts
+}
+
+/// Split a line into pieces of at most `limit` UTF-16 code units, preferring
+/// spaces
+fn split_chars(line: &str, limit: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+
+    while text_len(rest) > limit {
+        // Always take at least one character, however wide
+        let mut len = 0;
+        let cut = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                len += c.len_utf16();
+                i > 0 && len > limit
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let cut = match rest[..cut].rfind(' ') {
+            Some(space) if space > 0 => space,
+            _ => cut,
+        };
+        pieces.push(rest[..cut].to_string());
+        rest = rest[cut..].trim_start();
+    }
+
+    pieces.push(rest.to_string());
+    pieces
+}
+
+/// Save a long reply as a Markdown file to send as a document. The caller
+/// removes it once it's been sent.
+fn write_reply_document(message: &str) -> Result<PathBuf> {
+    let dir = crate::config::paths()?.internal_dir.join("replies");
+    std::fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!("reply-{}.md", uuid::Uuid::new_v4()));
+    std::fs::write(&path, message)?;
+
+    Ok(path)
+}
+
+// ============================================================================
+// Attachments
+// ============================================================================
+
+/// Image extensions that can be passed to the backend with @path
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Check if a path points to an image based on its extension
+pub fn is_image_path(path: &std::path::Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Check whether files from a sender should be processed: they are approved,
+/// or the channel approves everyone automatically
+pub fn is_trusted_sender(channel: &str, user_id: &str) -> bool {
+    PairingStore::load()
+        .map(|s| s.is_approved(channel, user_id))
+        .unwrap_or(false)
+        || crate::config::Config::load()
+            .map(|c| c.channel_settings(channel).auto_approve)
+            .unwrap_or(false)
+}
+
+/// Get a user's inbox directory, where incoming documents are stored
+pub fn inbox_dir(channel: &str, user_id: &str) -> Result<PathBuf> {
+    let store = PairingStore::load()?;
+    Ok(onboarding::user_dir(&store, channel, user_id)?.join("inbox"))
+}
+
+/// Largest document accepted, in bytes
+pub fn max_attachment_bytes() -> u64 {
+    crate::config::Config::load()
+        .map(|c| c.attachments)
+        .unwrap_or_default()
+        .max_size_mb
+        * 1024
+        * 1024
+}
+
+/// Pick a path in the user's inbox for an incoming file.
+///
+/// Keeps the original file name where possible and never overwrites an
+/// existing file.
+pub fn inbox_path(channel: &str, user_id: &str, file_name: &str) -> Result<PathBuf> {
+    let dir = inbox_dir(channel, user_id)?;
+    std::fs::create_dir_all(&dir)?;
+
+    let name = sanitize_file_name(file_name);
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem.to_string(), format!(".{}", ext)),
+        _ => (name.clone(), String::new()),
+    };
+
+    let mut path = dir.join(&name);
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}{}", stem, n, ext));
+        n += 1;
+    }
+
+    Ok(path)
+}
+
+/// Make a sender-supplied file name safe to use inside the inbox
+fn sanitize_file_name(file_name: &str) -> String {
+    let base = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').to_string();
+
+    if cleaned.is_empty() {
+        "file".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Note added to the message when a document is over the size limit, so the
+/// assistant can tell the user
+pub fn oversized_attachment_note(file_name: &str, limit_bytes: u64) -> String {
+    format!(
+        "[The user sent \"{}\" but it was not saved: files larger than {} MB are not accepted]",
+        file_name,
+        limit_bytes / 1024 / 1024
+    )
+}
+
+// ============================================================================
+// Voice Notes
+// ============================================================================
+
+/// Transcribe voice notes and combine them with the message text.
+///
+/// Audio that isn't transcribed (transcription is off or failed) is saved to
+/// the user's inbox instead, and returned to be passed on like any other
+/// document. Nothing is done for senders who aren't approved yet (they get
+/// pairing instructions instead).
+pub async fn add_voice_transcripts(
+    channel: &dyn Channel,
+    user_id: &str,
+    text: &str,
+    audio_paths: &[PathBuf],
+) -> (String, Vec<PathBuf>) {
+    if audio_paths.is_empty() || !is_trusted_sender(channel.name(), user_id) {
+        return (text.to_string(), Vec::new());
+    }
+
+    let config = crate::config::Config::load().unwrap_or_default();
+    let Some(transcription) = config.transcription.clone() else {
+        debug!("Transcription is not enabled, passing voice notes on as files");
+        let kept = audio_paths
+            .iter()
+            .filter_map(|path| keep_audio(channel.name(), user_id, path))
+            .collect();
+        return (text.to_string(), kept);
+    };
+
+    let mut parts = Vec::new();
+    if !text.trim().is_empty() {
+        parts.push(text.trim().to_string());
+    }
+
+    let mut kept = Vec::new();
+    for path in audio_paths {
+        let _typing = channel.start_typing();
+        match transcribe::transcribe(path, &transcription).await {
+            Ok(transcript) if !transcript.is_empty() => {
+                if transcription.echo_transcript {
+                    let _ = channel
+                        .send_message(&format!("Transcript: {}", transcript))
+                        .await;
+                }
+                parts.push(transcript);
+            }
+            Ok(_) => debug!("No speech found in {:?}", path),
+            Err(e) => {
+                warn!("Failed to transcribe {:?}: {:#}", path, e);
+                kept.extend(keep_audio(channel.name(), user_id, path));
+            }
+        }
+    }
+
+    (parts.join("\n\n"), kept)
+}
+
+/// Copy an audio file into the user's inbox
+fn keep_audio(channel: &str, user_id: &str, path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_string_lossy();
+    let saved = inbox_path(channel, user_id, &file_name)
+        .and_then(|dest| std::fs::copy(path, &dest).map(|_| dest).map_err(Into::into));
+    match saved {
+        Ok(dest) => Some(dest),
+        Err(e) => {
+            warn!("Failed to save audio {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+// ============================================================================
+// Replies, Edits and Reactions
+// ============================================================================
+
+/// Longest quoted text included in a prompt, in characters
+const MAX_QUOTE_CHARS: usize = 1000;
+
+/// Put the message the user replied to (or quoted) in front of their text.
+///
+/// `author` is who wrote the quoted message: "you" for the assistant's own
+/// messages, a name for others, or None when unknown.
+pub fn add_reply_context(text: &str, author: Option<&str>, quoted: &str) -> String {
+    let quoted = quoted.trim();
+    if quoted.is_empty() {
+        return text.to_string();
+    }
+
+    let mut excerpt: String = quoted.chars().take(MAX_QUOTE_CHARS).collect();
+    if excerpt.len() < quoted.len() {
+        excerpt.push('…');
+    }
+    let quote_lines: Vec<String> = excerpt.lines().map(|line| format!("> {}", line)).collect();
+
+    let heading = match author {
+        Some(author) => format!("Quoting {}:", author),
+        None => "Quoting an earlier message:".to_string(),
+    };
+    format!("{}\n{}\n\n{}", heading, quote_lines.join("\n"), text)
+}
+
+/// Handle an edited message: if it is still waiting to be answered (or
+/// being answered), answer the edited text instead. Edits to messages that
+/// were already answered are ignored.
+///
+/// `text` is the edited message prepared the same way as a new one.
+pub async fn handle_edit(
+    channel: Arc<dyn Channel>,
+    user_id: &str,
+    session_user_id: &str,
+    message_id: &str,
+    text: String,
+    task_manager: &Arc<UserTaskManager>,
+) -> bool {
+    let user_key = format!("{}:{}", channel.name(), session_user_id);
+    let user_id = user_id.to_string();
+    let session_user_id = session_user_id.to_string();
+    let replaced = task_manager
+        .process_edit(
+            user_key,
+            message_id,
+            text,
+            move |messages, steering| async move {
+                execute_claude_query(channel, &user_id, &session_user_id, messages, steering).await;
+            },
+        )
+        .await;
+
+    if replaced {
+        info!("Message {} was edited before it was answered", message_id);
+    } else {
+        debug!("Ignoring edit of already answered message {}", message_id);
+    }
+    replaced
+}
+
+/// Log a reaction to one of the assistant's replies as feedback. Reactions
+/// to other messages are ignored.
+pub fn handle_reaction(channel: &str, message_id: &str, user_id: &str, emoji: &str, removed: bool) {
+    match feedback::record_reaction(channel, message_id, user_id, emoji, removed) {
+        Ok(true) => info!(
+            "Feedback from {} on {} message {}: {}{}",
+            user_id,
+            channel,
+            message_id,
+            if removed { "removed " } else { "" },
+            emoji
+        ),
+        Ok(false) => debug!("Ignoring reaction to unknown message {}", message_id),
+        Err(e) => warn!("Failed to log reaction: {}", e),
+    }
+}
+
+// ============================================================================
+// Group Chats
+// ============================================================================
+
+/// Prefix a group message with its sender so the assistant knows who is talking
+pub fn format_group_message(sender: &str, text: &str) -> String {
+    if text.is_empty() {
+        return text.to_string();
+    }
+    format!("{}: {}", sender, text)
+}
+
+/// Remove every case-insensitive occurrence of `mention` (e.g. "@cica_bot")
+/// from a group message, including command suffixes like "/new@cica_bot".
+pub fn strip_mention(text: &str, mention: &str) -> String {
+    if mention.is_empty() {
+        return text.trim().to_string();
+    }
+
+    let lower_text = text.to_lowercase();
+    let lower_mention = mention.to_lowercase();
+
+    // Lowercasing can change byte lengths for some scripts; leave those untouched
+    if lower_text.len() != text.len() || lower_mention.len() != mention.len() {
+        return text.trim().to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = 0;
+    while let Some(pos) = lower_text[rest..].find(&lower_mention) {
+        let start = rest + pos;
+        result.push_str(&text[rest..start]);
+        rest = start + mention.len();
+    }
+    result.push_str(&text[rest..]);
+
+    result.trim().to_string()
+}
+
+// ============================================================================
+// Task Manager
+// ============================================================================
+
+/// How long to wait for more messages before answering, unless configured
+const DEFAULT_DEBOUNCE_MS: u64 = 200;
+
+/// How long an answer can take before "still working" updates start
+const PROGRESS_AFTER: Duration = Duration::from_secs(60);
+
+/// Time between "still working" updates
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(120);
+
+/// Prompt for follow-ups that cut a running answer short under the steer policy
+const STEERING_PROMPT: &str = "I interrupted you to add the following. Carry on from where you were, taking it into account in your reply:";
+
+/// Work handed to a user's intake queue
+type IntakeWork = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// How long a user's intake queue waits for more work before closing
+const INTAKE_IDLE: Duration = Duration::from_secs(60);
+
+/// Answers a batch of messages; the latest one given for a user is used
+type BatchHandler =
+    Box<dyn FnOnce(Vec<String>, Steering) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// Active task for a user
+struct ActiveTask {
+    id: u64,
+    handle: JoinHandle<()>,
+}
+
+/// A message waiting in a user's batch. The id (if the channel has one) lets
+/// an edit of the message replace it.
+struct QueuedMessage {
+    id: Option<String>,
+    text: String,
+}
+
+/// Manages per-user message processing with debouncing and interruption.
+///
+/// Each user has at most one task, which answers their pending messages batch
+/// by batch until none are left. What a new message does to a running answer
+/// depends on the channel's interruption policy.
+pub struct UserTaskManager {
+    policy: InterruptionPolicy,
+    debounce: Duration,
+    next_task_id: AtomicU64,
+    tasks: Mutex<HashMap<String, ActiveTask>>,
+    pending: Mutex<HashMap<String, Vec<QueuedMessage>>>,
+    handlers: Mutex<HashMap<String, BatchHandler>>,
+    /// Batches currently being answered, kept so edits can restart them
+    in_flight: Mutex<HashMap<String, Vec<QueuedMessage>>>,
+    /// Wakes a running answer when new messages arrive (steer policy)
+    interrupts: Mutex<HashMap<String, Arc<Notify>>>,
+    /// Queues running each user's incoming messages one after another
+    intake: Arc<std::sync::Mutex<HashMap<String, mpsc::UnboundedSender<IntakeWork>>>>,
+}
+
+impl UserTaskManager {
+    /// Create a manager with a channel's configured interruption policy and debounce
+    pub fn for_channel(channel: &str) -> Arc<Self> {
+        let settings = crate::config::Config::load()
+            .map(|c| c.channel_settings(channel))
+            .unwrap_or_default();
+        let debounce_ms = settings.debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS);
+        Self::with_policy(settings.interruption, Duration::from_millis(debounce_ms))
+    }
+
+    pub fn with_policy(policy: InterruptionPolicy, debounce: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            policy,
+            debounce,
+            next_task_id: AtomicU64::new(0),
+            tasks: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            handlers: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            interrupts: Mutex::new(HashMap::new()),
+            intake: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Run `work` once the work handed over earlier for the same user is done.
+    ///
+    /// For channels that receive messages faster than they can prepare them
+    /// (downloads, pairing checks), so each user's messages still reach
+    /// `process_message` in the order they were sent.
+    pub fn run_in_order<Fut>(&self, user_key: String, work: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut intake = self.intake.lock().unwrap();
+        let work: IntakeWork = Box::pin(work);
+        let work = match intake.get(&user_key) {
+            Some(queue) => match queue.send(work) {
+                Ok(()) => return,
+                Err(mpsc::error::SendError(work)) => work,
+            },
+            None => work,
+        };
+
+        let (queue, mut works) = mpsc::unbounded_channel();
+        let _ = queue.send(work);
+        intake.insert(user_key.clone(), queue);
+
+        let intake = self.intake.clone();
+        tokio::spawn(async move {
+            loop {
+                match tokio::time::timeout(INTAKE_IDLE, works.recv()).await {
+                    Ok(Some(work)) => work.await,
+                    Ok(None) => return,
+                    Err(_) => {
+                        // Nothing can be queued while the map is locked
+                        let mut intake = intake.lock().unwrap();
+                        if works.is_empty() {
+                            intake.remove(&user_key);
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Process a message for a user.
+    /// Messages are debounced - if more arrive within the debounce delay, they're batched.
+    /// Under the abort policy a running answer is stopped and restarted with
+    /// the new message; otherwise the message waits for it to finish.
+    pub async fn process_message<F, Fut>(
+        self: &Arc<Self>,
+        user_key: String,
+        message: String,
+        handler: F,
+    ) where
+        F: FnOnce(Vec<String>, Steering) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.process_message_with_id(user_key, None, message, handler)
+            .await;
+    }
+
+    /// Like `process_message`, remembering the channel's id for the message
+    /// so a later edit can replace it with `process_edit`.
+    pub async fn process_message_with_id<F, Fut>(
+        self: &Arc<Self>,
+        user_key: String,
+        message_id: Option<String>,
+        message: String,
+        handler: F,
+    ) where
+        F: FnOnce(Vec<String>, Steering) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        debug!("Queueing message for {}: {}", user_key, message);
+
+        // Add message to pending queue
+        {
+            let mut pending = self.pending.lock().await;
+            pending
+                .entry(user_key.clone())
+                .or_insert_with(Vec::new)
+                .push(QueuedMessage {
+                    id: message_id,
+                    text: message,
+                });
+        }
+        self.set_handler(&user_key, handler).await;
+
+        match self.policy {
+            InterruptionPolicy::Abort => self.restart(user_key).await,
+            InterruptionPolicy::Queue => self.ensure_running(user_key).await,
+            InterruptionPolicy::Steer => {
+                if let Some(interrupt) = self.interrupts.lock().await.get(&user_key) {
+                    interrupt.notify_one();
+                }
+                self.ensure_running(user_key).await
+            }
+        }
+    }
+
+    /// Replace an edited message that hasn't been answered yet.
+    ///
+    /// If the message is still waiting, its text is swapped in place. If it
+    /// is part of the batch being answered right now, the interruption policy
+    /// decides: abort starts the batch over with the new text, while queue and
+    /// steer treat the new text like a message sent in the meantime. Returns
+    /// false when the message is unknown, e.g. because it was already answered.
+    pub async fn process_edit<F, Fut>(
+        self: &Arc<Self>,
+        user_key: String,
+        message_id: &str,
+        message: String,
+        handler: F,
+    ) -> bool
+    where
+        F: FnOnce(Vec<String>, Steering) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // Still waiting: the scheduled task will pick up the new text
+        {
+            let mut pending = self.pending.lock().await;
+            if let Some(queued) = pending.get_mut(&user_key).and_then(|batch| {
+                batch
+                    .iter_mut()
+                    .find(|m| m.id.as_deref() == Some(message_id))
+            }) {
+                debug!("Replacing pending message {} for {}", message_id, user_key);
+                queued.text = message;
+                return true;
+            }
+        }
+
+        // Being answered
+        {
+            let mut in_flight = self.in_flight.lock().await;
+            let Some(queued) = in_flight.get_mut(&user_key).and_then(|batch| {
+                batch
+                    .iter_mut()
+                    .find(|m| m.id.as_deref() == Some(message_id))
+            }) else {
+                return false;
+            };
+            if self.policy != InterruptionPolicy::Abort {
+                drop(in_flight);
+                debug!("Queueing edit of {} for {}", message_id, user_key);
+                self.process_message_with_id(
+                    user_key,
+                    Some(message_id.to_string()),
+                    message,
+                    handler,
+                )
+                .await;
+                return true;
+            }
+            queued.text = message;
+        }
+
+        debug!(
+            "Restarting batch for {} after edit of {}",
+            user_key, message_id
+        );
+        self.set_handler(&user_key, handler).await;
+        self.restart(user_key).await;
+
+        true
+    }
+
+    /// Cancel everything a user has running or waiting, killing the backend
+    /// process of an answer in progress. Covers all of the user's conversations
+    /// on the channel (e.g. every Slack thread). Returns false if nothing was
+    /// running or waiting.
+    pub async fn stop(&self, channel: &str, user_id: &str) -> bool {
+        let prefix = format!("{}:{}", channel, user_id);
+        let is_users = |key: &String| {
+            key.strip_prefix(&prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        };
+
+        let mut tasks = self.tasks.lock().await;
+        let mut stopped = false;
+        tasks.retain(|key, task| {
+            if !is_users(key) {
+                return true;
+            }
+            debug!("Stopping task for {}", key);
+            task.handle.abort();
+            stopped = true;
+            false
+        });
+
+        let mut pending = self.pending.lock().await;
+        stopped |= pending.keys().any(is_users);
+        pending.retain(|key, _| !is_users(key));
+        self.handlers.lock().await.retain(|key, _| !is_users(key));
+        self.in_flight.lock().await.retain(|key, _| !is_users(key));
+
+        stopped
     }
 
-    /// Process a message for a user.
-    /// If there's already a task running for this user, it will be aborted.
-    /// Messages are debounced - if more arrive within DEBOUNCE_MS, they're batched.
-    pub async fn process_message<F, Fut>(
-        self: &Arc<Self>,
-        user_key: String,
-        message: String,
-        handler: F,
-    ) where
-        F: FnOnce(Vec<String>) -> Fut + Send + 'static,
-        Fut: std::future::Future<Output = ()> + Send,
+    async fn set_handler<F, Fut>(&self, user_key: &str, handler: F)
+    where
+        F: FnOnce(Vec<String>, Steering) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
     {
-        debug!("Queueing message for {}: {}", user_key, message);
-
-        // Add message to pending queue
-        {
-            let mut pending = self.pending.lock().await;
-            pending
-                .entry(user_key.clone())
-                .or_insert_with(Vec::new)
-                .push(message);
-        }
+        let handler: BatchHandler =
+            Box::new(move |messages, steering| Box::pin(handler(messages, steering)));
+        self.handlers
+            .lock()
+            .await
+            .insert(user_key.to_string(), handler);
+    }
 
+    /// Abort the user's running answer, if any, and start over with the batch
+    /// it was answering followed by whatever is pending
+    async fn restart(self: &Arc<Self>, user_key: String) {
         let mut tasks = self.tasks.lock().await;
 
-        // If there's an existing task, abort it - we'll start fresh with all pending messages
         if let Some(existing) = tasks.remove(&user_key) {
             debug!("Aborting existing task for {}", user_key);
             existing.handle.abort();
         }
 
-        // Spawn new task with debounce
+        let interrupted = self.in_flight.lock().await.remove(&user_key);
+        if let Some(mut batch) = interrupted {
+            let mut pending = self.pending.lock().await;
+            let waiting = pending.entry(user_key.clone()).or_insert_with(Vec::new);
+            batch.append(waiting);
+            *waiting = batch;
+        }
+
+        self.spawn_task(&mut tasks, user_key);
+    }
+
+    /// Start a task for the user unless one is already running
+    async fn ensure_running(self: &Arc<Self>, user_key: String) {
+        let mut tasks = self.tasks.lock().await;
+        if !tasks.contains_key(&user_key) {
+            self.spawn_task(&mut tasks, user_key);
+        }
+    }
+
+    fn spawn_task(self: &Arc<Self>, tasks: &mut HashMap<String, ActiveTask>, user_key: String) {
+        let id = self.next_task_id.fetch_add(1, Ordering::Relaxed);
         let manager = Arc::clone(self);
         let user_key_clone = user_key.clone();
+        let handle = tokio::spawn(async move { manager.run_task(user_key_clone, id).await });
+        tasks.insert(user_key, ActiveTask { id, handle });
+    }
 
-        let handle = tokio::spawn(async move {
+    /// Answer the user's pending messages batch by batch until none are left
+    async fn run_task(self: Arc<Self>, user_key: String, id: u64) {
+        loop {
             // Debounce: wait a bit for more messages
-            tokio::time::sleep(Duration::from_millis(DEBOUNCE_MS)).await;
+            tokio::time::sleep(self.debounce).await;
+
+            let batch = self
+                .pending
+                .lock()
+                .await
+                .remove(&user_key)
+                .unwrap_or_default();
+            let handler = self.handlers.lock().await.remove(&user_key);
+
+            if let (false, Some(handler)) = (batch.is_empty(), handler) {
+                debug!("Processing {} message(s) for {}", batch.len(), user_key);
+
+                let messages = batch.iter().map(|m| m.text.clone()).collect();
+                self.in_flight.lock().await.insert(user_key.clone(), batch);
+
+                let interrupt = Arc::new(Notify::new());
+                self.interrupts
+                    .lock()
+                    .await
+                    .insert(user_key.clone(), interrupt.clone());
+                let steering = Steering {
+                    manager: Arc::clone(&self),
+                    user_key: user_key.clone(),
+                    interrupt,
+                };
+                handler(messages, steering).await;
 
-            // Collect all pending messages for this user
-            let messages = {
-                let mut pending = manager.pending.lock().await;
-                pending.remove(&user_key_clone).unwrap_or_default()
-            };
+                self.interrupts.lock().await.remove(&user_key);
+                self.in_flight.lock().await.remove(&user_key);
+            }
 
-            if messages.is_empty() {
+            // Checked under the task lock, so a message arriving now is either
+            // picked up here or starts a new task
+            let mut tasks = self.tasks.lock().await;
+            let has_more = self
+                .pending
+                .lock()
+                .await
+                .get(&user_key)
+                .is_some_and(|batch| !batch.is_empty());
+            if !has_more {
+                if tasks.get(&user_key).is_some_and(|task| task.id == id) {
+                    tasks.remove(&user_key);
+                }
                 return;
             }
+        }
+    }
+}
 
-            debug!(
-                "Processing {} message(s) for {}",
-                messages.len(),
-                user_key_clone
-            );
+/// Lets a running answer pick up messages sent after it started
+pub struct Steering {
+    manager: Arc<UserTaskManager>,
+    user_key: String,
+    interrupt: Arc<Notify>,
+}
+
+impl Steering {
+    /// Wait for messages sent since the answer started, to cut it short and
+    /// carry on with them. Only the steer policy interrupts; otherwise this
+    /// never returns.
+    pub async fn interrupted(&self) {
+        if self.manager.policy != InterruptionPolicy::Steer {
+            return std::future::pending().await;
+        }
+        loop {
+            self.interrupt.notified().await;
+            // Let a burst of messages arrive together
+            tokio::time::sleep(self.manager.debounce).await;
+            let has_messages = self
+                .manager
+                .pending
+                .lock()
+                .await
+                .get(&self.user_key)
+                .is_some_and(|batch| !batch.is_empty());
+            if has_messages {
+                return;
+            }
+        }
+    }
 
-            // Run the handler
-            handler(messages).await;
+    /// Take the messages that arrived since the answer started. Only the
+    /// steer policy hands them over; otherwise they wait for their own turn.
+    pub async fn take(&self) -> Vec<String> {
+        if self.manager.policy != InterruptionPolicy::Steer {
+            return Vec::new();
+        }
 
-            // Clean up task entry
-            manager.tasks.lock().await.remove(&user_key_clone);
-        });
+        let batch = self
+            .manager
+            .pending
+            .lock()
+            .await
+            .remove(&self.user_key)
+            .unwrap_or_default();
+        let messages = batch.iter().map(|m| m.text.clone()).collect();
 
-        tasks.insert(user_key, ActiveTask { handle });
+        // They're part of the answer now, so edits to them restart it
+        if let Some(in_flight) = self.manager.in_flight.lock().await.get_mut(&self.user_key) {
+            in_flight.extend(batch);
+        }
+        messages
     }
 }
 
@@ -498,16 +2191,33 @@ pub enum CommandResult {
     NotACommand,
     /// Command was handled, return this response to the user
     Response(String),
+    /// Command was handled, return this response with buttons
+    Buttons(String, Vec<Vec<Button>>),
     /// Trigger async cron job execution (job_id)
     CronRun(String),
+    /// Cancel the user's in-flight task
+    Stop,
 }
 
 /// Available commands
 const COMMANDS: &[(&str, &str)] = &[
     ("/commands", "Show available commands"),
     ("/new", "Start a new conversation"),
+    ("/stop", "Stop what I'm working on"),
     ("/skills", "List available skills"),
     ("/cron", "Manage scheduled jobs"),
+    ("/token", "Create a token for the OpenAI-compatible API"),
+    ("/link", "Link your accounts on other channels"),
+    ("/unlink", "Give this account its own profile again"),
+];
+
+/// Commands only admins can use, listed for them by /commands
+const ADMIN_COMMANDS: &[(&str, &str)] = &[
+    ("/users", "Show who has access and who is waiting"),
+    ("/approve", "Approve a pairing request: /approve <code>"),
+    ("/deny", "Deny a pairing request: /deny <code>"),
+    ("/revoke", "Take away access: /revoke <channel:user_id>"),
+    ("/role", "Change a role: /role <channel:user_id> <role>"),
 ];
 
 /// Process a command if the message is one.
@@ -517,54 +2227,351 @@ pub fn process_command(
     user_id: &str,
     text: &str,
     onboarding_complete: bool,
+) -> Result<CommandResult> {
+    let role = user_role(store, channel, user_id);
+    process_command_as(store, &role, channel, user_id, text, onboarding_complete)
+}
+
+/// Process a command for a user with the given role
+fn process_command_as(
+    store: &mut PairingStore,
+    role: &Role,
+    channel: &str,
+    user_id: &str,
+    text: &str,
+    onboarding_complete: bool,
 ) -> Result<CommandResult> {
     let text = text.trim();
+    let (command, args) = text
+        .split_once(char::is_whitespace)
+        .map(|(command, args)| (command, args.trim()))
+        .unwrap_or((text, ""));
+
+    if ADMIN_COMMANDS.iter().any(|(cmd, _)| *cmd == command) {
+        return process_admin_command(store, channel, user_id, command, args);
+    }
 
-    if text == "/commands" {
-        let mut response = String::from("Available commands:\n");
-        for (cmd, desc) in COMMANDS {
-            response.push_str(&format!("\n{} - {}", cmd, desc));
+    // The user's role decides which commands they may use
+    if COMMANDS.iter().any(|(cmd, _)| *cmd == command) && !role.allows_command(command) {
+        return Ok(CommandResult::Response(format!(
+            "Sorry, {} isn't available to you.",
+            command
+        )));
+    }
+
+    match command {
+        "/commands" => {
+            let mut response = String::from("Available commands:\n");
+            for (cmd, desc) in COMMANDS.iter().filter(|(cmd, _)| role.allows_command(cmd)) {
+                response.push_str(&format!("\n{} - {}", cmd, desc));
+            }
+            if is_admin(store, channel, user_id) {
+                response.push_str("\n\nAdmin commands:\n");
+                for (cmd, desc) in ADMIN_COMMANDS {
+                    response.push_str(&format!("\n{} - {}", cmd, desc));
+                }
+            }
+            Ok(CommandResult::Response(response))
+        }
+        "/new" => {
+            if !onboarding_complete {
+                return Ok(CommandResult::Response(
+                    "Please complete the onboarding first. Say \"hello\" to get started!"
+                        .to_string(),
+                ));
+            }
+            let session_key = session_key(store, channel, user_id);
+            store.sessions.remove(&session_key);
+            store.save()?;
+            Ok(CommandResult::Response(
+                "Starting fresh! Our previous conversation has been cleared.".to_string(),
+            ))
+        }
+        "/stop" => Ok(CommandResult::Stop),
+        "/skills" => {
+            let available_skills: Vec<_> = skills::discover_skills()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|skill| role.allows_skill(&skill.name))
+                .collect();
+            if available_skills.is_empty() {
+                return Ok(CommandResult::Response("No skills installed.".to_string()));
+            }
+            let mut response = String::from("Available skills:\n");
+            for skill in available_skills {
+                response.push_str(&format!("\n• {} - {}", skill.name, skill.description));
+            }
+            Ok(CommandResult::Response(response))
+        }
+        "/token" => {
+            if pairing::is_group(user_id) {
+                return Ok(CommandResult::Response(
+                    "API tokens are personal. Send /token to me in a direct message.".to_string(),
+                ));
+            }
+            let api = crate::config::Config::load().ok().and_then(|c| c.api);
+            let Some(api) = api else {
+                return Ok(CommandResult::Response(
+                    "The API server is not enabled. Ask the owner to add an [api] section to config.toml."
+                        .to_string(),
+                ));
+            };
+            let token = store.create_api_token(channel, user_id)?;
+            Ok(CommandResult::Response(format!(
+                "Your API token (replaces any previous one):\n\n{}\n\n\
+                 Base URL: {}\n\
+                 Use it as the API key in any OpenAI-compatible client.",
+                token,
+                api.base_url()
+            )))
         }
-        return Ok(CommandResult::Response(response));
+        "/link" => process_link_command(store, channel, user_id, args),
+        "/unlink" => process_unlink_command(store, channel, user_id),
+        "/cron" => process_cron_command(store, channel, user_id, args),
+        // Not a way around the role check above
+        _ if command.starts_with("/cron") => Ok(CommandResult::Response(format!(
+            "Unknown command {}. Try /cron help.",
+            command
+        ))),
+        _ => Ok(CommandResult::NotACommand),
+    }
+}
+
+/// A user's role; guest if the config can't be read
+fn user_role(store: &PairingStore, channel: &str, user_id: &str) -> Role {
+    match crate::config::Config::load() {
+        Ok(config) => store.role(&config, channel, user_id),
+        Err(_) => Role::guest(),
+    }
+}
+
+/// Admins are listed in `admins` or have a role that makes them one
+fn is_admin(store: &PairingStore, channel: &str, user_id: &str) -> bool {
+    let listed = crate::config::Config::load()
+        .map(|c| c.is_admin(channel, user_id))
+        .unwrap_or(false);
+    listed || user_role(store, channel, user_id).admin
+}
+
+/// Process /users, /approve, /deny and /revoke for an admin
+fn process_admin_command(
+    store: &mut PairingStore,
+    channel: &str,
+    user_id: &str,
+    command: &str,
+    args: &str,
+) -> Result<CommandResult> {
+    if !is_admin(store, channel, user_id) {
+        return Ok(CommandResult::Response(format!(
+            "Only admins can use {}.",
+            command
+        )));
+    }
+
+    let response = match (command, args) {
+        ("/users", _) => return Ok(users_overview(store)),
+        ("/approve" | "/deny", "") => format!("Usage: {} <code>", command),
+        ("/approve", code) => match store.approve(code) {
+            Ok(request) => format!("Approved {}.", describe_request(&request)),
+            Err(_) => "That request has already been handled or has expired.".to_string(),
+        },
+        ("/deny", code) => match store.deny(code) {
+            Ok(request) => format!("Denied {}.", describe_request(&request)),
+            Err(_) => "That request has already been handled or has expired.".to_string(),
+        },
+        ("/revoke", user) => match user.split_once(':') {
+            Some((target_channel, target_id))
+                if (target_channel, target_id) == (channel, user_id) =>
+            {
+                "You can't revoke your own access.".to_string()
+            }
+            Some((target_channel, target_id)) if !target_id.is_empty() => {
+                if store.revoke(target_channel, target_id)? {
+                    if let Err(e) = crate::cron::pause_jobs_for(target_channel, target_id) {
+                        warn!("Failed to pause cron jobs of {}: {}", user, e);
+                    }
+                    format!("Revoked access for {}.", user)
+                } else {
+                    format!("{} was not approved.", user)
+                }
+            }
+            _ => "Usage: /revoke <channel:user_id>, as shown by /users".to_string(),
+        },
+        ("/role", args) => process_role_command(store, args)?,
+        _ => return Ok(CommandResult::NotACommand),
+    };
+    Ok(CommandResult::Response(response))
+}
+
+/// Process /role: show or change a user's role
+fn process_role_command(store: &mut PairingStore, args: &str) -> Result<String> {
+    let config = crate::config::Config::load()?;
+    let mut parts = args.split_whitespace();
+    let Some((channel, user_id)) = parts.next().and_then(|user| user.split_once(':')) else {
+        return Ok("Usage: /role <channel:user_id> <role>, as shown by /users".to_string());
+    };
+    if !store.is_approved(channel, user_id) {
+        return Ok(format!("{}:{} is not approved.", channel, user_id));
     }
 
-    if text == "/new" {
-        if !onboarding_complete {
-            return Ok(CommandResult::Response(
-                "Please complete the onboarding first. Say \"hello\" to get started!".to_string(),
-            ));
+    let Some(role) = parts.next() else {
+        return Ok(format!(
+            "{}:{} is a {}.",
+            channel,
+            user_id,
+            store.role_name(&config, channel, user_id)
+        ));
+    };
+    if config.role(role).is_none() {
+        return Ok(format!(
+            "There's no {} role. Available: {}",
+            role,
+            config.role_names().join(", ")
+        ));
+    }
+
+    store.set_role(channel, user_id, role)?;
+    Ok(format!("{}:{} is now a {}.", channel, user_id, role))
+}
+
+/// Approved users and pending requests, with buttons to handle the requests
+fn users_overview(store: &mut PairingStore) -> CommandResult {
+    let config = crate::config::Config::load().unwrap_or_default();
+    let approved = store.approved_users();
+    let mut response = format!("Approved ({}):", approved.len());
+    for (channel, user_id) in &approved {
+        let key = format!("{}:{}", channel, user_id);
+        let role = store.role_name(&config, channel, user_id);
+        match onboarding::user_name_for_user(store, channel, user_id) {
+            Some(name) => response.push_str(&format!("\n• {} ({}), {}", name, key, role)),
+            None => response.push_str(&format!("\n• {}, {}", key, role)),
         }
-        let session_key = format!("{}:{}", channel, user_id);
-        store.sessions.remove(&session_key);
-        store.save()?;
+    }
+
+    let blocked: usize = store.blocked.values().map(Vec::len).sum();
+    if blocked > 0 {
+        response.push_str(&format!("\n\nBlocked: {}", blocked));
+    }
+
+    let pending = store.list_pending();
+    if pending.is_empty() {
+        response.push_str("\n\nNo one is waiting for approval.");
+        return CommandResult::Response(response);
+    }
+
+    response.push_str(&format!("\n\nWaiting for approval ({}):", pending.len()));
+    let mut buttons = Vec::new();
+    for request in pending {
+        let channel = get_channel_info(&request.channel)
+            .map(|c| c.display_name)
+            .unwrap_or(request.channel.as_str());
+        response.push_str(&format!(
+            "\n• {} on {}: /approve {}",
+            describe_request(request),
+            channel,
+            request.code
+        ));
+        buttons.push(vec![
+            Button::new(
+                format!("Approve {}", describe_request(request)),
+                format!("pair:approve:{}", request.code),
+            ),
+            Button::new("Deny", format!("pair:deny:{}", request.code)),
+        ]);
+    }
+    CommandResult::Buttons(response, buttons)
+}
+
+/// Process /link: create a code, or redeem one created on another channel
+fn process_link_command(
+    store: &mut PairingStore,
+    channel: &str,
+    user_id: &str,
+    code: &str,
+) -> Result<CommandResult> {
+    if pairing::is_group(user_id) {
         return Ok(CommandResult::Response(
-            "Starting fresh! Our previous conversation has been cleared.".to_string(),
+            "Accounts are linked per person. Send /link to me in a direct message.".to_string(),
         ));
     }
 
-    if text == "/skills" {
-        let available_skills = skills::discover_skills().unwrap_or_default();
-        if available_skills.is_empty() {
-            return Ok(CommandResult::Response("No skills installed.".to_string()));
-        }
-        let mut response = String::from("Available skills:\n");
-        for skill in available_skills {
-            response.push_str(&format!("\n• {} - {}", skill.name, skill.description));
+    if code.is_empty() {
+        let code = store.create_link_code(channel, user_id)?;
+        return Ok(CommandResult::Response(format!(
+            "To link another account, send this from it within 10 minutes:\n\n/link {}\n\n\
+             That account will then share this one's profile, memories and scheduled jobs.",
+            code
+        )));
+    }
+
+    // Whatever this account had so far moves over to the linked one
+    let (own_channel, _) = store.identity(channel, user_id);
+    let own_dir = onboarding::user_dir(store, channel, user_id)?;
+    match store.redeem_link_code(code, channel, user_id) {
+        Ok((linked_channel, _)) => {
+            let shared_dir = onboarding::user_dir(store, channel, user_id)?;
+            if let Err(e) = onboarding::merge_user_dir(&own_dir, &shared_dir, &own_channel) {
+                warn!("Failed to move {} after linking: {}", own_dir.display(), e);
+            }
+
+            let name = get_channel_info(&linked_channel)
+                .map(|c| c.display_name)
+                .unwrap_or(linked_channel.as_str());
+            Ok(CommandResult::Response(format!(
+                "Linked! I now know you as the same person as on {}, \
+                 with the same profile, memories and scheduled jobs. \
+                 Anything I knew about you here has been added to it.",
+                name
+            )))
         }
-        return Ok(CommandResult::Response(response));
+        Err(e) => Ok(CommandResult::Response(format!(
+            "Couldn't link accounts: {}",
+            e
+        ))),
+    }
+}
+
+/// Process /unlink: give this account its own files again
+fn process_unlink_command(
+    store: &mut PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<CommandResult> {
+    if pairing::is_group(user_id) {
+        return Ok(CommandResult::Response(
+            "Accounts are linked per person. Send /unlink to me in a direct message.".to_string(),
+        ));
     }
 
-    // Handle /cron commands
-    if text.starts_with("/cron") {
-        let args = text.strip_prefix("/cron").unwrap_or("").trim();
-        return process_cron_command(channel, user_id, args);
+    let shared_dir = onboarding::user_dir(store, channel, user_id)?;
+    let heir = match store.unlink(channel, user_id) {
+        Ok(heir) => heir,
+        Err(e) => {
+            return Ok(CommandResult::Response(format!("Couldn't unlink: {}", e)));
+        }
+    };
+
+    // The shared files were kept under this account, so they move to one
+    // of the accounts that stay linked
+    if let Some((heir_channel, heir_user_id)) = heir {
+        let heir_dir = onboarding::user_dir(store, &heir_channel, &heir_user_id)?;
+        onboarding::merge_user_dir(&shared_dir, &heir_dir, channel)?;
     }
 
-    Ok(CommandResult::NotACommand)
+    Ok(CommandResult::Response(
+        "Unlinked. This account starts over with its own profile and memories; \
+         your other accounts keep the shared ones."
+            .to_string(),
+    ))
 }
 
 /// Process /cron subcommands
-fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<CommandResult> {
+fn process_cron_command(
+    pairing: &PairingStore,
+    channel: &str,
+    user_id: &str,
+    args: &str,
+) -> Result<CommandResult> {
     let parts: Vec<&str> = args.splitn(2, ' ').collect();
     let subcommand = parts.first().copied().unwrap_or("help");
     let rest = parts.get(1).copied().unwrap_or("");
@@ -572,7 +2579,7 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
     match subcommand {
         "list" | "ls" => {
             let store = CronStore::load()?;
-            let jobs = store.list_for_user(channel, user_id);
+            let jobs = store.list_for_user(pairing, channel, user_id);
 
             if jobs.is_empty() {
                 return Ok(CommandResult::Response(
@@ -582,6 +2589,7 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
             }
 
             let mut response = String::from("Your scheduled jobs:\n");
+            let mut buttons = Vec::new();
             for job in jobs {
                 let status = job.state.last_status.as_str();
                 let next = job
@@ -600,8 +2608,9 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
                     status,
                     next
                 ));
+                buttons.push(cron_job_buttons(job));
             }
-            Ok(CommandResult::Response(response))
+            Ok(CommandResult::Buttons(response, buttons))
         }
 
         "add" => {
@@ -666,9 +2675,9 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
             let mut store = CronStore::load()?;
 
             // Find job by full ID or prefix
-            let job_id = find_job_id(&store, channel, user_id, id)?;
+            let job_id = find_job_id(pairing, &store, channel, user_id, id)?;
 
-            match store.remove(&job_id, channel, user_id)? {
+            match store.remove(pairing, &job_id, channel, user_id)? {
                 Some(job) => Ok(CommandResult::Response(format!(
                     "Removed job [{}] \"{}\"",
                     job.short_id(),
@@ -687,7 +2696,7 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
             }
 
             let store = CronStore::load()?;
-            let job_id = find_job_id(&store, channel, user_id, id)?;
+            let job_id = find_job_id(pairing, &store, channel, user_id, id)?;
 
             // Return special variant for async execution by the channel handler
             Ok(CommandResult::CronRun(job_id))
@@ -702,12 +2711,10 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
             }
 
             let mut store = CronStore::load()?;
-            let job_id = find_job_id(&store, channel, user_id, id)?;
+            let job_id = find_job_id(pairing, &store, channel, user_id, id)?;
 
+            // find_job_id only returns jobs this user (or a linked identity) owns
             let result = if let Some(job) = store.get_mut(&job_id) {
-                if job.channel != channel || job.user_id != user_id {
-                    return Ok(CommandResult::Response("Job not found".to_string()));
-                }
                 job.enabled = false;
                 job.state.next_run_at = None;
                 Some((job.short_id().to_string(), job.name.clone()))
@@ -735,12 +2742,10 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
             }
 
             let mut store = CronStore::load()?;
-            let job_id = find_job_id(&store, channel, user_id, id)?;
+            let job_id = find_job_id(pairing, &store, channel, user_id, id)?;
 
+            // find_job_id only returns jobs this user (or a linked identity) owns
             let result = if let Some(job) = store.get_mut(&job_id) {
-                if job.channel != channel || job.user_id != user_id {
-                    return Ok(CommandResult::Response("Job not found".to_string()));
-                }
                 job.enabled = true;
                 job.update_next_run(cron::store::now_millis());
                 let next = job
@@ -788,14 +2793,16 @@ fn process_cron_command(channel: &str, user_id: &str, args: &str) -> Result<Comm
 /// Execute a cron job manually and return the output.
 /// Shared by all channel handlers.
 pub async fn execute_cron_job(job_id: &str, channel: &str, user_id: &str) -> Result<String> {
+    let pairing = PairingStore::load()?;
     let store = CronStore::load()?;
     let job = store
-        .get(job_id, channel, user_id)
+        .get(&pairing, job_id, channel, user_id)
         .ok_or_else(|| anyhow::anyhow!("Job not found"))?;
 
     // Build context prompt so the job has access to skills, configs, etc.
     let channel_display = get_channel_info(channel).map(|c| c.display_name);
     let context_prompt = onboarding::build_context_prompt_for_user(
+        &pairing,
         channel_display,
         Some(channel),
         Some(user_id),
@@ -806,8 +2813,7 @@ pub async fn execute_cron_job(job_id: &str, channel: &str, user_id: &str) -> Res
         &job.prompt,
         QueryOptions {
             system_prompt: Some(context_prompt),
-            skip_permissions: true,
-            ..Default::default()
+            ..QueryOptions::for_user(channel, user_id)
         },
     )
     .await?;
@@ -817,6 +2823,7 @@ pub async fn execute_cron_job(job_id: &str, channel: &str, user_id: &str) -> Res
 
 /// Find a job ID by full ID or prefix match
 fn find_job_id(
+    pairing: &PairingStore,
     store: &CronStore,
     channel: &str,
     user_id: &str,
@@ -825,13 +2832,13 @@ fn find_job_id(
     let id = id_or_prefix.trim();
 
     // First try exact match
-    if store.get(id, channel, user_id).is_some() {
+    if store.get(pairing, id, channel, user_id).is_some() {
         return Ok(id.to_string());
     }
 
     // Try prefix match
     let matches: Vec<_> = store
-        .list_for_user(channel, user_id)
+        .list_for_user(pairing, channel, user_id)
         .into_iter()
         .filter(|j| j.id.starts_with(id))
         .collect();
@@ -851,6 +2858,56 @@ fn find_job_id(
     }
 }
 
+/// Key a user's conversation is stored under. Linked identities share one
+/// when `share_linked_sessions` is on.
+fn session_key(store: &PairingStore, channel: &str, user_id: &str) -> String {
+    let shared = crate::config::Config::load()
+        .map(|c| c.share_linked_sessions)
+        .unwrap_or(false);
+    session_key_with(store, shared, channel, user_id)
+}
+
+fn session_key_with(store: &PairingStore, shared: bool, channel: &str, user_id: &str) -> String {
+    if shared {
+        store.canonical_key(channel, user_id)
+    } else {
+        format!("{}:{}", channel, user_id)
+    }
+}
+
+/// Options for a query in `session_user_id`'s conversation, limited by the
+/// role of `user_id`. The two differ for Slack threads, where the
+/// conversation is keyed by `user_id:thread_ts`.
+fn user_query_options(
+    store: &PairingStore,
+    config: &crate::config::Config,
+    channel: &str,
+    user_id: &str,
+    session_user_id: &str,
+) -> (String, QueryOptions) {
+    let session_key = session_key_with(
+        store,
+        config.share_linked_sessions,
+        channel,
+        session_user_id,
+    );
+    let options = QueryOptions {
+        resume_session: store.sessions.get(&session_key).cloned(),
+        ..QueryOptions::for_role(store.role(config, channel, user_id))
+    };
+    (session_key, options)
+}
+
+/// Whether the conversation has a backend session to resume
+fn has_session(store: &PairingStore, channel: &str, user_id: &str, session_user_id: &str) -> bool {
+    crate::config::Config::load().is_ok_and(|config| {
+        user_query_options(store, &config, channel, user_id, session_user_id)
+            .1
+            .resume_session
+            .is_some()
+    })
+}
+
 /// Query AI backend with automatic session recovery.
 ///
 /// If the session has expired, clears it and retries with a fresh conversation.
@@ -859,17 +2916,19 @@ pub async fn query_ai_with_session(
     store: &mut PairingStore,
     channel: &str,
     user_id: &str,
+    session_user_id: &str,
     text: &str,
     context_prompt: String,
+    progress: Option<ProgressSender>,
 ) -> Result<(String, String)> {
-    let session_key = format!("{}:{}", channel, user_id);
-    let existing_session = store.sessions.get(&session_key).cloned();
+    let config = crate::config::Config::load()?;
+    let (session_key, user_options) =
+        user_query_options(store, &config, channel, user_id, session_user_id);
 
     let options = backends::QueryOptions {
         system_prompt: Some(context_prompt.clone()),
-        resume_session: existing_session,
-        skip_permissions: true,
-        ..Default::default()
+        progress: progress.clone(),
+        ..user_options
     };
 
     let (response, session_id) = match backends::query_with_options(text, options).await {
@@ -881,14 +2940,15 @@ pub async fn query_ai_with_session(
                 || error_msg.contains("session")
             {
                 warn!("Session expired, starting fresh conversation");
-                store.sessions.remove(&session_key);
-                store.save()?;
+                store.update_session(&session_key, None)?;
 
+                let (_, user_options) =
+                    user_query_options(store, &config, channel, user_id, session_user_id);
                 let retry_options = backends::QueryOptions {
                     system_prompt: Some(context_prompt),
                     resume_session: None,
-                    skip_permissions: true,
-                    ..Default::default()
+                    progress,
+                    ..user_options
                 };
 
                 match backends::query_with_options(text, retry_options).await {
@@ -915,8 +2975,7 @@ pub async fn query_ai_with_session(
     if !session_id.is_empty()
         && store.sessions.get(&session_key).map(|s| s.as_str()) != Some(&session_id)
     {
-        store.sessions.insert(session_key, session_id.clone());
-        store.save()?;
+        store.update_session(&session_key, Some(&session_id))?;
     }
 
     Ok((response, session_id))
@@ -924,12 +2983,20 @@ pub async fn query_ai_with_session(
 
 /// Handle onboarding flow - AI drives the conversation
 pub async fn handle_onboarding(channel: &str, user_id: &str, message: &str) -> Result<String> {
-    let system_prompt = onboarding::system_prompt_for_user(channel, user_id)?;
-
+    let store = PairingStore::load()?;
+    let system_prompt = onboarding::system_prompt_for_user(&store, channel, user_id)?;
+
+    // Onboarding runs within the user's role, but may always write the
+    // profile files in their own directory
+    let config = crate::config::Config::load()?;
+    let profile_files =
+        backends::path_rule_pattern(&onboarding::user_dir(&store, channel, user_id)?);
     let options = backends::QueryOptions {
         system_prompt: Some(system_prompt),
-        skip_permissions: true,
-        ..Default::default()
+        allowed_tools: ["Write", "Edit"]
+            .map(|tool| format!("{}({})", tool, profile_files))
+            .to_vec(),
+        ..QueryOptions::for_role(store.role(&config, channel, user_id))
     };
 
     let (response, _) = backends::query_with_options(message, options).await?;
@@ -937,10 +3004,10 @@ pub async fn handle_onboarding(channel: &str, user_id: &str, message: &str) -> R
 }
 
 /// Re-index memories for a user (called after Claude responds)
-pub fn reindex_user_memories(channel: &str, user_id: &str) {
+pub fn reindex_user_memories(store: &PairingStore, channel: &str, user_id: &str) {
     match MemoryIndex::open() {
         Ok(mut index) => {
-            if let Err(e) = index.index_user_memories(channel, user_id) {
+            if let Err(e) = index.index_user_memories(store, channel, user_id) {
                 warn!(
                     "Failed to re-index memories for {}:{}: {}",
                     channel, user_id, e
@@ -973,9 +3040,597 @@ pub const SUPPORTED_CHANNELS: &[ChannelInfo] = &[
         name: "slack",
         display_name: "Slack",
     },
+    ChannelInfo {
+        name: "irc",
+        display_name: "IRC",
+    },
 ];
 
 /// Get channel info by name
 pub fn get_channel_info(name: &str) -> Option<&'static ChannelInfo> {
     SUPPORTED_CHANNELS.iter().find(|c| c.name == name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_text_with_images() {
+        let images = vec![PathBuf::from("/tmp/a.jpg"), PathBuf::from("/tmp/b.png")];
+        let docs = vec![PathBuf::from("/inbox/report.pdf")];
+
+        assert_eq!(
+            build_text_with_images("look", &images, &[]),
+            "look\n\n@/tmp/a.jpg @/tmp/b.png"
+        );
+        assert_eq!(
+            build_text_with_images("", &[], &docs),
+            "Files sent by the user (saved in their inbox):\n- /inbox/report.pdf"
+        );
+        assert!(
+            build_text_with_images("summarize", &images[..1], &docs)
+                .starts_with("summarize\n\n@/tmp/a.jpg\n\nFiles sent")
+        );
+    }
+
+    #[test]
+    fn test_split_confirmation() {
+        assert_eq!(
+            split_confirmation("Delete all 12 files in notes/?\n\n[[confirm]]"),
+            ("Delete all 12 files in notes/?".to_string(), true)
+        );
+        assert_eq!(split_confirmation("Done!"), ("Done!".to_string(), false));
+    }
+
+    #[test]
+    fn test_add_reply_context() {
+        assert_eq!(
+            add_reply_context("Why?", Some("you"), "It will rain.\nTake an umbrella."),
+            "Quoting you:\n> It will rain.\n> Take an umbrella.\n\nWhy?"
+        );
+        assert_eq!(
+            add_reply_context("Agreed", None, "Lunch at noon"),
+            "Quoting an earlier message:\n> Lunch at noon\n\nAgreed"
+        );
+        assert_eq!(add_reply_context("Hi", Some("Ana"), "  "), "Hi");
+    }
+
+    #[tokio::test]
+    async fn test_process_edit_replaces_pending_message() {
+        let manager = UserTaskManager::with_policy(
+            InterruptionPolicy::Abort,
+            Duration::from_millis(DEFAULT_DEBOUNCE_MS),
+        );
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        manager
+            .process_message_with_id(
+                "test:1".to_string(),
+                Some("42".to_string()),
+                "Remind me at 5".to_string(),
+                move |messages, _| async move {
+                    let _ = tx.send(messages);
+                },
+            )
+            .await;
+        let replaced = manager
+            .process_edit(
+                "test:1".to_string(),
+                "42",
+                "Remind me at 6".to_string(),
+                |_, _| async {},
+            )
+            .await;
+        assert!(replaced);
+        assert!(
+            !manager
+                .process_edit("test:1".to_string(), "7", "Hi".to_string(), |_, _| async {})
+                .await
+        );
+
+        assert_eq!(rx.await.unwrap(), vec!["Remind me at 6".to_string()]);
+    }
+
+    /// Send "first", then "second" while "first" is being answered, and
+    /// collect what each answer was given
+    async fn interrupt(policy: InterruptionPolicy) -> Vec<Vec<String>> {
+        let manager = UserTaskManager::with_policy(policy, Duration::from_millis(10));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let done = done_tx.clone();
+        manager
+            .process_message(
+                "test:1".to_string(),
+                "first".to_string(),
+                move |mut messages, steering| async move {
+                    let _ = started_tx.send(());
+                    tokio::select! {
+                        _ = release_rx => {}
+                        () = steering.interrupted() => {}
+                    }
+                    messages.extend(steering.take().await);
+                    let _ = done.send(messages);
+                },
+            )
+            .await;
+        started_rx.await.unwrap();
+        manager
+            .process_message(
+                "test:1".to_string(),
+                "second".to_string(),
+                move |messages, _| async move {
+                    let _ = done_tx.send(messages);
+                },
+            )
+            .await;
+        let _ = release_tx.send(());
+
+        let mut answers = Vec::new();
+        while let Ok(Some(messages)) =
+            tokio::time::timeout(Duration::from_millis(200), done_rx.recv()).await
+        {
+            answers.push(messages);
+        }
+        answers
+    }
+
+    #[tokio::test]
+    async fn test_interruption_policies() {
+        assert_eq!(
+            interrupt(InterruptionPolicy::Abort).await,
+            vec![vec!["first".to_string(), "second".to_string()]]
+        );
+        assert_eq!(
+            interrupt(InterruptionPolicy::Queue).await,
+            vec![vec!["first".to_string()], vec!["second".to_string()]]
+        );
+        assert_eq!(
+            interrupt(InterruptionPolicy::Steer).await,
+            vec![vec!["first".to_string(), "second".to_string()]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_in_order() {
+        let manager = UserTaskManager::with_policy(InterruptionPolicy::Queue, Duration::ZERO);
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        // The first message takes longest to prepare but still comes first
+        for (i, delay) in [(1, 50), (2, 0), (3, 10)] {
+            let done_tx = done_tx.clone();
+            manager.run_in_order("plugin:alice".to_string(), async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                let _ = done_tx.send(i);
+            });
+        }
+        for expected in 1..=3 {
+            assert_eq!(done_rx.recv().await, Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_steer_interrupts_running_answer() {
+        let manager =
+            UserTaskManager::with_policy(InterruptionPolicy::Steer, Duration::from_millis(10));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+
+        manager
+            .process_message(
+                "test:1".to_string(),
+                "first".to_string(),
+                move |_, steering| async move {
+                    let _ = started_tx.send(());
+                    // Stands in for a backend that would run for a long time
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+                        () = steering.interrupted() => {}
+                    }
+                    let _ = done_tx.send(steering.take().await);
+                },
+            )
+            .await;
+        started_rx.await.unwrap();
+        manager
+            .process_message("test:1".to_string(), "second".to_string(), |_, _| async {})
+            .await;
+
+        let follow_ups = tokio::time::timeout(Duration::from_millis(500), done_rx)
+            .await
+            .expect("the answer wasn't interrupted")
+            .unwrap();
+        assert_eq!(follow_ups, vec!["second".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_edit_while_queued_answer_runs() {
+        let manager =
+            UserTaskManager::with_policy(InterruptionPolicy::Queue, Duration::from_millis(10));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let done = done_tx.clone();
+        manager
+            .process_message_with_id(
+                "test:1".to_string(),
+                Some("7".to_string()),
+                "Remind me at 5".to_string(),
+                move |messages, _| async move {
+                    let _ = started_tx.send(());
+                    let _ = release_rx.await;
+                    let _ = done.send(messages);
+                },
+            )
+            .await;
+        started_rx.await.unwrap();
+
+        // The running answer isn't cut short; the edit is answered after it
+        let edited = manager
+            .process_edit(
+                "test:1".to_string(),
+                "7",
+                "Remind me at 6".to_string(),
+                move |messages, _| async move {
+                    let _ = done_tx.send(messages);
+                },
+            )
+            .await;
+        assert!(edited);
+        let _ = release_tx.send(());
+
+        assert_eq!(
+            done_rx.recv().await.unwrap(),
+            vec!["Remind me at 5".to_string()]
+        );
+        assert_eq!(
+            done_rx.recv().await.unwrap(),
+            vec!["Remind me at 6".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_users_overview_pending() {
+        let mut store = PairingStore::default();
+        store.pending.push(pairing::PendingRequest {
+            code: "ABCD2345".to_string(),
+            channel: "signal".to_string(),
+            user_id: "+15550001111".to_string(),
+            username: None,
+            display_name: Some("Alex".to_string()),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        });
+
+        let CommandResult::Buttons(text, buttons) = users_overview(&mut store) else {
+            panic!("expected buttons for the pending request");
+        };
+        assert!(text.starts_with("Approved (0):"));
+        assert!(text.contains("• Alex on Signal: /approve ABCD2345"));
+        assert_eq!(
+            buttons,
+            vec![vec![
+                Button::new("Approve Alex", "pair:approve:ABCD2345"),
+                Button::new("Deny", "pair:deny:ABCD2345"),
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_location_from_map_link() {
+        let location =
+            SharedLocation::from_map_link("https://maps.google.com/maps?q=52.370216%2C4.895168")
+                .unwrap();
+        assert_eq!(location, SharedLocation::new(52.370216, 4.895168));
+
+        assert_eq!(
+            SharedLocation::from_map_link("I'm here: geo:-33.8688,151.2093;u=35"),
+            Some(SharedLocation::new(-33.8688, 151.2093))
+        );
+        assert!(SharedLocation::from_map_link("https://maps.google.com/maps?q=coffee").is_none());
+        assert!(SharedLocation::from_map_link("geo:95.0,10.0").is_none());
+        assert!(SharedLocation::from_map_link("meet at 52.37,4.89").is_none());
+    }
+
+    #[test]
+    fn test_add_shared_context() {
+        let venue = SharedLocation {
+            title: Some("Blue Bottle".to_string()),
+            address: Some("1 Ferry Building".to_string()),
+            ..SharedLocation::new(37.7956, -122.3934)
+        };
+        let contact = SharedContact {
+            name: "Jane Doe".to_string(),
+            phone_numbers: vec!["+15551234567".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            add_shared_context("", Some(&venue), &[contact]),
+            "[Location shared by the user]\n\
+             Place: Blue Bottle\n\
+             Address: 1 Ferry Building\n\
+             Coordinates: 37.795600, -122.393400\n\
+             Map: https://www.openstreetmap.org/?mlat=37.795600&mlon=-122.393400\n\n\
+             [Contact card shared by the user]\n\
+             Name: Jane Doe\n\
+             Phone: +15551234567"
+        );
+        assert_eq!(add_shared_context("hi", None, &[]), "hi");
+    }
+
+    #[tokio::test]
+    async fn test_stop() {
+        let manager =
+            UserTaskManager::with_policy(InterruptionPolicy::Queue, Duration::from_millis(10));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+
+        let done = done_tx.clone();
+        manager
+            .process_message(
+                "slack:U1:1700000000.000100".to_string(),
+                "long task".to_string(),
+                move |_, _| async move {
+                    let _ = started_tx.send(());
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    let _ = done.send("long task".to_string());
+                },
+            )
+            .await;
+        started_rx.await.unwrap();
+        manager
+            .process_message(
+                "slack:U10".to_string(),
+                "someone else".to_string(),
+                move |_, _| async move {
+                    let _ = done_tx.send("someone else".to_string());
+                },
+            )
+            .await;
+
+        assert!(manager.stop("slack", "U1").await);
+        assert!(!manager.stop("slack", "U1").await);
+
+        let answer = tokio::time::timeout(Duration::from_millis(200), done_rx.recv()).await;
+        assert_eq!(answer.unwrap().as_deref(), Some("someone else"));
+        // The stopped task was dropped without answering
+        assert_eq!(done_rx.recv().await, None);
+    }
+
+    #[test]
+    fn test_split_message_paragraphs() {
+        let text = "First paragraph.\n\nSecond paragraph.\n\nThird paragraph.";
+        assert_eq!(split_message(text, 100), vec![text.to_string()]);
+        assert_eq!(
+            split_message(text, 40),
+            vec![
+                "First paragraph.\n\nSecond paragraph.".to_string(),
+                "Third paragraph.".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_message_code_fence() {
+        let code: Vec<String> = (0..20).map(|i| format!("let x{} = {};", i, i)).collect();
+        let text = format!("Here you go:\n\n```rust\n{}\n```\n\nDone.", code.join("\n"));
+        let parts = split_message(&text, 120);
+
+        assert!(parts.len() > 2);
+        for part in &parts {
+            assert!(part.chars().count() <= 120, "part too long: {:?}", part);
+            assert_eq!(
+                part.matches("```").count() % 2,
+                0,
+                "unbalanced fence: {:?}",
+                part
+            );
+        }
+        assert!(parts.iter().any(|p| p.starts_with("```rust\nlet x")));
+        assert!(parts.last().unwrap().ends_with("```\n\nDone."));
+    }
+
+    #[test]
+    fn test_split_message_long_line() {
+        let text = "word ".repeat(50);
+        let parts = split_message(&text, 32);
+        assert!(parts.iter().all(|p| p.chars().count() <= 32));
+        assert_eq!(parts.join(" ").split_whitespace().count(), 50);
+    }
+
+    #[test]
+    fn test_split_message_utf16() {
+        // Each emoji is two UTF-16 code units
+        let text = "\u{1F600}".repeat(30);
+        let parts = split_message(&text, 20);
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| text_len(p) <= 20));
+        assert_eq!(parts.concat(), text);
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("Q3 report.pdf"), "Q3 report.pdf");
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("C:\\docs\\notes.txt"), "notes.txt");
+        assert_eq!(sanitize_file_name("..hidden"), "hidden");
+        assert_eq!(sanitize_file_name("a:b*c?.csv"), "a_b_c_.csv");
+        assert_eq!(sanitize_file_name(""), "file");
+    }
+
+    #[test]
+    fn test_guest_in_slack_thread() {
+        let config = crate::config::Config::default();
+        let mut store = PairingStore::default();
+        store
+            .roles
+            .insert("slack:U1".to_string(), "guest".to_string());
+        store
+            .sessions
+            .insert("slack:U1:1700000000.000100".to_string(), "abc".to_string());
+
+        // The thread keeps its own conversation but the user's role
+        let (session_key, options) =
+            user_query_options(&store, &config, "slack", "U1", "U1:1700000000.000100");
+        assert_eq!(session_key, "slack:U1:1700000000.000100");
+        assert_eq!(options.resume_session.as_deref(), Some("abc"));
+        assert!(!options.skip_permissions);
+
+        let (_, options) = user_query_options(&store, &config, "slack", "U2", "U2:1");
+        assert!(options.skip_permissions);
+    }
+
+    #[test]
+    fn test_blocked_member_in_group() {
+        let group = pairing::group_user_id("-100123");
+        let mut store = PairingStore::default();
+        store
+            .approved
+            .insert("telegram".to_string(), vec![group.clone()]);
+        store
+            .blocked
+            .insert("telegram".to_string(), vec!["42".to_string()]);
+
+        let sender = SenderInfo::new(None, Some("Book club".to_string())).with_member(
+            "42".to_string(),
+            SenderInfo::new(Some("mallory".to_string()), None),
+        );
+        let settings = crate::config::ChannelSettings::default();
+        let action = determine_action_with(
+            &settings,
+            "telegram",
+            &group,
+            "hello",
+            &[],
+            &mut store,
+            sender,
+        )
+        .unwrap();
+        assert!(matches!(action, MessageAction::Ignore));
+    }
+
+    #[test]
+    fn test_command_role_check() {
+        let mut store = PairingStore::default();
+        let guest = Role::guest();
+        let refused = |store: &mut PairingStore, text: &str| match process_command_as(
+            store, &guest, "telegram", "1", text, true,
+        )
+        .unwrap()
+        {
+            CommandResult::Response(response) => response,
+            _ => panic!("{:?} wasn't refused", text),
+        };
+        assert_eq!(
+            refused(&mut store, "/cron\tadd every 1h ping"),
+            "Sorry, /cron isn't available to you."
+        );
+        assert_eq!(
+            refused(&mut store, "/cronadd every 1h ping"),
+            "Unknown command /cronadd. Try /cron help."
+        );
+        assert_eq!(
+            refused(&mut store, "/cronlist"),
+            "Unknown command /cronlist. Try /cron help."
+        );
+    }
+
+    #[test]
+    fn test_group_buttons_for_one_member() {
+        let settings = crate::config::ChannelSettings::default();
+        let group = pairing::group_user_id("-100123");
+        let mut store = PairingStore::default();
+        store
+            .approved
+            .insert("telegram".to_string(), vec![group.clone()]);
+        store
+            .blocked
+            .insert("telegram".to_string(), vec!["666".to_string()]);
+
+        let buttons = restrict_to_member(&confirmation_buttons(), Some("42"));
+        let confirm = buttons[0][0].action.clone();
+        assert_eq!(confirm, "confirm:yes@42");
+        let tap = |store: &mut PairingStore, member: &str, action: &str| {
+            let sender =
+                SenderInfo::default().with_member(member.to_string(), SenderInfo::default());
+            determine_button_action_with(&settings, "telegram", &group, action, store, sender)
+                .unwrap()
+        };
+
+        assert!(matches!(
+            tap(&mut store, "42", &confirm),
+            MessageAction::QueryClaude { .. }
+        ));
+        assert!(matches!(
+            tap(&mut store, "7", &confirm),
+            MessageAction::Ignore
+        ));
+        assert!(matches!(
+            tap(&mut store, "7", "confirm:yes"),
+            MessageAction::Ignore
+        ));
+        assert!(matches!(
+            tap(&mut store, "7", "cron:delete:abc"),
+            MessageAction::Ignore
+        ));
+        assert!(matches!(
+            tap(&mut store, "666", "cancel"),
+            MessageAction::Ignore
+        ));
+        assert!(matches!(
+            tap(&mut store, "7", "cancel"),
+            MessageAction::SendResponse(_)
+        ));
+    }
+
+    #[test]
+    fn test_invite_token() {
+        assert_eq!(invite_token("/start 3f2a9c"), Some("3f2a9c"));
+        assert_eq!(invite_token("3f2a9c"), Some("3f2a9c"));
+        assert_eq!(invite_token("/start"), None);
+        assert_eq!(invite_token("hello there"), None);
+        assert_eq!(invite_token("/new"), None);
+    }
+
+    #[test]
+    fn test_access_lists() {
+        let settings = crate::config::ChannelSettings {
+            allow: vec![
+                "123".to_string(),
+                "@Alice".to_string(),
+                "*@example.com".to_string(),
+            ],
+            block: vec!["team:T999".to_string(), "@Mallory".to_string()],
+            ..Default::default()
+        };
+
+        // Usernames can be taken over, so only the id lets Alice in
+        let alice = SenderInfo::new(Some("alice".to_string()), None);
+        assert!(settings.is_allowed(&alice.stable_identifiers("123")));
+        assert!(!settings.is_allowed(&alice.stable_identifiers("321")));
+
+        // ...but they still keep people out
+        let mallory = SenderInfo::new(Some("mallory".to_string()), None);
+        assert!(settings.is_blocked(&mallory.identifiers("666")));
+
+        let mut bob = SenderInfo::new(Some("bob".to_string()), None);
+        assert!(!settings.is_allowed(&bob.stable_identifiers("456")));
+        bob.aliases.push("bob@example.com".to_string());
+        assert!(settings.is_allowed(&bob.stable_identifiers("456")));
+        assert!(!settings.is_blocked(&bob.identifiers("456")));
+
+        // A domain entry doesn't match an id that merely ends the same way
+        let carol = SenderInfo {
+            aliases: vec!["carol@notexample.com".to_string(), "team:t999".to_string()],
+            ..Default::default()
+        };
+        assert!(!settings.is_allowed(&carol.identifiers("789")));
+        assert!(settings.is_blocked(&carol.identifiers("789")));
+    }
+}
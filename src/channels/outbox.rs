@@ -0,0 +1,595 @@
+//! Durable queue for outgoing messages.
+//!
+//! Messages are kept in `internal/outbox.json` until a channel accepts them,
+//! and retried with backoff when sending fails. Ones that still fail after
+//! `MAX_ATTEMPTS` are moved to the failed list and reported to the admins.
+//! Long messages remember how many of their parts went out, so a retry
+//! picks up where the last attempt stopped, buttons included.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use super::registry::ChannelRegistry;
+use super::{
+    BUTTONS_PROMPT, Button, Channel, extract_media_attachments, remove_file_path_lines, reply_parts,
+};
+use crate::config::{self, Config};
+
+/// Attempts before a message is given up on
+const MAX_ATTEMPTS: u32 = 10;
+
+/// Wait before the first retry; doubles with every attempt after that
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest wait between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How often the outbox is checked for messages due a retry
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Failed deliveries kept around for inspection
+const MAX_FAILED: usize = 100;
+
+/// Serializes reads and writes of the outbox file
+static FILE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Ids of the messages being sent right now, so no two delivery passes send
+/// the same one. Claimed and released while holding `FILE_LOCK`.
+static IN_FLIGHT: std::sync::Mutex<Option<HashSet<String>>> = std::sync::Mutex::new(None);
+
+/// A message waiting to be delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundMessage {
+    pub id: String,
+    pub channel: String,
+    pub user_id: String,
+    /// Conversation to send to instead of the user's direct messages
+    /// (see `Channel::reply_address`)
+    #[serde(default)]
+    pub address: Option<String>,
+    pub text: String,
+    #[serde(default)]
+    pub attachments: Vec<PathBuf>,
+    /// Buttons under the last part
+    #[serde(default)]
+    pub buttons: Vec<Vec<Button>>,
+    /// Parts of a long message already delivered
+    #[serde(default)]
+    pub parts_sent: usize,
+    pub created_at: u64, // Unix timestamp
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_attempt_at: u64, // Unix timestamp
+    pub last_error: Option<String>,
+}
+
+impl OutboundMessage {
+    /// Queue an assistant response; media files it mentions become attachments
+    pub fn new(channel: &str, user_id: &str, response: &str) -> Self {
+        let attachments = extract_media_attachments(response);
+        let text = if attachments.is_empty() {
+            response.to_string()
+        } else {
+            remove_file_path_lines(response)
+        };
+        let now = now_timestamp();
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            channel: channel.to_string(),
+            user_id: user_id.to_string(),
+            address: None,
+            text,
+            attachments,
+            buttons: Vec::new(),
+            parts_sent: 0,
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+        }
+    }
+
+    /// Send to a conversation rather than the user's direct messages
+    pub fn with_address(mut self, address: Option<String>) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Show buttons under the message
+    pub fn with_buttons(mut self, buttons: Vec<Vec<Button>>) -> Self {
+        self.buttons = buttons;
+        self
+    }
+
+    /// Carry on after the parts an earlier attempt already delivered
+    pub fn with_parts_sent(mut self, parts_sent: usize) -> Self {
+        self.parts_sent = parts_sent;
+        self
+    }
+
+    /// Same recipient and content
+    fn is_duplicate_of(&self, other: &OutboundMessage) -> bool {
+        self.channel == other.channel
+            && self.user_id == other.user_id
+            && self.address == other.address
+            && self.text == other.text
+            && self.attachments == other.attachments
+    }
+}
+
+/// Messages waiting to go out, and those that never made it
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Outbox {
+    #[serde(default)]
+    pub pending: Vec<OutboundMessage>,
+    #[serde(default)]
+    pub failed: Vec<OutboundMessage>,
+}
+
+impl Outbox {
+    fn path() -> Result<PathBuf> {
+        Ok(config::paths()?.internal_dir.join("outbox.json"))
+    }
+
+    /// Load the outbox from disk
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read outbox: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse outbox: {:?}", path))
+    }
+
+    /// Save the outbox to disk
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Add a message unless the same one is already waiting.
+    /// Returns false for duplicates.
+    fn push(&mut self, message: OutboundMessage) -> bool {
+        if self.pending.iter().any(|m| m.is_duplicate_of(&message)) {
+            return false;
+        }
+        self.pending.push(message);
+        true
+    }
+
+    /// Record a failed attempt, after `parts_sent` parts went out, and
+    /// schedule the next one. Returns the message if it has now been given up on.
+    fn record_failure(
+        &mut self,
+        id: &str,
+        error: String,
+        parts_sent: usize,
+        now: u64,
+    ) -> Option<OutboundMessage> {
+        let idx = self.pending.iter().position(|m| m.id == id)?;
+        let message = &mut self.pending[idx];
+        message.attempts += 1;
+        message.last_error = Some(error);
+        message.parts_sent = parts_sent;
+
+        if message.attempts < MAX_ATTEMPTS {
+            message.next_attempt_at = now + retry_delay(message.attempts).as_secs();
+            return None;
+        }
+
+        let message = self.pending.remove(idx);
+        self.failed.push(message.clone());
+        let excess = self.failed.len().saturating_sub(MAX_FAILED);
+        self.failed.drain(..excess);
+        Some(message)
+    }
+}
+
+/// How long to wait after the given number of failed attempts
+fn retry_delay(attempts: u32) -> Duration {
+    let factor = 1u32 << attempts.saturating_sub(1).min(16);
+    FIRST_RETRY_DELAY
+        .saturating_mul(factor)
+        .min(MAX_RETRY_DELAY)
+}
+
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// ============================================================================
+// Delivery
+// ============================================================================
+
+/// Queue a message to be retried later
+pub async fn enqueue(message: OutboundMessage) -> Result<()> {
+    let _guard = FILE_LOCK.lock().await;
+    let mut outbox = Outbox::load()?;
+    if outbox.push(message) {
+        outbox.save()?;
+    } else {
+        debug!("Skipping duplicate outbound message");
+    }
+    Ok(())
+}
+
+/// Queue a message and try to deliver it right away.
+/// A failed attempt leaves it queued for retry rather than returning an error.
+pub async fn send(registry: &ChannelRegistry, message: OutboundMessage) -> Result<()> {
+    enqueue(message).await?;
+    deliver_due(registry).await
+}
+
+/// Keep retrying queued messages as they come due
+pub async fn run(registry: std::sync::Arc<ChannelRegistry>) {
+    loop {
+        if let Err(e) = deliver_due(&registry).await {
+            warn!("Failed to process outbox: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Claim messages for this delivery pass, skipping ones another pass is
+/// already sending. Returns the ids claimed.
+fn claim(messages: &[OutboundMessage]) -> HashSet<String> {
+    let Ok(mut in_flight) = IN_FLIGHT.lock() else {
+        return HashSet::new();
+    };
+    let in_flight = in_flight.get_or_insert_with(HashSet::new);
+    messages
+        .iter()
+        .filter(|m| in_flight.insert(m.id.clone()))
+        .map(|m| m.id.clone())
+        .collect()
+}
+
+/// Let other delivery passes pick these messages up again
+fn release(ids: &HashSet<String>) {
+    if let Ok(mut in_flight) = IN_FLIGHT.lock()
+        && let Some(in_flight) = in_flight.as_mut()
+    {
+        in_flight.retain(|id| !ids.contains(id));
+    }
+}
+
+/// Try every queued message that's due
+async fn deliver_due(registry: &ChannelRegistry) -> Result<()> {
+    let now = now_timestamp();
+    let (due, claimed) = {
+        let _guard = FILE_LOCK.lock().await;
+        let due: Vec<OutboundMessage> = Outbox::load()?
+            .pending
+            .into_iter()
+            .filter(|m| m.next_attempt_at <= now)
+            .collect();
+        let claimed = claim(&due);
+        let due: Vec<OutboundMessage> = due
+            .into_iter()
+            .filter(|m| claimed.contains(&m.id))
+            .collect();
+        (due, claimed)
+    };
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    // No locks are held while sending, so new messages can be queued meanwhile
+    let mut results = Vec::new();
+    for message in &due {
+        let mut parts_sent = message.parts_sent;
+        let result = deliver(registry, message, &mut parts_sent).await;
+        results.push((message.id.clone(), parts_sent, result));
+    }
+
+    let given_up = {
+        let _guard = FILE_LOCK.lock().await;
+        let saved = Outbox::load().and_then(|mut outbox| {
+            let mut given_up = Vec::new();
+            for (id, parts_sent, result) in results {
+                match result {
+                    Ok(()) => outbox.pending.retain(|m| m.id != id),
+                    Err(e) => {
+                        let error = format!("{:#}", e);
+                        if let Some(message) = outbox.record_failure(&id, error, parts_sent, now) {
+                            given_up.push(message);
+                        }
+                    }
+                }
+            }
+            outbox.save()?;
+            Ok(given_up)
+        });
+        release(&claimed);
+        saved?
+    };
+
+    for message in given_up {
+        report_failure(registry, &message).await;
+    }
+    Ok(())
+}
+
+/// Send one queued message through its channel, counting the parts of a
+/// long one in `parts_sent` as they go out
+async fn deliver(
+    registry: &ChannelRegistry,
+    message: &OutboundMessage,
+    parts_sent: &mut usize,
+) -> Result<()> {
+    let address = message.address.as_deref().unwrap_or(&message.user_id);
+    let channel = registry.channel_for(&message.channel, address)?;
+
+    // Attachments may have been cleaned up since the message was queued
+    let attachments: Vec<PathBuf> = message
+        .attachments
+        .iter()
+        .filter(|p| p.exists())
+        .cloned()
+        .collect();
+
+    let result = send_remaining(
+        channel.as_ref(),
+        &message.text,
+        &attachments,
+        &message.buttons,
+        parts_sent,
+    )
+    .await;
+
+    match &result {
+        Ok(()) if message.attempts > 0 => info!(
+            "Delivered queued message to {}:{} after {} failed attempt(s)",
+            message.channel, address, message.attempts
+        ),
+        Ok(()) => {}
+        Err(e) => warn!(
+            "Failed to deliver message to {}:{}: {:#}",
+            message.channel, address, e
+        ),
+    }
+    result
+}
+
+/// Send the parts of a message that haven't gone out yet, counting them in
+/// `parts_sent`, with buttons under the last one. Text sent with attachments,
+/// or as a single document, is one part.
+pub async fn send_remaining(
+    channel: &dyn Channel,
+    text: &str,
+    attachments: &[PathBuf],
+    buttons: &[Vec<Button>],
+    parts_sent: &mut usize,
+) -> Result<()> {
+    let parts = if attachments.is_empty() {
+        reply_parts(channel.max_message_length(), text)
+    } else {
+        None
+    };
+    let Some(parts) = parts else {
+        if *parts_sent == 0 {
+            if attachments.is_empty() {
+                channel.send_reply(text, &[]).await?;
+            } else {
+                channel
+                    .send_message_with_attachments(text, attachments)
+                    .await?;
+            }
+            *parts_sent = 1;
+        }
+        if !buttons.is_empty() {
+            channel
+                .send_message_with_buttons(BUTTONS_PROMPT, buttons)
+                .await?;
+        }
+        return Ok(());
+    };
+
+    let last = parts.len().saturating_sub(1);
+    for (i, part) in parts.iter().enumerate().skip(*parts_sent) {
+        if i == last && !buttons.is_empty() {
+            channel.send_message_with_buttons(part, buttons).await?;
+        } else {
+            channel.send_message(part).await?;
+        }
+        *parts_sent += 1;
+    }
+    Ok(())
+}
+
+/// What the admins are told about a message that couldn't be delivered.
+/// The message itself is private to its recipient, so only its size is given.
+fn failure_report(message: &OutboundMessage) -> String {
+    let mut report = format!(
+        "I couldn't deliver a message to {} on {} after {} attempts.\n\nLast error: {}\n\nIt was {} characters long",
+        message.user_id,
+        message.channel,
+        message.attempts,
+        message.last_error.as_deref().unwrap_or("unknown"),
+        message.text.chars().count(),
+    );
+    if !message.attachments.is_empty() {
+        report.push_str(&format!(
+            " with {} attachment(s)",
+            message.attachments.len()
+        ));
+    }
+    report.push('.');
+    report
+}
+
+/// Tell the admins about a message that couldn't be delivered
+async fn report_failure(registry: &ChannelRegistry, message: &OutboundMessage) {
+    error!(
+        "Giving up on message to {}:{} after {} attempts",
+        message.channel, message.user_id, message.attempts
+    );
+
+    let admins = Config::load().map(|c| c.admins).unwrap_or_default();
+    let report = failure_report(message);
+
+    for admin in admins {
+        let Some((channel, user_id)) = admin.split_once(':') else {
+            continue;
+        };
+        // Sent directly: queueing the report could fail the same way
+        if let Err(e) = registry.send(channel, user_id, &report).await {
+            warn!(
+                "Failed to tell admin {} about a failed delivery: {}",
+                admin, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::TypingGuard;
+    use async_trait::async_trait;
+
+    /// Records what it sends and fails once, on the given message
+    struct FlakyChannel {
+        sent: std::sync::Mutex<Vec<String>>,
+        fail_on: std::sync::Mutex<Option<usize>>,
+    }
+
+    #[async_trait]
+    impl Channel for FlakyChannel {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn display_name(&self) -> &'static str {
+            "Test"
+        }
+
+        async fn send_message(&self, message: &str) -> Result<()> {
+            let mut sent = self.sent.lock().unwrap();
+            if *self.fail_on.lock().unwrap() == Some(sent.len()) {
+                *self.fail_on.lock().unwrap() = None;
+                anyhow::bail!("offline");
+            }
+            sent.push(message.to_string());
+            Ok(())
+        }
+
+        async fn send_message_with_buttons(
+            &self,
+            message: &str,
+            buttons: &[Vec<Button>],
+        ) -> Result<()> {
+            self.send_message(&format!("{} [{} button row(s)]", message, buttons.len()))
+                .await
+        }
+
+        fn max_message_length(&self) -> Option<usize> {
+            Some(12)
+        }
+
+        fn start_typing(&self) -> TypingGuard {
+            TypingGuard::noop()
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(2), Duration::from_secs(60));
+        assert_eq!(retry_delay(4), Duration::from_secs(240));
+        assert_eq!(retry_delay(9), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_outbox_dedupes_and_gives_up() {
+        let mut outbox = Outbox::default();
+        let message = OutboundMessage::new("telegram", "1", "Good morning!");
+        let id = message.id.clone();
+
+        assert!(outbox.push(message));
+        assert!(!outbox.push(OutboundMessage::new("telegram", "1", "Good morning!")));
+        assert!(outbox.push(OutboundMessage::new("signal", "1", "Good morning!")));
+
+        for attempt in 1..MAX_ATTEMPTS {
+            let parts_sent = attempt as usize;
+            assert!(
+                outbox
+                    .record_failure(&id, "offline".into(), parts_sent, 0)
+                    .is_none()
+            );
+            assert_eq!(outbox.pending[0].attempts, attempt);
+            assert_eq!(outbox.pending[0].parts_sent, parts_sent);
+        }
+        let failed = outbox.record_failure(&id, "offline".into(), 0, 0).unwrap();
+        assert_eq!(failed.attempts, MAX_ATTEMPTS);
+        assert_eq!(outbox.pending.len(), 1);
+        assert_eq!(outbox.failed.len(), 1);
+
+        let report = failure_report(&failed);
+        assert!(report.contains("13 characters"));
+        assert!(!report.contains("Good morning"));
+    }
+
+    #[test]
+    fn test_claim() {
+        let first = OutboundMessage::new("telegram", "1", "one");
+        let second = OutboundMessage::new("telegram", "1", "two");
+
+        let claimed = claim(std::slice::from_ref(&first));
+        assert!(claimed.contains(&first.id));
+
+        // Another pass only gets what isn't being sent already
+        let both = [first.clone(), second.clone()];
+        let others = claim(&both);
+        assert_eq!(others, HashSet::from([second.id.clone()]));
+
+        release(&claimed);
+        release(&others);
+        assert_eq!(claim(&both).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_remaining_resumes() {
+        let channel = FlakyChannel {
+            sent: std::sync::Mutex::new(Vec::new()),
+            fail_on: std::sync::Mutex::new(Some(1)),
+        };
+        let buttons = vec![vec![Button::new("Confirm", "confirm:yes")]];
+        let text = "First part.\n\nSecond part.";
+
+        let mut parts_sent = 0;
+        assert!(
+            send_remaining(&channel, text, &[], &buttons, &mut parts_sent)
+                .await
+                .is_err()
+        );
+        assert_eq!(parts_sent, 1);
+
+        // The retry sends only what's missing, buttons included
+        send_remaining(&channel, text, &[], &buttons, &mut parts_sent)
+            .await
+            .unwrap();
+        assert_eq!(parts_sent, 2);
+        assert_eq!(
+            *channel.sent.lock().unwrap(),
+            ["First part.", "Second part. [1 button row(s)]"]
+        );
+    }
+}
@@ -0,0 +1,674 @@
+//! External channel plugins speaking JSON-RPC 2.0 over stdio.
+//!
+//! A plugin is an executable declared in `config.toml` under `[[channels.plugins]]`.
+//! Cica spawns it and exchanges newline-delimited JSON-RPC messages on its
+//! stdin/stdout. Anything the plugin writes to stderr is logged.
+//!
+//! Plugin → Cica (notification):
+//! - `message` `{user_id, username?, display_name?, text, attachments?: [path], conversation_id?}`
+//!   (`user_id` must not contain `:`; attachments must be in the directory
+//!   given to the plugin as `CICA_INBOX`)
+//!
+//! Cica → Plugin (requests, answered with any `result` or an `error`):
+//! - `send` `{user_id, conversation_id?, text}`
+//! - `send_with_attachments` `{user_id, conversation_id?, text, attachments: [path]}`
+//! - `typing` `{user_id, conversation_id?, active}`
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::{Mutex, oneshot};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info, warn};
+
+use super::registry::ChannelRegistry;
+use super::{
+    Channel, SUPPORTED_CHANNELS, SenderInfo, TypingGuard, UserTaskManager, build_text_with_images,
+    determine_action, execute_action, execute_claude_query, inbox_path, is_image_path,
+    is_trusted_sender, max_attachment_bytes, oversized_attachment_note,
+};
+use crate::config::PluginChannelConfig;
+use crate::pairing::PairingStore;
+
+/// How long to wait for the plugin to answer a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay before restarting a plugin that exited
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+// ============================================================================
+// JSON-RPC Client
+// ============================================================================
+
+/// Connection to a running plugin process
+pub struct PluginClient {
+    name: &'static str,
+    stdin: Mutex<ChildStdin>,
+    pending: Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>,
+    next_id: AtomicU64,
+}
+
+impl PluginClient {
+    /// Send a request and wait for the plugin's response
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(id, tx);
+
+        let line = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = self.write_line(&line).await {
+            self.pending.lock().await.remove(&id);
+            return Err(e);
+        }
+
+        match timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => bail!("Plugin {} exited before responding", self.name),
+            Err(_) => {
+                self.pending.lock().await.remove(&id);
+                bail!("Plugin {} did not respond to {} in time", self.name, method)
+            }
+        }
+    }
+
+    /// Write a single JSON-RPC message to the plugin's stdin
+    async fn write_line(&self, message: &Value) -> Result<()> {
+        let mut line = message.to_string();
+        line.push('\n');
+
+        let mut stdin = self.stdin.lock().await;
+        stdin
+            .write_all(line.as_bytes())
+            .await
+            .with_context(|| format!("Failed to write to plugin {}", self.name))?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Route a response from the plugin to the request waiting for it
+    async fn resolve(&self, id: u64, result: Result<Value>) {
+        if let Some(tx) = self.pending.lock().await.remove(&id) {
+            let _ = tx.send(result);
+        } else {
+            debug!("Plugin {} answered unknown request {}", self.name, id);
+        }
+    }
+
+    /// Fail every outstanding request (called when the plugin exits)
+    async fn fail_pending(&self) {
+        for (_, tx) in self.pending.lock().await.drain() {
+            let _ = tx.send(Err(anyhow!("Plugin {} exited", self.name)));
+        }
+    }
+}
+
+/// A line received from the plugin
+#[derive(Debug, Deserialize)]
+struct RpcMessage {
+    id: Option<Value>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    #[serde(default)]
+    code: i64,
+    message: String,
+}
+
+/// Parameters of an incoming `message` notification
+#[derive(Debug, Deserialize)]
+struct IncomingMessage {
+    user_id: String,
+    username: Option<String>,
+    display_name: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    attachments: Vec<PathBuf>,
+    conversation_id: Option<String>,
+}
+
+// ============================================================================
+// Channel Implementation
+// ============================================================================
+
+/// Channel backed by an external plugin
+pub struct PluginChannel {
+    client: Arc<PluginClient>,
+    display_name: &'static str,
+    user_id: String,
+    conversation_id: Option<String>,
+}
+
+impl PluginChannel {
+    pub fn new(
+        client: Arc<PluginClient>,
+        display_name: &'static str,
+        user_id: String,
+        conversation_id: Option<String>,
+    ) -> Self {
+        Self {
+            client,
+            display_name,
+            user_id,
+            conversation_id,
+        }
+    }
+
+    fn params(&self) -> serde_json::Map<String, Value> {
+        let mut params = serde_json::Map::new();
+        params.insert("user_id".to_string(), json!(self.user_id));
+        if let Some(conversation_id) = &self.conversation_id {
+            params.insert("conversation_id".to_string(), json!(conversation_id));
+        }
+        params
+    }
+}
+
+#[async_trait]
+impl Channel for PluginChannel {
+    fn name(&self) -> &'static str {
+        self.client.name
+    }
+
+    fn display_name(&self) -> &'static str {
+        self.display_name
+    }
+
+    async fn send_message(&self, message: &str) -> Result<()> {
+        let mut params = self.params();
+        params.insert("text".to_string(), json!(message));
+        self.client.request("send", Value::Object(params)).await?;
+        Ok(())
+    }
+
+    async fn send_message_with_attachments(
+        &self,
+        message: &str,
+        attachment_paths: &[PathBuf],
+    ) -> Result<()> {
+        if attachment_paths.is_empty() {
+            return self.send_message(message).await;
+        }
+
+        let mut params = self.params();
+        params.insert("text".to_string(), json!(message));
+        params.insert("attachments".to_string(), json!(attachment_paths));
+        self.client
+            .request("send_with_attachments", Value::Object(params))
+            .await?;
+        Ok(())
+    }
+
+    fn for_user(&self, user_id: &str) -> Option<Arc<dyn Channel>> {
+        Some(Arc::new(PluginChannel::new(
+            self.client.clone(),
+            self.display_name,
+            user_id.to_string(),
+            None,
+        )))
+    }
+
+    fn start_typing(&self) -> TypingGuard {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let client = self.client.clone();
+        let mut params = self.params();
+
+        tokio::spawn(async move {
+            params.insert("active".to_string(), json!(true));
+            if let Err(e) = client
+                .request("typing", Value::Object(params.clone()))
+                .await
+            {
+                debug!("Plugin typing indicator failed: {}", e);
+            }
+
+            // Wait for the guard to be dropped, then clear the indicator
+            let _ = cancel_rx.await;
+            params.insert("active".to_string(), json!(false));
+            let _ = client.request("typing", Value::Object(params)).await;
+        });
+
+        TypingGuard::new(cancel_tx)
+    }
+}
+
+// ============================================================================
+// Public API
+// ============================================================================
+
+/// Run an external channel plugin, restarting it whenever it exits
+pub async fn run(config: PluginChannelConfig, registry: Arc<ChannelRegistry>) -> Result<()> {
+    if config.name.is_empty() || config.name.contains(':') {
+        bail!("Invalid plugin channel name: {:?}", config.name);
+    }
+    if SUPPORTED_CHANNELS.iter().any(|c| c.name == config.name) {
+        bail!(
+            "Plugin channel name {:?} clashes with a built-in channel",
+            config.name
+        );
+    }
+
+    // Channel names are 'static throughout the crate; plugins are configured once per process
+    let name: &'static str = Box::leak(config.name.clone().into_boxed_str());
+    let display_name: &'static str = Box::leak(
+        config
+            .display_name
+            .clone()
+            .unwrap_or_else(|| config.name.clone())
+            .into_boxed_str(),
+    );
+
+    info!("Starting {} plugin: {}", display_name, config.command);
+
+    // Shared across restarts so pending batches survive a plugin crash
+    let task_manager = UserTaskManager::for_channel(name);
+
+    loop {
+        if let Err(e) =
+            run_plugin_process(&config, name, display_name, &task_manager, &registry).await
+        {
+            error!("{} plugin error: {:#}", display_name, e);
+        }
+
+        warn!(
+            "{} plugin exited, restarting in {}s...",
+            display_name,
+            RESTART_DELAY.as_secs()
+        );
+        sleep(RESTART_DELAY).await;
+    }
+}
+
+/// Spawn the plugin and process its output until it exits
+async fn run_plugin_process(
+    config: &PluginChannelConfig,
+    name: &'static str,
+    display_name: &'static str,
+    task_manager: &Arc<UserTaskManager>,
+    registry: &ChannelRegistry,
+) -> Result<()> {
+    let inbox = plugin_inbox(name)?;
+    let mut child = Command::new(&config.command)
+        .args(&config.args)
+        .envs(&config.env)
+        .env("CICA_INBOX", &inbox)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start plugin {}", config.command))?;
+
+    let stdin = child.stdin.take().context("Plugin stdin unavailable")?;
+    let stdout = child.stdout.take().context("Plugin stdout unavailable")?;
+
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                info!("[{}] {}", name, line);
+            }
+        });
+    }
+
+    let client = Arc::new(PluginClient {
+        name,
+        stdin: Mutex::new(stdin),
+        pending: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    });
+
+    let registry_client = client.clone();
+    registry.register(name, move |user_id| {
+        Some(Arc::new(PluginChannel::new(
+            registry_client.clone(),
+            display_name,
+            user_id.to_string(),
+            None,
+        )) as Arc<dyn Channel>)
+    });
+
+    info!("{} plugin running. Listening for messages...", display_name);
+
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Some(incoming) = route_line(&client, &line).await else {
+            continue;
+        };
+
+        // Handled in the background, as replies need this loop to read the
+        // plugin's answers, but one at a time per user to keep their order
+        let user_key = format!("{}:{}", name, incoming.user_id);
+        let client = client.clone();
+        let inbox = inbox.clone();
+        let manager = Arc::clone(task_manager);
+        task_manager.run_in_order(user_key, async move {
+            if let Err(e) = handle_message(client, display_name, &inbox, incoming, manager).await {
+                warn!("Error handling {} message: {}", name, e);
+            }
+        });
+    }
+
+    client.fail_pending().await;
+    let status = child.wait().await?;
+    bail!("plugin process exited with {}", status)
+}
+
+/// Handle one line from the plugin: responses go to the request waiting for
+/// them, and incoming messages are returned
+async fn route_line(client: &PluginClient, line: &str) -> Option<IncomingMessage> {
+    let name = client.name;
+    if line.trim().is_empty() {
+        return None;
+    }
+
+    let message: RpcMessage = match serde_json::from_str(line) {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("Invalid JSON-RPC from {} plugin: {} ({})", name, e, line);
+            return None;
+        }
+    };
+
+    // Responses to our requests
+    let Some(method) = message.method else {
+        if let Some(id) = message.id.as_ref().and_then(Value::as_u64) {
+            let result = match message.error {
+                Some(err) => Err(anyhow!("Plugin error {}: {}", err.code, err.message)),
+                None => Ok(message.result.unwrap_or(Value::Null)),
+            };
+            client.resolve(id, result).await;
+        }
+        return None;
+    };
+
+    // Plugins may send events as requests; acknowledge them
+    if let Some(id) = &message.id {
+        let ack = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+        let _ = client.write_line(&ack).await;
+    }
+
+    match method.as_str() {
+        "message" => match serde_json::from_value::<IncomingMessage>(message.params) {
+            Ok(incoming) => Some(incoming),
+            Err(e) => {
+                warn!("Invalid message event from {} plugin: {}", name, e);
+                None
+            }
+        },
+        other => {
+            debug!("Ignoring {} plugin method: {}", name, other);
+            None
+        }
+    }
+}
+
+/// Directory a plugin puts attachments in; files anywhere else are ignored
+fn plugin_inbox(name: &str) -> Result<PathBuf> {
+    let dir = crate::config::paths()?
+        .internal_dir
+        .join("plugins")
+        .join(name)
+        .join("inbox");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.canonicalize()?)
+}
+
+/// Resolve an attachment the plugin sent, if it's a file in its inbox
+fn inbox_attachment(inbox: &Path, path: &Path) -> Option<PathBuf> {
+    let resolved = path.canonicalize().ok()?;
+    (resolved.starts_with(inbox) && resolved.is_file()).then_some(resolved)
+}
+
+// ============================================================================
+// Message Handling
+// ============================================================================
+
+/// Handle an incoming message
+async fn handle_message(
+    client: Arc<PluginClient>,
+    display_name: &'static str,
+    inbox: &Path,
+    msg: IncomingMessage,
+    task_manager: Arc<UserTaskManager>,
+) -> Result<()> {
+    if msg.user_id.is_empty() {
+        return Ok(());
+    }
+    // Colons would let a user id pass for a group's
+    if msg.user_id.contains(':') {
+        warn!(
+            "{} sent a message from {:?}, but user ids can't contain ':'",
+            display_name, msg.user_id
+        );
+        return Ok(());
+    }
+
+    let attachments: Vec<PathBuf> = msg
+        .attachments
+        .iter()
+        .filter_map(|p| {
+            let resolved = inbox_attachment(inbox, p);
+            if resolved.is_none() {
+                warn!("Ignoring plugin attachment outside its inbox: {:?}", p);
+            }
+            resolved
+        })
+        .collect();
+
+    // Skip if no text and no attachments
+    if msg.text.is_empty() && attachments.is_empty() {
+        return Ok(());
+    }
+
+    info!("Message from {}: {}", msg.user_id, msg.text);
+
+    // Create channel wrapper
+    let channel: Arc<dyn Channel> = Arc::new(PluginChannel::new(
+        client,
+        display_name,
+        msg.user_id.clone(),
+        msg.conversation_id,
+    ));
+
+    // Images are passed through; other files are copied into the user's inbox
+    let mut text = msg.text;
+    let (image_paths, documents): (Vec<PathBuf>, Vec<PathBuf>) =
+        attachments.iter().cloned().partition(|p| is_image_path(p));
+    let mut document_paths: Vec<PathBuf> = Vec::new();
+    if is_trusted_sender(channel.name(), &msg.user_id) {
+        let limit = max_attachment_bytes();
+        for source in documents {
+            let file_name = source
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if std::fs::metadata(&source).map(|m| m.len()).unwrap_or(0) > limit {
+                text = format!(
+                    "{}\n\n{}",
+                    text,
+                    oversized_attachment_note(&file_name, limit)
+                )
+                .trim()
+                .to_string();
+                continue;
+            }
+            let saved = inbox_path(channel.name(), &msg.user_id, &file_name).and_then(|dest| {
+                std::fs::copy(&source, &dest)
+                    .map(|_| dest)
+                    .map_err(Into::into)
+            });
+            match saved {
+                Ok(path) => document_paths.push(path),
+                Err(e) => warn!("Failed to save plugin attachment {:?}: {}", source, e),
+            }
+        }
+    }
+
+    // Determine what action to take
+    let mut store = PairingStore::load()?;
+    let action = determine_action(
+        channel.name(),
+        &msg.user_id,
+        &text,
+        &attachments,
+        &mut store,
+        SenderInfo::new(msg.username, msg.display_name),
+    )?;
+
+    // Execute the action
+    if let Some(query_text) =
+        execute_action(channel.as_ref(), &msg.user_id, action, &task_manager).await?
+    {
+        // QueryClaude action - queue with task manager for debouncing
+        let text_with_images = build_text_with_images(&query_text, &image_paths, &document_paths);
+        let user_key = format!("{}:{}", channel.name(), msg.user_id);
+        let channel_clone = channel.clone();
+        let user_id = msg.user_id.clone();
+
+        task_manager
+            .process_message(
+                user_key,
+                text_with_images,
+                move |messages, steering| async move {
+                    execute_claude_query(channel_clone, &user_id, &user_id, messages, steering)
+                        .await;
+                },
+            )
+            .await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::Lines;
+    use tokio::process::{Child, ChildStdout};
+
+    /// A client whose requests come back out of `cat`, so tests can read them
+    fn echo_client() -> (Arc<PluginClient>, Lines<BufReader<ChildStdout>>, Child) {
+        let mut child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let client = Arc::new(PluginClient {
+            name: "test",
+            stdin: Mutex::new(child.stdin.take().unwrap()),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        });
+        let written = BufReader::new(child.stdout.take().unwrap()).lines();
+        (client, written, child)
+    }
+
+    async fn next_written(written: &mut Lines<BufReader<ChildStdout>>) -> Value {
+        serde_json::from_str(&written.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_route_responses() {
+        let (client, mut written, _child) = echo_client();
+
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move { client.request("send", json!({ "text": "hi" })).await }
+        });
+        let sent = next_written(&mut written).await;
+        assert_eq!(sent["method"], "send");
+        let id = sent["id"].as_u64().unwrap();
+
+        // Answers to requests nobody is waiting for are dropped
+        let other = json!({ "jsonrpc": "2.0", "id": id + 1, "result": "other" });
+        assert!(route_line(&client, &other.to_string()).await.is_none());
+        let answer = json!({ "jsonrpc": "2.0", "id": id, "result": "ok" });
+        assert!(route_line(&client, &answer.to_string()).await.is_none());
+        assert_eq!(request.await.unwrap().unwrap(), json!("ok"));
+
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move { client.request("typing", json!({ "active": true })).await }
+        });
+        let id = next_written(&mut written).await["id"].as_u64().unwrap();
+        let error = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32000, "message": "no such user" }
+        });
+        assert!(route_line(&client, &error.to_string()).await.is_none());
+        let err = request.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("no such user"));
+    }
+
+    #[tokio::test]
+    async fn test_route_messages() {
+        let (client, mut written, _child) = echo_client();
+
+        let line = json!({
+            "jsonrpc": "2.0",
+            "method": "message",
+            "params": { "user_id": "alice", "text": "hello", "conversation_id": "room" }
+        });
+        let incoming = route_line(&client, &line.to_string()).await.unwrap();
+        assert_eq!(incoming.user_id, "alice");
+        assert_eq!(incoming.text, "hello");
+        assert_eq!(incoming.conversation_id.as_deref(), Some("room"));
+
+        // Sent as a request, the message is acknowledged
+        let line = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "message",
+            "params": { "user_id": "bob", "text": "hi" }
+        });
+        assert!(route_line(&client, &line.to_string()).await.is_some());
+        let ack = next_written(&mut written).await;
+        assert_eq!(ack["id"], 7);
+        assert!(ack["result"].is_null());
+
+        let unknown = r#"{"jsonrpc":"2.0","method":"status","params":{}}"#;
+        assert!(route_line(&client, unknown).await.is_none());
+        let no_user = r#"{"jsonrpc":"2.0","method":"message","params":{"text":"hi"}}"#;
+        assert!(route_line(&client, no_user).await.is_none());
+        assert!(route_line(&client, "not json").await.is_none());
+    }
+
+    #[test]
+    fn test_inbox_attachment() {
+        let dir = std::env::temp_dir().join(format!("cica-plugin-{}", std::process::id()));
+        let inbox = dir.join("inbox");
+        std::fs::create_dir_all(&inbox).unwrap();
+        std::fs::write(inbox.join("report.pdf"), "pdf").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let inbox = inbox.canonicalize().unwrap();
+
+        assert_eq!(
+            inbox_attachment(&inbox, &inbox.join("report.pdf")),
+            Some(inbox.join("report.pdf"))
+        );
+        assert_eq!(inbox_attachment(&inbox, &dir.join("secret.txt")), None);
+        assert_eq!(inbox_attachment(&inbox, &inbox.join("../secret.txt")), None);
+        assert_eq!(inbox_attachment(&inbox, &inbox.join("missing.pdf")), None);
+        assert_eq!(inbox_attachment(&inbox, &inbox), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//! Live channels Cica can message without being asked first.
+//!
+//! Each channel registers how to reach one of its users once it's connected.
+//! Cron results and other proactive messages are sent through here, so they
+//! get the same formatting, splitting and attachments as replies.
+
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::{Channel, send_response};
+
+/// Builds a channel to one user, or `None` if the id can't be reached
+type Connector = Box<dyn Fn(&str) -> Option<Arc<dyn Channel>> + Send + Sync>;
+
+/// Registry of connected channels, keyed by channel name
+#[derive(Default)]
+pub struct ChannelRegistry {
+    connectors: RwLock<HashMap<String, Connector>>,
+}
+
+impl ChannelRegistry {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Register how to reach users on a channel, replacing the previous
+    /// connection's after a reconnect
+    pub fn register(
+        &self,
+        name: &str,
+        connect: impl Fn(&str) -> Option<Arc<dyn Channel>> + Send + Sync + 'static,
+    ) {
+        if let Ok(mut connectors) = self.connectors.write() {
+            connectors.insert(name.to_string(), Box::new(connect));
+        }
+    }
+
+    /// Get a channel to a user
+    pub fn channel_for(&self, channel: &str, user_id: &str) -> Result<Arc<dyn Channel>> {
+        let connectors = self
+            .connectors
+            .read()
+            .map_err(|_| anyhow!("Channel registry is unavailable"))?;
+        let connect = connectors
+            .get(channel)
+            .ok_or_else(|| anyhow!("{} is not connected", channel))?;
+        connect(user_id).ok_or_else(|| anyhow!("Can't reach {} on {}", user_id, channel))
+    }
+
+    /// Send a message to a user, split to fit the channel and with any media
+    /// files it mentions attached
+    pub async fn send(&self, channel: &str, user_id: &str, message: &str) -> Result<()> {
+        let target = self.channel_for(channel, user_id)?;
+        send_response(target.as_ref(), message, &[], &mut 0).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::TypingGuard;
+    use async_trait::async_trait;
+
+    struct TestChannel;
+
+    #[async_trait]
+    impl Channel for TestChannel {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn display_name(&self) -> &'static str {
+            "Test"
+        }
+
+        async fn send_message(&self, _message: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn start_typing(&self) -> TypingGuard {
+            TypingGuard::noop()
+        }
+    }
+
+    #[test]
+    fn test_channel_for() {
+        let registry = ChannelRegistry::new();
+        assert!(registry.channel_for("test", "alice").is_err());
+
+        registry.register("test", |user_id| {
+            (user_id != "nobody").then(|| Arc::new(TestChannel) as Arc<dyn Channel>)
+        });
+        assert_eq!(
+            registry.channel_for("test", "alice").unwrap().name(),
+            "test"
+        );
+        assert!(registry.channel_for("test", "nobody").is_err());
+        assert!(registry.channel_for("other", "alice").is_err());
+    }
+}
@@ -16,27 +16,71 @@ use tokio::sync::oneshot;
 use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 
+use super::registry::ChannelRegistry;
 use super::{
-    Channel, TypingGuard, UserTaskManager, build_text_with_images, determine_action,
-    execute_action, execute_claude_query,
+    Channel, SenderInfo, SharedContact, SharedLocation, TypingGuard, UserTaskManager,
+    add_reply_context, add_shared_context, add_voice_transcripts, build_text_with_images,
+    determine_action, execute_action, execute_claude_query, format_group_message, handle_edit,
+    handle_reaction, inbox_path, is_trusted_sender, markdown, max_attachment_bytes,
+    oversized_attachment_note,
 };
-use crate::config::{self, SignalConfig};
-use crate::pairing::PairingStore;
+use crate::config::{self, SignalConfig, SignalDaemonConfig};
+use crate::pairing::{self, PairingStore};
 use crate::setup;
 
 // ============================================================================
 // Channel Implementation
 // ============================================================================
 
+/// Where a Signal message is sent
+#[derive(Debug, Clone)]
+enum Recipient {
+    User(String),
+    Group(String),
+}
+
+impl Recipient {
+    /// Add the recipient to JSON-RPC params (`recipient` or `groupId`)
+    fn insert_into(&self, params: &mut ObjectParams) -> Result<(), serde_json::Error> {
+        match self {
+            Self::User(number) => params.insert("recipient", vec![number.as_str()]),
+            Self::Group(group_id) => params.insert("groupId", group_id.as_str()),
+        }
+    }
+}
+
 /// Signal channel implementation
 pub struct SignalChannel {
     client: Arc<HttpClient>,
-    recipient: String,
+    recipient: Recipient,
+    /// Timestamps of messages sent since `take_sent_message_ids` was last called
+    sent: std::sync::Mutex<Vec<String>>,
 }
 
 impl SignalChannel {
     pub fn new(client: Arc<HttpClient>, recipient: String) -> Self {
-        Self { client, recipient }
+        Self {
+            client,
+            recipient: Recipient::User(recipient),
+            sent: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Create a channel that replies to a group
+    pub fn for_group(client: Arc<HttpClient>, group_id: String) -> Self {
+        Self {
+            client,
+            recipient: Recipient::Group(group_id),
+            sent: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Create a channel to a user or group by pairing id
+    pub fn for_pairing_id(client: Arc<HttpClient>, user_id: &str) -> Self {
+        match pairing::group_id(user_id) {
+            Some(group_id) => Self::for_group(client, decode_group_id(group_id)),
+            None => Self::new(client, user_id.to_string()),
+        }
     }
 }
 
@@ -60,8 +104,14 @@ impl Channel for SignalChannel {
         attachment_paths: &[PathBuf],
     ) -> Result<()> {
         let mut params = ObjectParams::new();
-        params.insert("recipient", vec![self.recipient.as_str()])?;
-        params.insert("message", message)?;
+        self.recipient.insert_into(&mut params)?;
+
+        // Markdown becomes plain text plus style ranges
+        let styled = markdown::to_signal(message);
+        params.insert("message", styled.text)?;
+        if !styled.styles.is_empty() {
+            params.insert("textStyle", styled.styles)?;
+        }
 
         // Add attachments if any
         if !attachment_paths.is_empty() {
@@ -72,15 +122,41 @@ impl Channel for SignalChannel {
             params.insert("attachments", attachment_strings)?;
         }
 
-        let _: Value = self
+        let result: Value = self
             .client
             .request("send", params)
             .await
             .context("Failed to send message")?;
 
+        // Signal identifies messages by their sent timestamp
+        if let Some(timestamp) = result.get("timestamp").and_then(Value::as_u64)
+            && let Ok(mut sent) = self.sent.lock()
+        {
+            sent.push(timestamp.to_string());
+        }
+
         Ok(())
     }
 
+    fn max_message_length(&self) -> Option<usize> {
+        // Longer messages are collapsed behind "Read more" in Signal clients
+        Some(2000)
+    }
+
+    fn for_user(&self, user_id: &str) -> Option<Arc<dyn Channel>> {
+        Some(Arc::new(SignalChannel::for_pairing_id(
+            self.client.clone(),
+            user_id,
+        )))
+    }
+
+    fn take_sent_message_ids(&self) -> Vec<String> {
+        self.sent
+            .lock()
+            .map(|mut sent| std::mem::take(&mut *sent))
+            .unwrap_or_default()
+    }
+
     fn start_typing(&self) -> TypingGuard {
         let (cancel_tx, mut cancel_rx) = oneshot::channel();
         let client = self.client.clone();
@@ -90,7 +166,7 @@ impl Channel for SignalChannel {
             loop {
                 // Send typing indicator (lasts 15 seconds on Signal)
                 let mut params = ObjectParams::new();
-                if params.insert("recipient", vec![recipient.as_str()]).is_ok() {
+                if recipient.insert_into(&mut params).is_ok() {
                     let _: Result<Value, _> = client.request("sendTyping", params).await;
                 }
 
@@ -115,7 +191,6 @@ impl Channel for SignalChannel {
 // Daemon Management
 // ============================================================================
 
-const DAEMON_PORT: u16 = 18080;
 const PID_FILE_NAME: &str = "cica-signal-daemon.pid";
 
 /// signal-cli daemon manager
@@ -159,20 +234,19 @@ impl SignalDaemon {
     }
 
     /// Check if daemon HTTP endpoint is responding
-    async fn is_daemon_ready() -> bool {
-        let url = format!("http://127.0.0.1:{}/api/v1/rpc", DAEMON_PORT);
-        reqwest::get(&url).await.is_ok()
+    async fn is_daemon_ready(daemon_config: &SignalDaemonConfig) -> bool {
+        reqwest::get(&daemon_config.rpc_url()).await.is_ok()
     }
 
     /// Start signal-cli daemon with JSON-RPC HTTP interface
-    async fn start(phone_number: &str) -> Result<Self> {
+    async fn start(phone_number: &str, daemon_config: &SignalDaemonConfig) -> Result<Self> {
         let paths = config::paths()?;
         let pid_file = Self::pid_file_path()?;
 
         // Check if daemon is already running
         if let Some(pid) = Self::check_existing() {
             // Verify it's actually responding
-            if Self::is_daemon_ready().await {
+            if Self::is_daemon_ready(daemon_config).await {
                 bail!(
                     "signal-cli daemon is already running (PID {}). \
                      Kill it first or let cica manage it.",
@@ -200,7 +274,10 @@ impl SignalDaemon {
             .and_then(|p| p.parent())
             .ok_or_else(|| anyhow!("Could not determine signal-cli home directory"))?;
 
-        info!("Starting signal-cli daemon on port {}...", DAEMON_PORT);
+        info!(
+            "Starting signal-cli daemon on {}:{}...",
+            daemon_config.host, daemon_config.port
+        );
 
         // Build JAVA_HOME from java binary path
         let java_home = java
@@ -211,9 +288,10 @@ impl SignalDaemon {
         // Ensure data directory exists
         std::fs::create_dir_all(&paths.signal_data_dir)?;
 
-        // Start signal-cli daemon
-        // Use --receive-mode manual so we can poll with the receive RPC method
-        let http_addr = format!("localhost:{}", DAEMON_PORT);
+        // Start signal-cli daemon. It only fetches messages from Signal while
+        // we're connected to its events endpoint, so none are lost (they wait
+        // on the server) while we reconnect.
+        let http_addr = format!("{}:{}", daemon_config.host, daemon_config.port);
         let process = Command::new(&signal_cli)
             .args([
                 "-a",
@@ -224,7 +302,7 @@ impl SignalDaemon {
                 "--http",
                 &http_addr,
                 "--receive-mode",
-                "manual",
+                "on-connection",
             ])
             .env("JAVA_HOME", java_home)
             .env(
@@ -250,13 +328,13 @@ impl SignalDaemon {
         let mut daemon = Self { process, pid_file };
 
         // Wait for daemon to be ready
-        daemon.wait_for_ready().await?;
+        daemon.wait_for_ready(daemon_config).await?;
 
         Ok(daemon)
     }
 
     /// Wait for the daemon HTTP server to become available
-    async fn wait_for_ready(&mut self) -> Result<()> {
+    async fn wait_for_ready(&mut self, daemon_config: &SignalDaemonConfig) -> Result<()> {
         for i in 0..30 {
             sleep(Duration::from_millis(500)).await;
 
@@ -279,7 +357,7 @@ impl SignalDaemon {
                 );
             }
 
-            if Self::is_daemon_ready().await {
+            if Self::is_daemon_ready(daemon_config).await {
                 info!("signal-cli daemon is ready");
                 return Ok(());
             }
@@ -289,11 +367,6 @@ impl SignalDaemon {
         bail!("signal-cli daemon failed to start within 15 seconds")
     }
 
-    /// Get the JSON-RPC endpoint URL
-    fn rpc_url(&self) -> String {
-        format!("http://127.0.0.1:{}/api/v1/rpc", DAEMON_PORT)
-    }
-
     /// Gracefully shutdown the daemon
     async fn shutdown(&mut self) {
         info!("Shutting down signal-cli daemon...");
@@ -352,6 +425,18 @@ struct Envelope {
     source_uuid: Option<String>,
     #[serde(rename = "sourceName")]
     source_name: Option<String>,
+    timestamp: Option<u64>,
+    #[serde(rename = "dataMessage")]
+    data_message: Option<DataMessage>,
+    #[serde(rename = "editMessage")]
+    edit_message: Option<EditMessage>,
+}
+
+/// A new version of a message sent earlier
+#[derive(Debug, Deserialize)]
+struct EditMessage {
+    #[serde(rename = "targetSentTimestamp")]
+    target_sent_timestamp: u64,
     #[serde(rename = "dataMessage")]
     data_message: Option<DataMessage>,
 }
@@ -360,6 +445,97 @@ struct Envelope {
 struct DataMessage {
     message: Option<String>,
     attachments: Option<Vec<Attachment>>,
+    #[serde(rename = "groupInfo")]
+    group_info: Option<GroupInfo>,
+    #[serde(default)]
+    mentions: Vec<Mention>,
+    quote: Option<Quote>,
+    reaction: Option<Reaction>,
+    #[serde(rename = "sharedContacts", default)]
+    shared_contacts: Vec<ContactCard>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupInfo {
+    #[serde(rename = "groupId")]
+    group_id: Option<String>,
+    #[serde(rename = "groupName")]
+    group_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Mention {
+    number: Option<String>,
+    uuid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Quote {
+    author: Option<String>,
+    #[serde(rename = "authorNumber")]
+    author_number: Option<String>,
+    #[serde(rename = "authorUuid")]
+    author_uuid: Option<String>,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reaction {
+    emoji: Option<String>,
+    #[serde(rename = "targetAuthorNumber")]
+    target_author_number: Option<String>,
+    #[serde(rename = "targetAuthorUuid")]
+    target_author_uuid: Option<String>,
+    #[serde(rename = "targetSentTimestamp")]
+    target_sent_timestamp: Option<u64>,
+    #[serde(rename = "isRemove", default)]
+    is_remove: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContactCard {
+    name: Option<ContactName>,
+    #[serde(default)]
+    phone: Vec<ContactDetail>,
+    #[serde(default)]
+    email: Vec<ContactDetail>,
+    organization: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContactName {
+    display: Option<String>,
+    given: Option<String>,
+    family: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContactDetail {
+    value: Option<String>,
+}
+
+impl ContactCard {
+    fn to_shared(&self) -> SharedContact {
+        let name = self.name.as_ref().and_then(|name| {
+            name.display.clone().or_else(|| {
+                let parts: Vec<&str> = [&name.given, &name.family]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect();
+                (!parts.is_empty()).then(|| parts.join(" "))
+            })
+        });
+        let values =
+            |details: &[ContactDetail]| details.iter().filter_map(|d| d.value.clone()).collect();
+
+        SharedContact {
+            name: name.unwrap_or_else(|| "Unknown".to_string()),
+            phone_numbers: values(&self.phone),
+            emails: values(&self.email),
+            organization: self.organization.clone().filter(|o| !o.is_empty()),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -378,22 +554,31 @@ struct Attachment {
 // ============================================================================
 
 /// Run the Signal bot
-pub async fn run(config: SignalConfig) -> Result<()> {
+pub async fn run(config: SignalConfig, registry: Arc<ChannelRegistry>) -> Result<()> {
     info!("Starting Signal bot for {}...", config.phone_number);
 
     // Create shared task manager for per-user message handling (persists across restarts)
-    let task_manager = UserTaskManager::new();
+    let task_manager = UserTaskManager::for_channel("signal");
+    let daemon_config = &config.daemon;
 
     // Outer loop for daemon recovery
     loop {
-        // Start the signal-cli daemon
-        let mut daemon = match SignalDaemon::start(&config.phone_number).await {
-            Ok(d) => d,
-            Err(e) => {
-                error!("Failed to start signal-cli daemon: {:#}", e);
-                info!("Retrying in 10 seconds...");
-                sleep(Duration::from_secs(10)).await;
-                continue;
+        // Start the signal-cli daemon, unless it's managed elsewhere
+        let mut daemon = if daemon_config.external {
+            info!(
+                "Using signal-cli daemon at {}:{}",
+                daemon_config.host, daemon_config.port
+            );
+            None
+        } else {
+            match SignalDaemon::start(&config.phone_number, daemon_config).await {
+                Ok(d) => Some(d),
+                Err(e) => {
+                    error!("Failed to start signal-cli daemon: {:#}", e);
+                    info!("Retrying in 10 seconds...");
+                    sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
             }
         };
 
@@ -401,20 +586,34 @@ pub async fn run(config: SignalConfig) -> Result<()> {
         let client = Arc::new(
             HttpClientBuilder::default()
                 .request_timeout(Duration::from_secs(30))
-                .build(daemon.rpc_url())
+                .build(daemon_config.rpc_url())
                 .context("Failed to create JSON-RPC client")?,
         );
 
+        let registry_client = client.clone();
+        registry.register("signal", move |user_id| {
+            let channel = SignalChannel::for_pairing_id(registry_client.clone(), user_id);
+            Some(Arc::new(channel) as Arc<dyn Channel>)
+        });
+
         info!("Signal bot running. Listening for messages...");
 
+        let account = Account {
+            number: config.phone_number.clone(),
+            uuid: own_uuid(&client, &config.phone_number).await,
+        };
+
         // Run message loop until it signals a restart is needed
-        let needs_restart = run_message_loop(client, Arc::clone(&task_manager)).await;
+        let needs_restart =
+            run_message_loop(client, &account, daemon_config, Arc::clone(&task_manager)).await;
 
         // Shutdown daemon gracefully
-        daemon.shutdown().await;
+        if let Some(daemon) = daemon.as_mut() {
+            daemon.shutdown().await;
+        }
 
         if needs_restart {
-            warn!("Restarting signal-cli daemon due to repeated failures...");
+            warn!("Reconnecting to signal-cli daemon due to repeated failures...");
             sleep(Duration::from_secs(2)).await;
         } else {
             // Clean exit requested
@@ -429,65 +628,186 @@ pub async fn run(config: SignalConfig) -> Result<()> {
 // Message Handling
 // ============================================================================
 
-/// Maximum consecutive receive failures before restarting daemon
+/// Our own Signal account. Mentions, quotes and reactions may name it by
+/// number or by UUID.
+#[derive(Debug, Clone)]
+struct Account {
+    number: String,
+    uuid: Option<String>,
+}
+
+impl Account {
+    fn is(&self, number: Option<&str>, uuid: Option<&str>) -> bool {
+        number == Some(self.number.as_str())
+            || uuid.is_some_and(|u| Some(u) == self.uuid.as_deref())
+    }
+}
+
+/// Look up our own UUID
+async fn own_uuid(client: &HttpClient, number: &str) -> Option<String> {
+    let mut params = ObjectParams::new();
+    params.insert("recipient", vec![number]).ok()?;
+    match client.request::<Value, _>("getUserStatus", params).await {
+        Ok(status) => status.get(0)?.get("uuid")?.as_str().map(String::from),
+        Err(e) => {
+            warn!(
+                "Couldn't look up our Signal UUID, mentions by UUID won't be seen: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Maximum consecutive connection failures before restarting daemon
 const MAX_CONSECUTIVE_FAILURES: u32 = 10;
 
-/// Main message polling loop
+/// Longest wait between attempts to reconnect to the event stream
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Reconnect to the event stream after this long without data, in case the
+/// connection died without being closed
+const EVENT_STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Main message loop: handle messages as the daemon pushes them, and
+/// reconnect with backoff when the event stream drops.
 /// Returns true if daemon should be restarted, false for clean exit
-async fn run_message_loop(client: Arc<HttpClient>, task_manager: Arc<UserTaskManager>) -> bool {
+async fn run_message_loop(
+    client: Arc<HttpClient>,
+    account: &Account,
+    daemon_config: &SignalDaemonConfig,
+    task_manager: Arc<UserTaskManager>,
+) -> bool {
+    let http = reqwest::Client::new();
     let mut consecutive_failures: u32 = 0;
 
     loop {
-        match receive_messages(&client).await {
-            Ok(messages) => {
-                // Reset failure counter on success
-                consecutive_failures = 0;
-
-                for msg in messages {
-                    if let Err(e) =
-                        handle_message(client.clone(), msg, Arc::clone(&task_manager)).await
-                    {
-                        error!("Error handling message: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                consecutive_failures += 1;
-                warn!(
-                    "Error receiving messages ({}/{}): {:#}",
-                    consecutive_failures, MAX_CONSECUTIVE_FAILURES, e
-                );
+        let result = receive_events(
+            &http,
+            &client,
+            account,
+            daemon_config,
+            &task_manager,
+            &mut consecutive_failures,
+        )
+        .await;
 
-                if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
-                    error!(
-                        "Too many consecutive receive failures ({}), triggering daemon restart",
-                        consecutive_failures
-                    );
-                    return true; // Signal restart needed
-                }
-            }
+        // An idle stream is reopened right away; it's not a failure
+        if let Ok(true) = result {
+            continue;
+        }
+
+        consecutive_failures += 1;
+        match result {
+            Ok(_) => warn!(
+                "signal-cli event stream closed ({}/{})",
+                consecutive_failures, MAX_CONSECUTIVE_FAILURES
+            ),
+            Err(e) => warn!(
+                "Error receiving messages ({}/{}): {:#}",
+                consecutive_failures, MAX_CONSECUTIVE_FAILURES, e
+            ),
+        }
+
+        if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            error!(
+                "Too many consecutive receive failures ({}), triggering daemon restart",
+                consecutive_failures
+            );
+            return true; // Signal restart needed
         }
 
-        // Poll interval
-        sleep(Duration::from_secs(1)).await;
+        let delay = Duration::from_secs(1 << consecutive_failures.min(5)).min(MAX_RECONNECT_DELAY);
+        sleep(delay).await;
     }
 }
 
-/// Receive pending messages
-async fn receive_messages(client: &HttpClient) -> Result<Vec<SignalMessage>> {
-    // In single-account daemon mode, we don't pass account parameter
-    let mut params = ObjectParams::new();
-    params.insert("timeout", 1)?; // 1 second timeout
-
-    let result: Value = client
-        .request("receive", params)
+/// Connect to the daemon's event stream and handle messages until it ends.
+/// Returns true when the stream was dropped for being idle.
+async fn receive_events(
+    http: &reqwest::Client,
+    client: &Arc<HttpClient>,
+    account: &Account,
+    daemon_config: &SignalDaemonConfig,
+    task_manager: &Arc<UserTaskManager>,
+    consecutive_failures: &mut u32,
+) -> Result<bool> {
+    let mut response = http
+        .get(daemon_config.events_url())
+        .send()
         .await
-        .context("Failed to receive messages")?;
+        .context("Failed to connect to the signal-cli event stream")?
+        .error_for_status()?;
 
-    // Parse the response - it's an array of message envelopes
-    let messages: Vec<SignalMessage> = serde_json::from_value(result).unwrap_or_default();
+    debug!("Connected to the signal-cli event stream");
+    *consecutive_failures = 0;
 
-    Ok(messages)
+    let mut events = EventStreamParser::default();
+    loop {
+        let Ok(chunk) = tokio::time::timeout(EVENT_STREAM_IDLE_TIMEOUT, response.chunk()).await
+        else {
+            debug!("No Signal events for a while, reconnecting");
+            return Ok(true);
+        };
+        let Some(chunk) = chunk.context("Failed to read the signal-cli event stream")? else {
+            return Ok(false);
+        };
+
+        for data in events.push(&chunk) {
+            let msg: SignalMessage = match serde_json::from_str(&data) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    debug!("Ignoring unreadable Signal event: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) =
+                handle_message(client.clone(), account, msg, Arc::clone(task_manager)).await
+            {
+                error!("Error handling message: {}", e);
+            }
+        }
+    }
+}
+
+/// Splits a server-sent event stream into the data of its `receive` events
+#[derive(Default)]
+struct EventStreamParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl EventStreamParser {
+    /// Feed a chunk of the stream, returning the data of each event it completes
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut complete = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // A blank line ends the event
+                let is_receive = self.event.as_deref().is_none_or(|e| e == "receive");
+                if is_receive && !self.data.is_empty() {
+                    complete.push(self.data.join("\n"));
+                }
+                self.event = None;
+                self.data.clear();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            } else if let Some(value) = line.strip_prefix("event:") {
+                self.event = Some(value.trim().to_string());
+            }
+            // Comments (keep-alives) and other fields are ignored
+        }
+
+        complete
+    }
 }
 
 /// Get the path where signal-cli stores attachments
@@ -512,9 +832,60 @@ fn is_image_content_type(content_type: &str) -> bool {
     )
 }
 
+/// Signal group ids are standard base64, which may contain '/'. Pairing ids
+/// become directory names, so groups are stored with the URL-safe alphabet.
+pub fn encode_group_id(group_id: &str) -> String {
+    group_id.replace('+', "-").replace('/', "_")
+}
+
+/// Reverse `encode_group_id`
+pub fn decode_group_id(encoded: &str) -> String {
+    encoded.replace('-', "+").replace('_', "/")
+}
+
+/// Check whether a group message is meant for us: it @mentions us or quotes
+/// one of our messages
+fn is_addressed_to_bot(data_message: &DataMessage, account: &Account) -> bool {
+    let mentioned = data_message
+        .mentions
+        .iter()
+        .any(|m| account.is(m.number.as_deref(), m.uuid.as_deref()));
+    let quoted = data_message
+        .quote
+        .as_ref()
+        .is_some_and(|q| account.is(q.author_number.as_deref(), q.author_uuid.as_deref()));
+
+    mentioned || quoted
+}
+
+/// Turn the user's text into the prompt for the assistant: name the sender
+/// in groups and include the message being quoted.
+fn prepare_query_text(
+    data_message: &DataMessage,
+    account: &Account,
+    text: &str,
+    group_sender: Option<&str>,
+) -> String {
+    let text = match group_sender {
+        Some(sender) => format_group_message(sender, text),
+        None => text.to_string(),
+    };
+
+    let Some(quote) = &data_message.quote else {
+        return text;
+    };
+    let author = if account.is(quote.author_number.as_deref(), quote.author_uuid.as_deref()) {
+        Some("you")
+    } else {
+        quote.author.as_deref().or(quote.author_number.as_deref())
+    };
+    add_reply_context(&text, author, quote.text.as_deref().unwrap_or_default())
+}
+
 /// Handle an incoming message
 async fn handle_message(
     client: Arc<HttpClient>,
+    account: &Account,
     msg: SignalMessage,
     task_manager: Arc<UserTaskManager>,
 ) -> Result<()> {
@@ -523,6 +894,9 @@ async fn handle_message(
         None => return Ok(()),
     };
 
+    // Allow and block lists may name the sender by UUID
+    let sender_uuid = envelope.source_uuid.clone();
+
     // Get sender info - prefer phone number, fall back to UUID
     let sender = envelope
         .source_number
@@ -534,14 +908,57 @@ async fn handle_message(
         return Ok(());
     }
 
-    // Extract message content and attachments
-    let data_message = match envelope.data_message {
-        Some(dm) => dm,
-        None => return Ok(()),
+    // Extract message content and attachments. Edits carry the new version
+    // of the message along with the timestamp of the original.
+    let (mut data_message, edit_target) = match (envelope.data_message, envelope.edit_message) {
+        (Some(dm), _) => (dm, None),
+        (
+            None,
+            Some(EditMessage {
+                target_sent_timestamp,
+                data_message: Some(dm),
+            }),
+        ) => (dm, Some(target_sent_timestamp)),
+        _ => return Ok(()),
     };
 
-    let text = data_message.message.clone().unwrap_or_default();
-    let attachments = data_message.attachments.unwrap_or_default();
+    // Reactions to our own messages are feedback on a reply
+    if let Some(reaction) = &data_message.reaction {
+        if account.is(
+            reaction.target_author_number.as_deref(),
+            reaction.target_author_uuid.as_deref(),
+        ) && let (Some(emoji), Some(target)) =
+            (reaction.emoji.as_deref(), reaction.target_sent_timestamp)
+        {
+            handle_reaction(
+                "signal",
+                &target.to_string(),
+                &sender,
+                emoji,
+                reaction.is_remove,
+            );
+        }
+        return Ok(());
+    }
+
+    // In groups, only respond when addressed, and treat the whole group as one user
+    let group = data_message
+        .group_info
+        .as_ref()
+        .and_then(|g| g.group_id.clone().map(|id| (id, g.group_name.clone())));
+    if group.is_some() && !is_addressed_to_bot(&data_message, account) {
+        return Ok(());
+    }
+
+    // Mentions show up as U+FFFC placeholders in the message text
+    let text = data_message
+        .message
+        .clone()
+        .unwrap_or_default()
+        .replace('\u{FFFC}', "")
+        .trim()
+        .to_string();
+    let attachments = data_message.attachments.take().unwrap_or_default();
 
     // Collect image attachment paths
     let image_paths: Vec<PathBuf> = attachments
@@ -555,12 +972,111 @@ async fn handle_message(
         .filter_map(|a| a.id.as_ref().and_then(|id| get_attachment_path(id)))
         .collect();
 
-    // Skip if no text and no images
-    if text.is_empty() && image_paths.is_empty() {
+    // Collect voice notes and other audio for transcription
+    let audio_paths: Vec<PathBuf> = attachments
+        .iter()
+        .filter(|a| {
+            a.content_type
+                .as_ref()
+                .is_some_and(|ct| ct.starts_with("audio/"))
+        })
+        .filter_map(|a| a.id.as_ref().and_then(|id| get_attachment_path(id)))
+        .collect();
+
+    // Groups are paired, queued and given a session as a single user
+    let pairing_id = match &group {
+        Some((group_id, _)) => pairing::group_user_id(&encode_group_id(group_id)),
+        None => sender.clone(),
+    };
+
+    let display_name = envelope.source_name;
+    let group_sender = group
+        .as_ref()
+        .map(|_| display_name.clone().unwrap_or_else(|| sender.clone()));
+
+    // An edit replaces the original if it hasn't been answered yet
+    if let Some(target) = edit_target {
+        // Commands have already run, there's nothing to redo
+        if text.is_empty() || text.starts_with('/') {
+            return Ok(());
+        }
+        let channel: Arc<dyn Channel> = match &group {
+            Some((group_id, _)) => Arc::new(SignalChannel::for_group(client, group_id.clone())),
+            None => Arc::new(SignalChannel::new(client, sender.clone())),
+        };
+        let query_text = prepare_query_text(&data_message, account, &text, group_sender.as_deref());
+        let text_with_images = build_text_with_images(&query_text, &image_paths, &[]);
+        handle_edit(
+            channel,
+            &pairing_id,
+            &pairing_id,
+            &target.to_string(),
+            text_with_images,
+            &task_manager,
+        )
+        .await;
         return Ok(());
     }
 
-    let display_name = envelope.source_name;
+    // Copy any other files into the user's inbox
+    let mut text = text;
+    let mut document_paths: Vec<PathBuf> = Vec::new();
+    let documents = attachments.iter().filter(|a| {
+        a.content_type
+            .as_ref()
+            .is_none_or(|ct| !is_image_content_type(ct) && !ct.starts_with("audio/"))
+    });
+    for attachment in documents {
+        if !is_trusted_sender("signal", &pairing_id) {
+            break;
+        }
+        let Some(id) = attachment.id.as_deref() else {
+            continue;
+        };
+        let file_name = attachment.filename.as_deref().unwrap_or(id);
+        let limit = max_attachment_bytes();
+        if attachment.size.unwrap_or(0) > limit {
+            info!("Attachment {} is over the size limit", file_name);
+            text = format!(
+                "{}\n\n{}",
+                text,
+                oversized_attachment_note(file_name, limit)
+            )
+            .trim()
+            .to_string();
+            continue;
+        }
+        let Some(source) = get_attachment_path(id) else {
+            continue;
+        };
+        let saved = inbox_path("signal", &pairing_id, file_name).and_then(|dest| {
+            std::fs::copy(&source, &dest)
+                .map(|_| dest)
+                .map_err(Into::into)
+        });
+        match saved {
+            Ok(path) => document_paths.push(path),
+            Err(e) => warn!("Failed to save attachment {}: {}", file_name, e),
+        }
+    }
+
+    // Shared places and contact cards become structured context
+    let location = SharedLocation::from_map_link(&text);
+    let contacts: Vec<SharedContact> = data_message
+        .shared_contacts
+        .iter()
+        .map(ContactCard::to_shared)
+        .collect();
+    let text = add_shared_context(&text, location.as_ref(), &contacts);
+
+    // Skip if no text, images, audio or documents
+    if text.is_empty()
+        && image_paths.is_empty()
+        && audio_paths.is_empty()
+        && document_paths.is_empty()
+    {
+        return Ok(());
+    }
 
     info!("Message from {}: {}", sender, text);
     if !image_paths.is_empty() {
@@ -570,34 +1086,78 @@ async fn handle_message(
             image_paths
         );
     }
+    if !document_paths.is_empty() {
+        info!(
+            "Message includes {} document(s): {:?}",
+            document_paths.len(),
+            document_paths
+        );
+    }
 
     // Create channel wrapper
-    let channel: Arc<dyn Channel> = Arc::new(SignalChannel::new(client, sender.clone()));
+    let (channel, pairing_name): (Arc<dyn Channel>, Option<String>) = match &group {
+        Some((group_id, group_name)) => (
+            Arc::new(SignalChannel::for_group(client, group_id.clone())),
+            group_name.clone(),
+        ),
+        None => (
+            Arc::new(SignalChannel::new(client, sender.clone())),
+            display_name.clone(),
+        ),
+    };
+
+    // Signal doesn't have usernames
+    let mut member = SenderInfo::new(None, display_name.clone());
+    member.aliases.extend(sender_uuid);
+    let sender_info = match &group {
+        Some(_) => SenderInfo::new(None, pairing_name).with_member(sender.clone(), member),
+        None => member,
+    };
+
+    // Voice notes become part of the message text, or are passed on as files
+    let (text, kept_audio) =
+        add_voice_transcripts(channel.as_ref(), &pairing_id, &text, &audio_paths).await;
+    document_paths.extend(kept_audio);
 
     // Determine what action to take
     let mut store = PairingStore::load()?;
     let action = determine_action(
         channel.name(),
-        &sender,
+        &pairing_id,
         &text,
-        &image_paths,
+        &[image_paths.as_slice(), document_paths.as_slice()].concat(),
         &mut store,
-        None, // Signal doesn't have usernames
-        display_name,
+        sender_info,
     )?;
 
     // Execute the action
-    if let Some(query_text) = execute_action(channel.as_ref(), &sender, action).await? {
+    if let Some(query_text) =
+        execute_action(channel.as_ref(), &pairing_id, action, &task_manager).await?
+    {
         // QueryClaude action - queue with task manager for debouncing
-        let text_with_images = build_text_with_images(&query_text, &image_paths);
-        let user_key = format!("{}:{}", channel.name(), sender);
+        let query_text =
+            prepare_query_text(&data_message, account, &query_text, group_sender.as_deref());
+        let text_with_images = build_text_with_images(&query_text, &image_paths, &document_paths);
+        let user_key = format!("{}:{}", channel.name(), pairing_id);
         let channel_clone = channel.clone();
-        let sender_clone = sender.clone();
+        let sender_clone = pairing_id.clone();
 
         task_manager
-            .process_message(user_key, text_with_images, move |messages| async move {
-                execute_claude_query(channel_clone, &sender_clone, messages).await;
-            })
+            .process_message_with_id(
+                user_key,
+                envelope.timestamp.map(|ts| ts.to_string()),
+                text_with_images,
+                move |messages, steering| async move {
+                    execute_claude_query(
+                        channel_clone,
+                        &sender_clone,
+                        &sender_clone,
+                        messages,
+                        steering,
+                    )
+                    .await;
+                },
+            )
             .await;
     }
 
@@ -770,3 +1330,100 @@ pub async fn verify_account(phone_number: &str, code: &str) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_id_roundtrip() {
+        let group_id = "ab+cd/ef==";
+        let encoded = encode_group_id(group_id);
+        assert_eq!(encoded, "ab-cd_ef==");
+        assert!(!encoded.contains('/'));
+        assert_eq!(decode_group_id(&encoded), group_id);
+    }
+
+    #[test]
+    fn test_group_message_addressing() {
+        let account = &Account {
+            number: "+15550001111".to_string(),
+            uuid: Some("0a1b2c3d-0000-4000-8000-000000000001".to_string()),
+        };
+        let parse = |v: Value| -> DataMessage { serde_json::from_value(v).unwrap() };
+
+        let mentioned = parse(serde_json::json!({
+            "message": "\u{FFFC} what's the weather?",
+            "groupInfo": { "groupId": "abc=" },
+            "mentions": [{ "number": account.number, "start": 0, "length": 1 }]
+        }));
+        assert!(is_addressed_to_bot(&mentioned, account));
+
+        // Members who can't see our number mention us by UUID
+        let by_uuid = parse(serde_json::json!({
+            "message": "\u{FFFC} hi",
+            "groupInfo": { "groupId": "abc=" },
+            "mentions": [{ "uuid": account.uuid, "start": 0, "length": 1 }]
+        }));
+        assert!(is_addressed_to_bot(&by_uuid, account));
+
+        let quoted = parse(serde_json::json!({
+            "message": "and tomorrow?",
+            "quote": { "authorNumber": account.number, "text": "Sunny" }
+        }));
+        assert!(is_addressed_to_bot(&quoted, account));
+
+        let chatter = parse(serde_json::json!({
+            "message": "dinner at 7?",
+            "groupInfo": { "groupId": "abc=" },
+            "mentions": [{ "number": "+15559998888" }]
+        }));
+        assert!(!is_addressed_to_bot(&chatter, account));
+    }
+
+    #[test]
+    fn test_shared_contacts() {
+        let message: DataMessage = serde_json::from_value(serde_json::json!({
+            "message": null,
+            "sharedContacts": [
+                {
+                    "name": {"display": null, "given": "Jane", "family": "Doe"},
+                    "phone": [{"value": "+15551234567", "type": "MOBILE"}],
+                    "email": [],
+                    "organization": ""
+                },
+                {"name": null}
+            ]
+        }))
+        .unwrap();
+
+        let contacts: Vec<SharedContact> = message
+            .shared_contacts
+            .iter()
+            .map(ContactCard::to_shared)
+            .collect();
+        assert_eq!(
+            contacts[0],
+            SharedContact {
+                name: "Jane Doe".to_string(),
+                phone_numbers: vec!["+15551234567".to_string()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(contacts[1].name, "Unknown");
+    }
+
+    #[test]
+    fn test_event_stream_parser() {
+        let mut parser = EventStreamParser::default();
+        assert!(
+            parser
+                .push(b":keep-alive\n\nevent:receive\ndata:{\"a\":")
+                .is_empty()
+        );
+        assert_eq!(
+            parser.push(b"1}\r\n\r\nevent:other\ndata:x\n\ndata: {}\n\n"),
+            vec!["{\"a\":1}".to_string(), "{}".to_string()]
+        );
+    }
+}
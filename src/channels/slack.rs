@@ -7,9 +7,12 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+use super::registry::ChannelRegistry;
 use super::{
-    Channel, TypingGuard, UserTaskManager, build_text_with_images, determine_action,
-    execute_action, execute_claude_query,
+    Button, Channel, CommandResult, MessageAction, SenderInfo, TypingGuard, UserTaskManager,
+    add_voice_transcripts, build_text_with_images, determine_action, execute_action,
+    execute_claude_query, handle_button, handle_edit, handle_reaction, inbox_path,
+    is_trusted_sender, markdown, max_attachment_bytes, oversized_attachment_note, process_command,
 };
 use crate::config::{self, SlackConfig};
 use crate::pairing::PairingStore;
@@ -30,12 +33,6 @@ fn get_slack_attachments_dir() -> Result<PathBuf> {
 /// Download a file from Slack and save it locally
 /// Requires the bot token for authentication
 async fn download_slack_file(file: &SlackFile, bot_token: &str) -> Result<PathBuf> {
-    let url = file
-        .url_private_download
-        .as_ref()
-        .or(file.url_private.as_ref())
-        .ok_or_else(|| anyhow::anyhow!("No download URL for file"))?;
-
     let file_name = file.name.as_deref().unwrap_or("unknown");
     let file_id = &file.id;
 
@@ -48,7 +45,21 @@ async fn download_slack_file(file: &SlackFile, bot_token: &str) -> Result<PathBu
         return Ok(local_path);
     }
 
-    // Download with authorization header
+    let bytes = fetch_slack_file(file, bot_token).await?.bytes().await?;
+    std::fs::write(&local_path, &bytes)?;
+
+    info!("Downloaded Slack file to {:?}", local_path);
+    Ok(local_path)
+}
+
+/// Start downloading a file from Slack, authorized with the bot token
+async fn fetch_slack_file(file: &SlackFile, bot_token: &str) -> Result<reqwest::Response> {
+    let url = file
+        .url_private_download
+        .as_ref()
+        .or(file.url_private.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("No download URL for file"))?;
+
     let client = reqwest::Client::new();
     let response = client
         .get(url.as_str())
@@ -60,11 +71,72 @@ async fn download_slack_file(file: &SlackFile, bot_token: &str) -> Result<PathBu
         anyhow::bail!("Failed to download file: {}", response.status());
     }
 
-    let bytes = response.bytes().await?;
-    std::fs::write(&local_path, &bytes)?;
+    Ok(response)
+}
 
-    info!("Downloaded Slack file to {:?}", local_path);
-    Ok(local_path)
+/// Download documents (anything that isn't an image or audio) into the user's
+/// inbox. Files over the size limit are skipped and noted in the message text.
+async fn save_documents(
+    files: &[SlackFile],
+    bot_token: &str,
+    user_id: &str,
+    text: &mut String,
+) -> Vec<PathBuf> {
+    let mut document_paths = Vec::new();
+    if !is_trusted_sender("slack", user_id) {
+        return document_paths;
+    }
+
+    let limit = max_attachment_bytes();
+    for file in files {
+        if is_image_file(file) || is_audio_file(file) {
+            continue;
+        }
+        let file_name = file.name.as_deref().unwrap_or(file.id.0.as_str());
+
+        let bytes = match fetch_slack_file(file, bot_token).await {
+            Ok(response) if response.content_length().unwrap_or(0) > limit => None,
+            Ok(response) => match response.bytes().await {
+                Ok(bytes) if bytes.len() as u64 > limit => None,
+                Ok(bytes) => Some(bytes),
+                Err(e) => {
+                    warn!("Failed to download Slack file: {}", e);
+                    continue;
+                }
+            },
+            Err(e) => {
+                warn!("Failed to download Slack file: {}", e);
+                continue;
+            }
+        };
+
+        let Some(bytes) = bytes else {
+            info!("Slack file {} is over the size limit", file_name);
+            *text = format!(
+                "{}\n\n{}",
+                text,
+                oversized_attachment_note(file_name, limit)
+            )
+            .trim()
+            .to_string();
+            continue;
+        };
+
+        let saved = inbox_path("slack", user_id, file_name).and_then(|dest| {
+            std::fs::write(&dest, &bytes)
+                .map(|_| dest)
+                .map_err(Into::into)
+        });
+        match saved {
+            Ok(path) => {
+                info!("Saved Slack file to {:?}", path);
+                document_paths.push(path);
+            }
+            Err(e) => warn!("Failed to save Slack file {}: {}", file_name, e),
+        }
+    }
+
+    document_paths
 }
 
 /// Check if a file is an image based on mimetype
@@ -75,6 +147,14 @@ fn is_image_file(file: &SlackFile) -> bool {
         .unwrap_or(false)
 }
 
+/// Check if a file is an audio clip based on mimetype
+fn is_audio_file(file: &SlackFile) -> bool {
+    file.mimetype
+        .as_ref()
+        .map(|m| m.to_string().starts_with("audio/"))
+        .unwrap_or(false)
+}
+
 /// Set suggested prompts for a new thread based on available skills
 async fn set_suggested_prompts(
     client: &Arc<SlackHyperClient>,
@@ -118,34 +198,6 @@ async fn set_suggested_prompts(
     }
 }
 
-// ============================================================================
-// Markdown to Slack mrkdwn conversion
-// ============================================================================
-
-/// Convert standard Markdown to Slack's mrkdwn format
-fn markdown_to_mrkdwn(text: &str) -> String {
-    let mut result = text.to_string();
-
-    // Convert bold: **text** -> *text*
-    // Be careful not to convert already-correct single asterisks
-    // Use a simple approach: replace ** with a placeholder, then convert
-    result = result.replace("**", "\x00BOLD\x00");
-    result = result.replace("\x00BOLD\x00", "*");
-
-    // Convert italic: *text* -> _text_ (but only single asterisks not part of bold)
-    // This is tricky because * is used for bold in mrkdwn
-    // Skip this for now as it can conflict with bullet points
-
-    // Convert links: [text](url) -> <url|text>
-    let link_re = regex_lite::Regex::new(r"\[([^\]]+)\]\(([^)]+)\)").unwrap();
-    result = link_re.replace_all(&result, "<$2|$1>").to_string();
-
-    // Convert inline code: `code` stays the same in Slack
-    // Convert code blocks: ```code``` stays the same in Slack
-
-    result
-}
-
 // ============================================================================
 // Channel Implementation
 // ============================================================================
@@ -158,6 +210,8 @@ pub struct SlackChannel {
     channel_id: SlackChannelId,
     /// Thread timestamp - required for AI Assistant apps to reply in the correct thread
     thread_ts: Option<SlackTs>,
+    /// Ids of messages sent since `take_sent_message_ids` was last called
+    sent: std::sync::Mutex<Vec<String>>,
 }
 
 impl SlackChannel {
@@ -172,10 +226,61 @@ impl SlackChannel {
             token,
             channel_id,
             thread_ts,
+            sent: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    fn remember_sent(&self, response: &SlackApiChatPostMessageResponse) {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push(message_key(&response.channel, &response.ts));
         }
     }
 }
 
+/// Message timestamps are only unique within a conversation
+fn message_key(channel_id: &SlackChannelId, ts: &SlackTs) -> String {
+    format!("{}:{}", channel_id, ts)
+}
+
+/// Longest text Slack accepts in a section block
+const SECTION_TEXT_LIMIT: usize = 3000;
+
+/// Build message content with Block Kit buttons under the text.
+///
+/// Each row becomes an actions block; a button's action doubles as its
+/// action_id so taps can be routed without extra state.
+fn message_with_buttons(message: &str, buttons: &[Vec<Button>]) -> SlackMessageContent {
+    let mrkdwn_message = markdown::to_slack_mrkdwn(message);
+
+    let mut blocks: Vec<SlackBlock> = Vec::new();
+    let mut rest = mrkdwn_message.as_str();
+    while !rest.is_empty() {
+        let mut cut = rest.len().min(SECTION_TEXT_LIMIT);
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        blocks.push(SlackSectionBlock::new().with_text(md!(rest[..cut])).into());
+        rest = &rest[cut..];
+    }
+
+    for row in buttons {
+        let elements = row
+            .iter()
+            .map(|button| {
+                SlackBlockButtonElement::new(pt!(button.label.clone()))
+                    .with_action_id(button.action.clone().into())
+                    .with_value(button.action.clone())
+                    .into()
+            })
+            .collect();
+        blocks.push(SlackActionsBlock::new(elements).into());
+    }
+
+    SlackMessageContent::new()
+        .with_text(mrkdwn_message)
+        .with_blocks(blocks)
+}
+
 #[async_trait]
 impl Channel for SlackChannel {
     fn name(&self) -> &'static str {
@@ -194,7 +299,7 @@ impl Channel for SlackChannel {
         let session = self.client.open_session(&self.token);
 
         // Convert markdown to Slack's mrkdwn format
-        let mrkdwn_message = markdown_to_mrkdwn(message);
+        let mrkdwn_message = markdown::to_slack_mrkdwn(message);
 
         // Build request with thread_ts if available (required for AI Assistant apps)
         let mut request = SlackApiChatPostMessageRequest::new(
@@ -212,6 +317,7 @@ impl Channel for SlackChannel {
         match session.chat_post_message(&request).await {
             Ok(response) => {
                 info!("Message sent successfully, ts: {:?}", response.ts);
+                self.remember_sent(&response);
                 Ok(())
             }
             Err(e) => {
@@ -221,6 +327,48 @@ impl Channel for SlackChannel {
         }
     }
 
+    async fn send_message_with_buttons(
+        &self,
+        message: &str,
+        buttons: &[Vec<Button>],
+    ) -> Result<()> {
+        let session = self.client.open_session(&self.token);
+
+        let mut request = SlackApiChatPostMessageRequest::new(
+            self.channel_id.clone(),
+            message_with_buttons(message, buttons),
+        );
+        if let Some(ts) = &self.thread_ts {
+            request = request.with_thread_ts(ts.clone());
+        }
+
+        let response = session.chat_post_message(&request).await?;
+        self.remember_sent(&response);
+        Ok(())
+    }
+
+    fn max_message_length(&self) -> Option<usize> {
+        // Keeps each part within a single Block Kit section
+        Some(SECTION_TEXT_LIMIT)
+    }
+
+    fn reply_address(&self) -> Option<String> {
+        Some(match &self.thread_ts {
+            Some(ts) => format!("{}:{}", self.channel_id, ts),
+            None => self.channel_id.to_string(),
+        })
+    }
+
+    fn for_user(&self, user_id: &str) -> Option<Arc<dyn Channel>> {
+        // Posting to a user id opens the app's DM with them
+        Some(Arc::new(SlackChannel::new(
+            self.client.clone(),
+            self.token.clone(),
+            SlackChannelId::new(user_id.to_string()),
+            None,
+        )))
+    }
+
     async fn send_message_with_attachments(
         &self,
         message: &str,
@@ -286,7 +434,7 @@ impl Channel for SlackChannel {
             .with_channel_id(self.channel_id.clone());
 
         if !message.is_empty() {
-            let mrkdwn_message = markdown_to_mrkdwn(message);
+            let mrkdwn_message = markdown::to_slack_mrkdwn(message);
             complete_req = complete_req.with_initial_comment(mrkdwn_message);
         }
 
@@ -303,6 +451,13 @@ impl Channel for SlackChannel {
         Ok(())
     }
 
+    fn take_sent_message_ids(&self) -> Vec<String> {
+        self.sent
+            .lock()
+            .map(|mut sent| std::mem::take(&mut *sent))
+            .unwrap_or_default()
+    }
+
     fn start_typing(&self) -> TypingGuard {
         // For Slack AI assistants, we use assistant.threads.setStatus
         // to show a "thinking" indicator
@@ -399,7 +554,7 @@ pub async fn validate_credentials(bot_token: &str, app_token: &str) -> Result<St
 }
 
 /// Run the Slack bot using Socket Mode
-pub async fn run(config: SlackConfig) -> Result<()> {
+pub async fn run(config: SlackConfig, registry: Arc<ChannelRegistry>) -> Result<()> {
     // Ensure rustls crypto provider is installed
     let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
 
@@ -415,8 +570,25 @@ pub async fn run(config: SlackConfig) -> Result<()> {
     let bot_user_id = auth_response.user_id.clone();
     info!("Connected as bot user: {}", bot_user_id);
 
+    // Posting to a user id opens the app's DM with them. Replies that failed
+    // come back as "channel:thread_ts" to be retried in their thread.
+    let registry_client = client.clone();
+    let registry_token = bot_token.clone();
+    registry.register("slack", move |address| {
+        let (channel_id, thread_ts) = match address.split_once(':') {
+            Some((channel_id, ts)) => (channel_id, Some(SlackTs::new(ts.to_string()))),
+            None => (address, None),
+        };
+        Some(Arc::new(SlackChannel::new(
+            registry_client.clone(),
+            registry_token.clone(),
+            SlackChannelId::new(channel_id.to_string()),
+            thread_ts,
+        )) as Arc<dyn Channel>)
+    });
+
     // Create shared task manager for per-user message handling
-    let task_manager = UserTaskManager::new();
+    let task_manager = UserTaskManager::for_channel("slack");
 
     // Create user state
     let user_state = SlackUserState {
@@ -492,6 +664,16 @@ async fn handle_push_events(
                 }
             });
         }
+        SlackEventCallbackBody::ReactionAdded(reaction) => {
+            if let SlackReactionsItem::Message(message) = &reaction.item {
+                record_slack_reaction(&reaction.user, &reaction.reaction, message, false);
+            }
+        }
+        SlackEventCallbackBody::ReactionRemoved(reaction) => {
+            if let SlackReactionsItem::Message(message) = &reaction.item {
+                record_slack_reaction(&reaction.user, &reaction.reaction, message, true);
+            }
+        }
         SlackEventCallbackBody::AssistantThreadStarted(thread_event) => {
             // User opened the assistant - send suggested prompts immediately
             let states = user_state_storage.read().await;
@@ -541,6 +723,52 @@ async fn handle_push_events(
     Ok(())
 }
 
+/// Log a reaction to one of our messages as feedback
+fn record_slack_reaction(
+    user: &SlackUserId,
+    reaction: &SlackReactionName,
+    message: &SlackHistoryMessage,
+    removed: bool,
+) {
+    let Some(channel_id) = &message.origin.channel else {
+        return;
+    };
+    handle_reaction(
+        "slack",
+        &message_key(channel_id, &message.origin.ts),
+        user.as_ref(),
+        &reaction_emoji(reaction.as_ref()),
+        removed,
+    );
+}
+
+/// Slack reports reactions by name; turn the common ones into the emoji
+/// itself so feedback reads the same as on other channels
+fn reaction_emoji(name: &str) -> String {
+    // "+1::skin-tone-3" is still a thumbs up
+    let name = name.split("::").next().unwrap_or(name);
+    let emoji = match name {
+        "+1" | "thumbsup" => "👍",
+        "-1" | "thumbsdown" => "👎",
+        "heart" => "❤️",
+        "fire" => "🔥",
+        "tada" => "🎉",
+        "clap" => "👏",
+        "pray" => "🙏",
+        "ok_hand" => "👌",
+        "100" => "💯",
+        "white_check_mark" | "heavy_check_mark" => "✅",
+        "x" => "❌",
+        "cry" => "😢",
+        "sob" => "😭",
+        "rage" => "😡",
+        "broken_heart" => "💔",
+        "confused" => "😕",
+        _ => return format!(":{}:", name),
+    };
+    emoji.to_string()
+}
+
 async fn handle_message_event(
     event: SlackMessageEvent,
     client: Arc<SlackHyperClient>,
@@ -550,6 +778,10 @@ async fn handle_message_event(
     task_manager: Arc<UserTaskManager>,
     user_threads: Arc<RwLock<HashMap<String, String>>>,
 ) -> Result<()> {
+    if event.subtype == Some(SlackMessageEventType::MessageChanged) {
+        return handle_message_changed(event, client, token, bot_user_id, task_manager).await;
+    }
+
     // Skip messages from bots (including ourselves)
     if event.sender.bot_id.is_some() {
         return Ok(());
@@ -582,28 +814,45 @@ async fn handle_message_event(
         None => String::new(),
     };
 
-    // Download any image files in the message
+    // Download any image files and audio clips in the message
     let mut image_paths: Vec<PathBuf> = Vec::new();
+    let mut audio_paths: Vec<PathBuf> = Vec::new();
     if let Some(content) = &event.content
         && let Some(files) = &content.files
     {
         for file in files {
-            if is_image_file(file) {
-                match download_slack_file(file, &bot_token_str).await {
-                    Ok(path) => image_paths.push(path),
-                    Err(e) => warn!("Failed to download Slack file: {}", e),
-                }
+            let paths = if is_image_file(file) {
+                &mut image_paths
+            } else if is_audio_file(file) {
+                &mut audio_paths
+            } else {
+                continue;
+            };
+            match download_slack_file(file, &bot_token_str).await {
+                Ok(path) => paths.push(path),
+                Err(e) => warn!("Failed to download Slack file: {}", e),
             }
         }
     }
 
-    // Skip if no text and no images
-    if text.is_empty() && image_paths.is_empty() {
+    // Save other files into the user's inbox
+    let mut text = text;
+    let mut document_paths = match event.content.as_ref().and_then(|c| c.files.as_ref()) {
+        Some(files) => save_documents(files, &bot_token_str, user_id.as_ref(), &mut text).await,
+        None => Vec::new(),
+    };
+
+    // Skip if no text, images, audio or documents
+    if text.is_empty()
+        && image_paths.is_empty()
+        && audio_paths.is_empty()
+        && document_paths.is_empty()
+    {
         return Ok(());
     }
 
     info!(
-        "Message from {} in channel {} (thread: {:?}, ts: {}, subtype: {:?}): {}{}",
+        "Message from {} in channel {} (thread: {:?}, ts: {}, subtype: {:?}): {}{}{}",
         user_id,
         channel_id,
         thread_ts,
@@ -614,6 +863,11 @@ async fn handle_message_event(
             String::new()
         } else {
             format!(" [{} image(s)]", image_paths.len())
+        },
+        if document_paths.is_empty() {
+            String::new()
+        } else {
+            format!(" [{} document(s)]", document_paths.len())
         }
     );
 
@@ -641,7 +895,7 @@ async fn handle_message_event(
     }
 
     // Get user info for display name
-    let (username, display_name) = get_user_info(&client, &token, &user_id).await;
+    let sender = get_user_info(&client, &token, &user_id).await;
 
     // Create channel wrapper with thread_ts for proper threading
     let channel: Arc<dyn Channel> = Arc::new(SlackChannel::new(
@@ -660,6 +914,11 @@ async fn handle_message_event(
         None => user_id_str.clone(),
     };
 
+    // Voice notes become part of the message text, or are passed on as files
+    let (text, kept_audio) =
+        add_voice_transcripts(channel.as_ref(), &user_id_str, &text, &audio_paths).await;
+    document_paths.extend(kept_audio);
+
     // Determine what action to take
     let mut store = PairingStore::load()?;
 
@@ -668,32 +927,141 @@ async fn handle_message_event(
         channel.name(),
         &user_id_str,
         &text,
-        &image_paths,
+        &[image_paths.as_slice(), document_paths.as_slice()].concat(),
         &mut store,
-        username,
-        display_name,
+        sender,
     )?;
 
     // Execute the action - use session_user_id (includes thread) for Claude queries
-    if let Some(query_text) = execute_action(channel.as_ref(), &user_id_str, action).await? {
+    if let Some(query_text) =
+        execute_action(channel.as_ref(), &user_id_str, action, &task_manager).await?
+    {
         // QueryClaude action - queue with task manager for debouncing
-        let text_with_images = build_text_with_images(&query_text, &image_paths);
+        let text_with_images = build_text_with_images(&query_text, &image_paths, &document_paths);
         // Use thread-aware key for task manager too
         let user_key = format!("{}:{}", channel.name(), session_user_id);
         let channel_clone = channel.clone();
         let session_user_id_clone = session_user_id.clone();
 
         task_manager
-            .process_message(user_key, text_with_images, move |messages| async move {
-                // Use session_user_id so each thread gets its own Claude session
-                execute_claude_query(channel_clone, &session_user_id_clone, messages).await;
-            })
+            .process_message_with_id(
+                user_key,
+                Some(event.origin.ts.to_string()),
+                text_with_images,
+                move |messages, steering| async move {
+                    // Use session_user_id so each thread gets its own Claude session,
+                    // and the user's own id for their role and files
+                    execute_claude_query(
+                        channel_clone,
+                        &user_id_str,
+                        &session_user_id_clone,
+                        messages,
+                        steering,
+                    )
+                    .await;
+                },
+            )
             .await;
     }
 
     Ok(())
 }
 
+/// Handle a `message_changed` event: if the user edited a message that
+/// hasn't been answered yet, answer the new text instead
+async fn handle_message_changed(
+    event: SlackMessageEvent,
+    client: Arc<SlackHyperClient>,
+    token: SlackApiToken,
+    bot_user_id: SlackUserId,
+    task_manager: Arc<UserTaskManager>,
+) -> Result<()> {
+    let Some(edited) = event.message else {
+        return Ok(());
+    };
+    let (Some(user_id), Some(channel_id)) = (edited.sender.user, event.origin.channel) else {
+        return Ok(());
+    };
+    if edited.sender.bot_id.is_some() || user_id == bot_user_id {
+        return Ok(());
+    }
+
+    let text = edited
+        .content
+        .and_then(|c| c.text)
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    // Link previews also arrive as message_changed, with the text unchanged
+    let previous_text = event
+        .previous_message
+        .and_then(|m| m.content)
+        .and_then(|c| c.text)
+        .unwrap_or_default();
+    if text.is_empty() || text.starts_with('/') || text == previous_text.trim() {
+        return Ok(());
+    }
+
+    let message_id = edited.ts.to_string();
+    let thread_ts = match event.origin.thread_ts {
+        Some(ts) => Some(ts),
+        None => edited_thread_ts(&client, &token, &channel_id, &edited.ts).await,
+    };
+    let session_user_id = match &thread_ts {
+        Some(ts) => format!("{}:{}", user_id, ts),
+        None => user_id.to_string(),
+    };
+    let channel: Arc<dyn Channel> = Arc::new(SlackChannel::new(
+        client.clone(),
+        token.clone(),
+        channel_id,
+        thread_ts,
+    ));
+    handle_edit(
+        channel,
+        user_id.as_ref(),
+        &session_user_id,
+        &message_id,
+        text,
+        &task_manager,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Find the thread an edited message was posted in. The edit event carries
+/// the message's `thread_ts`, but the event model drops it, so ask Slack for
+/// the message's thread: a reply's thread starts with the parent message.
+async fn edited_thread_ts(
+    client: &SlackHyperClient,
+    token: &SlackApiToken,
+    channel_id: &SlackChannelId,
+    ts: &SlackTs,
+) -> Option<SlackTs> {
+    let request =
+        SlackApiConversationsRepliesRequest::new(channel_id.clone(), ts.clone()).with_limit(1);
+    match client
+        .open_session(token)
+        .conversations_replies(&request)
+        .await
+    {
+        Ok(response) => response
+            .messages
+            .into_iter()
+            .next()
+            .map(|parent| parent.origin.ts)
+            .filter(|parent_ts| parent_ts != ts),
+        Err(e) => {
+            warn!(
+                "Failed to look up the thread of edited message {}: {}",
+                ts, e
+            );
+            None
+        }
+    }
+}
+
 /// Handle @mention events in channels
 async fn handle_app_mention_event(
     event: SlackAppMentionEvent,
@@ -736,13 +1104,19 @@ async fn handle_app_mention_event(
     // Check if user is approved before proceeding
     let user_id_str = user_id.to_string();
     let mut store = PairingStore::load()?;
+    let sender = get_user_info(&client, &token, &user_id).await;
+    let settings = crate::config::Config::load()
+        .map(|c: crate::config::Config| c.channel_settings("slack"))
+        .unwrap_or_default();
+    let identifiers = sender.identifiers(&user_id_str);
+
+    // Blocked users get no reply at all
+    if store.is_blocked("slack", &user_id_str) || settings.is_blocked(&identifiers) {
+        return Ok(());
+    }
 
     if !store.is_approved("slack", &user_id_str) {
-        let settings = crate::config::Config::load()
-            .map(|c: crate::config::Config| c.channel_settings("slack"))
-            .unwrap_or_default();
-
-        if !settings.auto_approve {
+        if !settings.auto_approve && !settings.is_allowed(&identifiers) {
             send_ephemeral_message(
                 &client,
                 &token,
@@ -755,11 +1129,11 @@ async fn handle_app_mention_event(
         }
 
         // Auto-approve the user
-        let (username, display_name) = get_user_info(&client, &token, &user_id).await;
-        store.auto_approve("slack", &user_id_str, username, display_name)?;
+        store.auto_approve("slack", &user_id_str, sender.username, sender.display_name)?;
     }
 
-    let onboarding_complete = crate::onboarding::is_complete_for_user("slack", &user_id_str)?;
+    let onboarding_complete =
+        crate::onboarding::is_complete_for_user(&store, "slack", &user_id_str)?;
     if !onboarding_complete {
         send_ephemeral_message(
             &client,
@@ -785,19 +1159,32 @@ async fn handle_app_mention_event(
         }
     }
 
-    // Download any image files in the message
+    // Download any image files and audio clips in the message
     let mut image_paths: Vec<PathBuf> = Vec::new();
+    let mut audio_paths: Vec<PathBuf> = Vec::new();
     if let Some(files) = &event.content.files {
         for file in files {
-            if is_image_file(file) {
-                match download_slack_file(file, &bot_token_str).await {
-                    Ok(path) => image_paths.push(path),
-                    Err(e) => warn!("Failed to download Slack file: {}", e),
-                }
+            let paths = if is_image_file(file) {
+                &mut image_paths
+            } else if is_audio_file(file) {
+                &mut audio_paths
+            } else {
+                continue;
+            };
+            match download_slack_file(file, &bot_token_str).await {
+                Ok(path) => paths.push(path),
+                Err(e) => warn!("Failed to download Slack file: {}", e),
             }
         }
     }
 
+    // Save other files into the user's inbox
+    let mut text = text;
+    let mut document_paths = match &event.content.files {
+        Some(files) => save_documents(files, &bot_token_str, &user_id_str, &mut text).await,
+        None => Vec::new(),
+    };
+
     // Create channel wrapper - always reply in thread
     let channel: Arc<dyn Channel> = Arc::new(SlackChannel::new(
         client.clone(),
@@ -809,15 +1196,29 @@ async fn handle_app_mention_event(
     // Session key includes thread for continuity
     let session_user_id = format!("{}:{}", user_id, thread_ts);
 
-    let text_with_images = build_text_with_images(&text, &image_paths);
+    let (text, kept_audio) =
+        add_voice_transcripts(channel.as_ref(), &user_id_str, &text, &audio_paths).await;
+    document_paths.extend(kept_audio);
+    let text_with_images = build_text_with_images(&text, &image_paths, &document_paths);
     let user_key = format!("{}:{}", channel.name(), session_user_id);
     let channel_clone = channel.clone();
     let session_user_id_clone = session_user_id.clone();
 
     task_manager
-        .process_message(user_key, text_with_images, move |messages| async move {
-            execute_claude_query(channel_clone, &session_user_id_clone, messages).await;
-        })
+        .process_message(
+            user_key,
+            text_with_images,
+            move |messages, steering| async move {
+                execute_claude_query(
+                    channel_clone,
+                    &user_id_str,
+                    &session_user_id_clone,
+                    messages,
+                    steering,
+                )
+                .await;
+            },
+        )
         .await;
 
     Ok(())
@@ -844,12 +1245,13 @@ async fn send_ephemeral_message(
     }
 }
 
-/// Get user info from Slack API
+/// Get user info from Slack API. The team and email address (which needs
+/// the `users:read.email` scope) let allow and block lists match the sender.
 async fn get_user_info(
     client: &Arc<SlackHyperClient>,
     token: &SlackApiToken,
     user_id: &SlackUserId,
-) -> (Option<String>, Option<String>) {
+) -> SenderInfo {
     let session = client.open_session(token);
 
     match session
@@ -857,46 +1259,232 @@ async fn get_user_info(
         .await
     {
         Ok(response) => {
-            let username = response.user.name.clone();
-            let display_name = response
-                .user
-                .profile
-                .as_ref()
+            let user = response.user;
+            let profile = user.profile.as_ref();
+            let display_name = profile
                 .and_then(|p| p.display_name.clone())
-                .or_else(|| {
-                    response
-                        .user
-                        .profile
-                        .as_ref()
-                        .and_then(|p| p.real_name.clone())
-                });
-            (username, display_name)
+                .or_else(|| profile.and_then(|p| p.real_name.clone()));
+
+            let mut sender = SenderInfo::new(user.name.clone(), display_name);
+            if let Some(team) = user
+                .team_id
+                .as_ref()
+                .or(profile.and_then(|p| p.team.as_ref()))
+            {
+                sender.aliases.push(format!("team:{}", team));
+            }
+            if let Some(email) = profile.and_then(|p| p.email.as_ref()) {
+                sender.aliases.push(email.to_string());
+            }
+            sender
         }
         Err(e) => {
             warn!("Failed to get user info for {}: {}", user_id, e);
-            (None, None)
+            SenderInfo::default()
         }
     }
 }
 
 async fn handle_interaction_events(
-    _event: SlackInteractionEvent,
-    _client: Arc<SlackHyperClient>,
-    _user_state_storage: SlackClientEventsUserState,
+    event: SlackInteractionEvent,
+    client: Arc<SlackHyperClient>,
+    user_state_storage: SlackClientEventsUserState,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Handle interactive components (buttons, menus, etc.) if needed
-    debug!("Received interaction event");
+    let SlackInteractionEvent::BlockActions(event) = event else {
+        debug!("Received interaction event");
+        return Ok(());
+    };
+
+    let states = user_state_storage.read().await;
+    let user_state = states
+        .get_user_state::<SlackUserState>()
+        .ok_or("Missing user state")?;
+    let bot_token = user_state.bot_token.clone();
+    let task_manager = user_state.task_manager.clone();
+    drop(states);
+
+    let Some(user_id) = event.user.as_ref().map(|u| u.id.to_string()) else {
+        return Ok(());
+    };
+    let channel_id = match (&event.channel, &event.container) {
+        (Some(channel), _) => channel.id.clone(),
+        (None, SlackInteractionActionContainer::Message(container)) => {
+            match &container.channel_id {
+                Some(id) => id.clone(),
+                None => return Ok(()),
+            }
+        }
+        (None, _) => return Ok(()),
+    };
+    let thread_ts = event
+        .message
+        .as_ref()
+        .and_then(|m| m.origin.thread_ts.clone());
+
+    // Same session key as messages in this conversation
+    let session_user_id = match &thread_ts {
+        Some(ts) => format!("{}:{}", user_id, ts),
+        None => user_id.clone(),
+    };
+
+    let channel: Arc<dyn Channel> =
+        Arc::new(SlackChannel::new(client, bot_token, channel_id, thread_ts));
+
+    // Handle in the background so the interaction is acknowledged immediately
+    for action in event.actions.unwrap_or_default() {
+        let channel = channel.clone();
+        let user_id = user_id.clone();
+        let session_user_id = session_user_id.clone();
+        let task_manager = task_manager.clone();
+        tokio::spawn(async move {
+            info!("Button {} pressed by {}", action.action_id, user_id);
+            if let Err(e) = handle_button(
+                channel,
+                &user_id,
+                &session_user_id,
+                &action.action_id.0,
+                SenderInfo::default(),
+                &task_manager,
+            )
+            .await
+            {
+                warn!("Error handling button: {}", e);
+            }
+        });
+    }
+
     Ok(())
 }
 
+/// Slash command registered in the Slack app
+pub const SLASH_COMMAND: &str = "/cica";
+
 async fn handle_command_events(
-    _event: SlackCommandEvent,
-    _client: Arc<SlackHyperClient>,
-    _user_state_storage: SlackClientEventsUserState,
+    event: SlackCommandEvent,
+    client: Arc<SlackHyperClient>,
+    user_state_storage: SlackClientEventsUserState,
 ) -> Result<SlackCommandEventResponse, Box<dyn std::error::Error + Send + Sync>> {
-    // Handle slash commands if needed
-    debug!("Received command event");
-    Ok(SlackCommandEventResponse::new(
-        SlackMessageContent::new().with_text("OK".to_string()),
-    ))
+    let states = user_state_storage.read().await;
+    let user_state = states
+        .get_user_state::<SlackUserState>()
+        .ok_or("Missing user state")?;
+    let bot_token = user_state.bot_token.clone();
+    let task_manager = user_state.task_manager.clone();
+    drop(states);
+
+    let command = slash_command_text(&event.command.0, event.text.as_deref().unwrap_or(""));
+    info!("Slash command from {}: {}", event.user_id, command);
+
+    let (response, buttons) =
+        match handle_slash_command(&event, &command, client, bot_token, task_manager).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Error handling slash command: {}", e);
+                (format!("Sorry, something went wrong: {}", e), Vec::new())
+            }
+        };
+
+    Ok(
+        SlackCommandEventResponse::new(message_with_buttons(&response, &buttons))
+            .with_response_type(SlackMessageResponseType::Ephemeral),
+    )
+}
+
+/// Turn a Slack slash command into the text command Cica understands.
+///
+/// `/cica cron list` becomes `/cron list`; other registered commands such as
+/// `/cron list` are passed through unchanged. A bare `/cica` shows the help.
+fn slash_command_text(command: &str, text: &str) -> String {
+    let text = text.trim();
+    if command == SLASH_COMMAND {
+        if text.is_empty() {
+            "/commands".to_string()
+        } else {
+            format!("/{}", text.trim_start_matches('/'))
+        }
+    } else {
+        format!("{} {}", command, text).trim().to_string()
+    }
+}
+
+/// Run a slash command and return the ephemeral reply and its buttons
+async fn handle_slash_command(
+    event: &SlackCommandEvent,
+    command: &str,
+    client: Arc<SlackHyperClient>,
+    bot_token: SlackApiToken,
+    task_manager: Arc<UserTaskManager>,
+) -> Result<(String, Vec<Vec<Button>>)> {
+    let user_id = event.user_id.to_string();
+    let mut store = PairingStore::load()?;
+
+    if !store.is_approved("slack", &user_id) {
+        return Ok((
+            "Hi! I don't recognize you yet. Please send me a direct message to get started."
+                .to_string(),
+            Vec::new(),
+        ));
+    }
+
+    let onboarding_complete = crate::onboarding::is_complete_for_user(&store, "slack", &user_id)?;
+    let response =
+        match process_command(&mut store, "slack", &user_id, command, onboarding_complete)? {
+            CommandResult::Response(response) => response,
+            CommandResult::Buttons(text, buttons) => return Ok((text, buttons)),
+            CommandResult::CronRun(job_id) => {
+                // Run in the background; the result is posted to the conversation
+                let channel: Arc<dyn Channel> = Arc::new(SlackChannel::new(
+                    client,
+                    bot_token,
+                    event.channel_id.clone(),
+                    None,
+                ));
+                let action = MessageAction::ExecuteCronJob {
+                    job_id: job_id.clone(),
+                };
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        execute_action(channel.as_ref(), &user_id, action, &task_manager).await
+                    {
+                        warn!("Error running job from slash command: {}", e);
+                    }
+                });
+                format!("Starting job {}.", job_id)
+            }
+            CommandResult::Stop => {
+                if task_manager.stop("slack", &user_id).await {
+                    "Stopped.".to_string()
+                } else {
+                    "I'm not working on anything right now.".to_string()
+                }
+            }
+            CommandResult::NotACommand => format!(
+                "Unknown command. Try `{} commands` to see what I can do.",
+                SLASH_COMMAND
+            ),
+        };
+
+    Ok((response, Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reaction_emoji() {
+        assert_eq!(reaction_emoji("+1"), "👍");
+        assert_eq!(reaction_emoji("-1::skin-tone-4"), "👎");
+        assert_eq!(reaction_emoji("partyparrot"), ":partyparrot:");
+    }
+
+    #[test]
+    fn test_slash_command_text() {
+        assert_eq!(slash_command_text("/cica", ""), "/commands");
+        assert_eq!(slash_command_text("/cica", "new"), "/new");
+        assert_eq!(slash_command_text("/cica", " cron list "), "/cron list");
+        assert_eq!(slash_command_text("/cica", "/skills"), "/skills");
+        assert_eq!(slash_command_text("/cron", "list"), "/cron list");
+        assert_eq!(slash_command_text("/new", ""), "/new");
+    }
 }
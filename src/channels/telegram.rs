@@ -1,20 +1,42 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::path::PathBuf;
+use http_body_util::Full;
+use hyper::body::{Body, Bytes};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
+use subtle::ConstantTimeEq;
 use teloxide::net::Download;
 use teloxide::prelude::*;
-use teloxide::types::{BotCommand, ChatAction, PhotoSize};
-use tokio::sync::oneshot;
+use teloxide::stop::{StopFlag, StopToken, mk_stop_token};
+use teloxide::types::{
+    AllowedUpdate, BotCommand, ChatAction, Contact, FileMeta, InlineKeyboardButton,
+    InlineKeyboardMarkup, InputFile, Me, MessageId, MessageReactionUpdated, ParseMode, PhotoSize,
+    ReactionType,
+};
+use teloxide::update_listeners::{StatefulListener, UpdateListener};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio_rustls::TlsAcceptor;
 use tracing::{debug, info, warn};
 
+use super::registry::ChannelRegistry;
 use super::{
-    Channel, TypingGuard, UserTaskManager, build_text_with_images, determine_action,
-    execute_action, execute_claude_query,
+    Button, Channel, SenderInfo, SharedContact, SharedLocation, TypingGuard, UserTaskManager,
+    add_reply_context, add_shared_context, add_voice_transcripts, build_text_with_images,
+    button_member, determine_action, execute_action, execute_claude_query, format_group_message,
+    handle_button, handle_edit, handle_reaction, inbox_dir, inbox_path, is_image_path,
+    is_trusted_sender, markdown, max_attachment_bytes, oversized_attachment_note,
+    remember_location, strip_mention,
 };
-use crate::config::{self, TelegramConfig};
-use crate::pairing::PairingStore;
+use crate::api::read_body;
+use crate::config::{self, TelegramConfig, TelegramWebhookConfig};
+use crate::pairing::{self, PairingStore};
 
 // ============================================================================
 // Channel Implementation
@@ -24,12 +46,83 @@ use crate::pairing::PairingStore;
 pub struct TelegramChannel {
     bot: Bot,
     chat_id: ChatId,
+    /// Ids of messages sent since `take_sent_message_ids` was last called
+    sent: std::sync::Mutex<Vec<String>>,
+    /// Group member being answered
+    member: Option<String>,
 }
 
 impl TelegramChannel {
     pub fn new(bot: Bot, chat_id: ChatId) -> Self {
-        Self { bot, chat_id }
+        Self {
+            bot,
+            chat_id,
+            sent: std::sync::Mutex::new(Vec::new()),
+            member: None,
+        }
+    }
+
+    /// Answer a member of a group chat
+    pub fn with_member(mut self, member: Option<String>) -> Self {
+        self.member = member;
+        self
+    }
+
+    /// Create a channel to a user or group chat by pairing id
+    pub fn for_pairing_id(bot: Bot, user_id: &str) -> Option<Self> {
+        let chat_id = pairing::group_id(user_id).unwrap_or(user_id).parse().ok()?;
+        Some(Self::new(bot, ChatId(chat_id)))
+    }
+
+    /// Send a file as a video, photo or document depending on its type
+    async fn send_file(
+        &self,
+        path: &Path,
+        caption: Option<String>,
+        parse_mode: Option<ParseMode>,
+    ) -> Result<Message> {
+        let input_file = InputFile::file(path);
+        let sent = if is_video_file(path) {
+            let mut req = self.bot.send_video(self.chat_id, input_file);
+            if let Some(caption) = caption {
+                req = req.caption(caption);
+            }
+            if let Some(parse_mode) = parse_mode {
+                req = req.parse_mode(parse_mode);
+            }
+            req.await?
+        } else if !is_image_path(path) {
+            let mut req = self.bot.send_document(self.chat_id, input_file);
+            if let Some(caption) = caption {
+                req = req.caption(caption);
+            }
+            if let Some(parse_mode) = parse_mode {
+                req = req.parse_mode(parse_mode);
+            }
+            req.await?
+        } else {
+            let mut req = self.bot.send_photo(self.chat_id, input_file);
+            if let Some(caption) = caption {
+                req = req.caption(caption);
+            }
+            if let Some(parse_mode) = parse_mode {
+                req = req.parse_mode(parse_mode);
+            }
+            req.await?
+        };
+        Ok(sent)
     }
+
+    fn remember_sent(&self, message: &Message) {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push(message_key(message.chat.id, message.id));
+        }
+    }
+}
+
+/// Message ids are only unique within a chat
+fn message_key(chat_id: ChatId, message_id: MessageId) -> String {
+    format!("{}:{}", chat_id.0, message_id.0)
 }
 
 #[async_trait]
@@ -43,22 +136,52 @@ impl Channel for TelegramChannel {
     }
 
     async fn send_message(&self, message: &str) -> Result<()> {
-        self.bot.send_message(self.chat_id, message).await?;
+        let html = markdown::to_telegram_html(message);
+        let sent = self
+            .bot
+            .send_message(self.chat_id, html)
+            .parse_mode(ParseMode::Html)
+            .await;
+
+        // If Telegram rejects the markup, the text still gets through
+        let sent = match sent {
+            Ok(sent) => sent,
+            Err(e) => {
+                warn!(
+                    "Failed to send formatted message, sending plain text: {}",
+                    e
+                );
+                self.bot
+                    .send_message(self.chat_id, markdown::to_plain(message))
+                    .await?
+            }
+        };
+        self.remember_sent(&sent);
         Ok(())
     }
 
+    fn max_message_length(&self) -> Option<usize> {
+        Some(MAX_MESSAGE_LENGTH)
+    }
+
     async fn send_message_with_attachments(
         &self,
         message: &str,
         attachment_paths: &[PathBuf],
     ) -> Result<()> {
-        use teloxide::types::InputFile;
-
         // If no attachments, just send the text message
         if attachment_paths.is_empty() {
-            return self.send_message(message).await;
+            return self.send_reply(message, &[]).await;
         }
 
+        // Text too long for a caption goes in its own message(s) first
+        let message = if message.encode_utf16().count() > MAX_CAPTION_LENGTH {
+            self.send_reply(message, &[]).await?;
+            ""
+        } else {
+            message
+        };
+
         let is_first_attachment = |path: &PathBuf| -> bool {
             attachment_paths.first().map(|p| p == path).unwrap_or(false)
         };
@@ -70,25 +193,25 @@ impl Channel for TelegramChannel {
                 continue;
             }
 
-            let input_file = InputFile::file(path);
-            let caption = if is_first_attachment(path) && !message.is_empty() {
-                Some(message)
-            } else {
-                None
-            };
-
-            if is_video_file(path) {
-                let mut req = self.bot.send_video(self.chat_id, input_file);
-                if let Some(caption) = caption {
-                    req = req.caption(caption);
-                }
-                req.await?;
+            if is_first_attachment(path) && !message.is_empty() {
+                let html = markdown::to_telegram_html(message);
+                let sent = match self
+                    .send_file(path, Some(html), Some(ParseMode::Html))
+                    .await
+                {
+                    Ok(sent) => sent,
+                    Err(e) => {
+                        warn!(
+                            "Failed to send formatted caption, sending plain text: {}",
+                            e
+                        );
+                        let plain = markdown::to_plain(message);
+                        self.send_file(path, Some(plain), None).await?
+                    }
+                };
+                self.remember_sent(&sent);
             } else {
-                let mut req = self.bot.send_photo(self.chat_id, input_file);
-                if let Some(caption) = caption {
-                    req = req.caption(caption);
-                }
-                req.await?;
+                self.remember_sent(&self.send_file(path, None, None).await?);
             }
         }
 
@@ -100,6 +223,56 @@ impl Channel for TelegramChannel {
         Ok(())
     }
 
+    async fn send_message_with_buttons(
+        &self,
+        message: &str,
+        buttons: &[Vec<Button>],
+    ) -> Result<()> {
+        let keyboard = InlineKeyboardMarkup::new(buttons.iter().map(|row| {
+            row.iter()
+                .map(|b| InlineKeyboardButton::callback(b.label.clone(), b.action.clone()))
+                .collect::<Vec<_>>()
+        }));
+        let sent = self
+            .bot
+            .send_message(self.chat_id, markdown::to_telegram_html(message))
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard.clone())
+            .await;
+
+        let sent = match sent {
+            Ok(sent) => sent,
+            Err(e) => {
+                warn!(
+                    "Failed to send formatted message, sending plain text: {}",
+                    e
+                );
+                self.bot
+                    .send_message(self.chat_id, markdown::to_plain(message))
+                    .reply_markup(keyboard)
+                    .await?
+            }
+        };
+        self.remember_sent(&sent);
+        Ok(())
+    }
+
+    fn for_user(&self, user_id: &str) -> Option<Arc<dyn Channel>> {
+        let channel = TelegramChannel::for_pairing_id(self.bot.clone(), user_id)?;
+        Some(Arc::new(channel))
+    }
+
+    fn member(&self) -> Option<String> {
+        self.member.clone()
+    }
+
+    fn take_sent_message_ids(&self) -> Vec<String> {
+        self.sent
+            .lock()
+            .map(|mut sent| std::mem::take(&mut *sent))
+            .unwrap_or_default()
+    }
+
     fn start_typing(&self) -> TypingGuard {
         let (cancel_tx, mut cancel_rx) = oneshot::channel();
         let bot = self.bot.clone();
@@ -131,6 +304,12 @@ impl Channel for TelegramChannel {
 // Media Handling
 // ============================================================================
 
+/// Telegram rejects messages over 4096 UTF-16 code units
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// Telegram limits media captions to 1024 UTF-16 code units
+const MAX_CAPTION_LENGTH: usize = 1024;
+
 /// Video file extensions supported for sending
 const VIDEO_EXTENSIONS: &[&str] = &[".mp4", ".mov", ".webm", ".avi"];
 
@@ -153,21 +332,24 @@ fn get_telegram_attachments_dir() -> Result<PathBuf> {
     Ok(dir)
 }
 
-/// Download a photo from Telegram and save it locally
+/// Download a file (photo, voice note, audio) from Telegram and save it locally
 /// Returns the local file path on success
-async fn download_photo(bot: &Bot, photo: &PhotoSize) -> Result<PathBuf> {
-    let file = bot.get_file(&photo.file.id).await?;
+async fn download_file(bot: &Bot, meta: &FileMeta, default_extension: &str) -> Result<PathBuf> {
+    let file = bot.get_file(&meta.id).await?;
     let file_path = file.path;
 
     // Determine extension from the file path
-    let extension = file_path.rsplit('.').next().unwrap_or("jpg");
+    let extension = file_path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or(default_extension);
 
     let attachments_dir = get_telegram_attachments_dir()?;
-    let local_path = attachments_dir.join(format!("{}.{}", photo.file.unique_id, extension));
+    let local_path = attachments_dir.join(format!("{}.{}", meta.unique_id, extension));
 
     // Skip download if file already exists
     if local_path.exists() {
-        debug!("Photo already downloaded: {:?}", local_path);
+        debug!("File already downloaded: {:?}", local_path);
         return Ok(local_path);
     }
 
@@ -175,10 +357,97 @@ async fn download_photo(bot: &Bot, photo: &PhotoSize) -> Result<PathBuf> {
     let mut dst = tokio::fs::File::create(&local_path).await?;
     bot.download_file(&file_path, &mut dst).await?;
 
-    info!("Downloaded photo to {:?}", local_path);
+    info!("Downloaded file to {:?}", local_path);
     Ok(local_path)
 }
 
+/// Download a document from Telegram into the given path
+async fn download_document(bot: &Bot, meta: &FileMeta, dest: &std::path::Path) -> Result<()> {
+    let file = bot.get_file(&meta.id).await?;
+    let mut dst = tokio::fs::File::create(dest).await?;
+    bot.download_file(&file.path, &mut dst).await?;
+
+    info!("Downloaded document to {:?}", dest);
+    Ok(())
+}
+
+/// Save a message's document into the user's inbox, if the sender is
+/// trusted with files. A file over the size limit is noted in `text` instead.
+async fn save_document(
+    bot: &Bot,
+    msg: &Message,
+    pairing_id: &str,
+    text: &mut String,
+) -> Option<PathBuf> {
+    let document = msg.document()?;
+    if !is_trusted_sender("telegram", pairing_id) {
+        return None;
+    }
+
+    let file_name = document
+        .file_name
+        .clone()
+        .unwrap_or_else(|| document.file.unique_id.to_string());
+    let limit = max_attachment_bytes();
+    if u64::from(document.file.size) > limit {
+        info!("Document {} is over the size limit", file_name);
+        *text = format!(
+            "{}\n\n{}",
+            text,
+            oversized_attachment_note(&file_name, limit)
+        )
+        .trim()
+        .to_string();
+        return None;
+    }
+
+    // An edit of the caption brings the same file again
+    if let Ok(existing) = inbox_dir("telegram", pairing_id).map(|dir| dir.join(&file_name))
+        && std::fs::metadata(&existing).is_ok_and(|m| m.len() == u64::from(document.file.size))
+    {
+        return Some(existing);
+    }
+
+    let saved = match inbox_path("telegram", pairing_id, &file_name) {
+        Ok(dest) => download_document(bot, &document.file, &dest)
+            .await
+            .map(|_| dest),
+        Err(e) => Err(e),
+    };
+    match saved {
+        Ok(path) => Some(path),
+        Err(e) => {
+            warn!("Failed to download document: {}", e);
+            None
+        }
+    }
+}
+
+/// A location or venue shared in a message
+fn shared_location(msg: &Message) -> Option<SharedLocation> {
+    if let Some(venue) = msg.venue() {
+        return Some(SharedLocation {
+            title: Some(venue.title.clone()),
+            address: Some(venue.address.clone()),
+            ..SharedLocation::new(venue.location.latitude, venue.location.longitude)
+        });
+    }
+    msg.location()
+        .map(|location| SharedLocation::new(location.latitude, location.longitude))
+}
+
+fn shared_contact(contact: &Contact) -> SharedContact {
+    let name = match &contact.last_name {
+        Some(last_name) => format!("{} {}", contact.first_name, last_name),
+        None => contact.first_name.clone(),
+    };
+    SharedContact {
+        name,
+        phone_numbers: vec![contact.phone_number.clone()],
+        ..Default::default()
+    }
+}
+
 /// Get the largest photo from a list of photo sizes
 fn get_largest_photo(photos: &[PhotoSize]) -> Option<&PhotoSize> {
     photos.iter().max_by_key(|p| p.width * p.height)
@@ -197,7 +466,7 @@ pub async fn validate_token(token: &str) -> Result<String> {
 }
 
 /// Run the Telegram bot
-pub async fn run(config: TelegramConfig) -> Result<()> {
+pub async fn run(config: TelegramConfig, registry: Arc<ChannelRegistry>) -> Result<()> {
     let bot = Bot::new(&config.bot_token);
 
     info!("Starting Telegram bot...");
@@ -205,6 +474,7 @@ pub async fn run(config: TelegramConfig) -> Result<()> {
     // Register bot commands for the UI menu
     let commands = vec![
         BotCommand::new("new", "Start a new conversation"),
+        BotCommand::new("stop", "Stop what I'm working on"),
         BotCommand::new("skills", "List available skills"),
         BotCommand::new("commands", "Show available commands"),
     ];
@@ -212,30 +482,368 @@ pub async fn run(config: TelegramConfig) -> Result<()> {
         warn!("Failed to set bot commands: {}", e);
     }
 
+    // Our own identity, needed to tell when a group message is meant for us
+    let me = Arc::new(bot.get_me().await?);
+
     // Create shared task manager for per-user message handling
-    let task_manager = UserTaskManager::new();
+    let task_manager = UserTaskManager::for_channel("telegram");
 
-    teloxide::repl(bot, move |bot: Bot, msg: Message| {
-        let task_manager = Arc::clone(&task_manager);
-        async move {
-            if let Err(e) = handle_message(&bot, &msg, task_manager).await {
-                warn!("Error handling message: {}", e);
-            }
-            Ok(())
+    let registry_bot = bot.clone();
+    registry.register("telegram", move |user_id| {
+        let channel = TelegramChannel::for_pairing_id(registry_bot.clone(), user_id)?;
+        Some(Arc::new(channel) as Arc<dyn Channel>)
+    });
+
+    let handler = dptree::entry()
+        .branch(Update::filter_message().endpoint(
+            |bot: Bot, me: Arc<Me>, msg: Message, task_manager: Arc<UserTaskManager>| async move {
+                if let Err(e) = handle_message(&bot, &me, &msg, task_manager).await {
+                    warn!("Error handling message: {}", e);
+                }
+                respond(())
+            },
+        ))
+        .branch(Update::filter_edited_message().endpoint(
+            |bot: Bot, me: Arc<Me>, msg: Message, task_manager: Arc<UserTaskManager>| async move {
+                if let Err(e) = handle_edited_message(&bot, &me, &msg, task_manager).await {
+                    warn!("Error handling edited message: {}", e);
+                }
+                respond(())
+            },
+        ))
+        .branch(Update::filter_message_reaction_updated().endpoint(
+            |reaction: MessageReactionUpdated| async move {
+                handle_reaction_update(&reaction);
+                respond(())
+            },
+        ))
+        .branch(Update::filter_callback_query().endpoint(
+            |bot: Bot, query: CallbackQuery, task_manager: Arc<UserTaskManager>| async move {
+                if let Err(e) = handle_callback_query(&bot, query, task_manager).await {
+                    warn!("Error handling button: {}", e);
+                }
+                respond(())
+            },
+        ));
+
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
+        .dependencies(dptree::deps![me, task_manager])
+        .enable_ctrlc_handler()
+        .build();
+
+    match &config.webhook {
+        Some(webhook) => {
+            let listener = webhook_listener(&bot, webhook).await?;
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("Telegram webhook error"),
+                )
+                .await;
         }
-    })
-    .await;
+        None => {
+            // Long polling doesn't work while a webhook is registered
+            bot.delete_webhook().await?;
+            dispatcher.dispatch().await;
+        }
+    }
 
     Ok(())
 }
 
+// ============================================================================
+// Webhook
+// ============================================================================
+
+/// Updates the handlers above need. The dispatcher only tells polling which
+/// updates to ask for, so webhooks have to list them explicitly.
+const WEBHOOK_ALLOWED_UPDATES: &[AllowedUpdate] = &[
+    AllowedUpdate::Message,
+    AllowedUpdate::EditedMessage,
+    AllowedUpdate::CallbackQuery,
+    AllowedUpdate::MessageReaction,
+];
+
+/// Header Telegram sends the webhook's secret token in
+const SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+type UpdateSender = mpsc::UnboundedSender<Result<Update, Infallible>>;
+
+/// What the webhook server needs to accept an update
+struct WebhookState {
+    path: String,
+    secret: String,
+    updates: UpdateSender,
+}
+
+/// Start the webhook server, register it with Telegram and return a listener
+/// the dispatcher can read updates from.
+///
+/// The webhook stays registered on shutdown so updates keep arriving when
+/// the process is started on demand; switching back to polling removes it.
+async fn webhook_listener(
+    bot: &Bot,
+    config: &TelegramWebhookConfig,
+) -> Result<impl UpdateListener<Err = Infallible>> {
+    let url: reqwest::Url = config
+        .url
+        .parse()
+        .with_context(|| format!("Invalid webhook URL {}", config.url))?;
+    let secret = config
+        .secret_token
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+
+    let acceptor = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls_acceptor(cert, key)?),
+        (None, None) => None,
+        _ => anyhow::bail!("Both tls_cert and tls_key are needed to serve HTTPS"),
+    };
+
+    let listener = TcpListener::bind((config.host.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind webhook server to {}:{}",
+                config.host, config.port
+            )
+        })?;
+    info!(
+        "Telegram webhook listening on {}://{}{}",
+        if acceptor.is_some() { "https" } else { "http" },
+        listener.local_addr()?,
+        url.path()
+    );
+
+    let (updates, receiver) = mpsc::unbounded_channel();
+    let (stop_token, stop_flag) = mk_stop_token();
+    let state = Arc::new(WebhookState {
+        path: url.path().to_string(),
+        secret: secret.clone(),
+        updates,
+    });
+    tokio::spawn(serve_webhook(listener, acceptor, state, stop_flag));
+
+    // Only point Telegram at us once we can take its requests
+    bot.set_webhook(url)
+        .secret_token(secret)
+        .allowed_updates(WEBHOOK_ALLOWED_UPDATES.to_vec())
+        .await
+        .context("Failed to register Telegram webhook")?;
+    info!("Registered Telegram webhook {}", config.url);
+
+    let stream = Box::pin(futures::stream::unfold(receiver, |mut receiver| async {
+        receiver.recv().await.map(|update| (update, receiver))
+    }));
+    Ok(StatefulListener::new(
+        (stream, stop_token),
+        first_mut,
+        |state: &mut (_, StopToken)| state.1.clone(),
+    ))
+}
+
+fn first_mut<A, B>(pair: &mut (A, B)) -> &mut A {
+    &mut pair.0
+}
+
+/// Load a certificate chain and private key for serving HTTPS
+fn tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    // Ensure rustls crypto provider is installed
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificate {}", cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("Failed to read private key {}", key_path))?;
+
+    let tls_config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid webhook certificate or key")?;
+    Ok(TlsAcceptor::from(Arc::new(tls_config)))
+}
+
+/// Accept webhook connections until the dispatcher stops
+async fn serve_webhook(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    state: Arc<WebhookState>,
+    mut stop_flag: StopFlag,
+) {
+    loop {
+        let stream = tokio::select! {
+            _ = &mut stop_flag => break,
+            conn = listener.accept() => match conn {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept webhook connection: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        let state = state.clone();
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle_webhook_request(req, state.clone()));
+            let served = match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(tls) => {
+                        http1::Builder::new()
+                            .serve_connection(TokioIo::new(tls), service)
+                            .await
+                    }
+                    Err(e) => {
+                        debug!("Webhook TLS handshake failed: {}", e);
+                        return;
+                    }
+                },
+                None => {
+                    http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                }
+            };
+            if let Err(e) = served {
+                debug!("Webhook connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Check a webhook request and pass its update on to the dispatcher
+async fn handle_webhook_request<B>(
+    req: Request<B>,
+    state: Arc<WebhookState>,
+) -> Result<Response<Full<Bytes>>, Infallible>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    if req.method() != Method::POST || req.uri().path() != state.path {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+
+    // Compared in constant time so response times don't give the secret away
+    let secret = req
+        .headers()
+        .get(SECRET_TOKEN_HEADER)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+    if !bool::from(secret.ct_eq(state.secret.as_bytes())) {
+        warn!("Rejected webhook request without the right secret token");
+        return Ok(status_response(StatusCode::UNAUTHORIZED));
+    }
+
+    let body = match read_body(req.into_body()).await {
+        Ok(body) => body,
+        Err((status, message)) => {
+            warn!("Failed to read webhook request: {}", message);
+            return Ok(status_response(status));
+        }
+    };
+
+    match serde_json::from_slice::<Update>(&body) {
+        Ok(update) => {
+            let _ = state.updates.send(Ok(update));
+        }
+        // Telegram would keep retrying an update we can't read, so accept it anyway
+        Err(e) => warn!("Failed to parse Telegram update: {}", e),
+    }
+
+    Ok(status_response(StatusCode::OK))
+}
+
+fn status_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = status;
+    response
+}
+
 // ============================================================================
 // Message Handling
 // ============================================================================
 
+/// Check whether a group message is meant for the bot: it mentions the bot,
+/// replies to one of its messages, or is a command for it.
+fn is_addressed_to_bot(msg: &Message, me: &Me, text: &str) -> bool {
+    let mention = format!("@{}", me.username()).to_lowercase();
+    let replied_to_bot = msg
+        .reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .is_some_and(|from| from.id == me.user.id);
+
+    text.to_lowercase().contains(&mention) || replied_to_bot || is_command_for(text, me.username())
+}
+
+/// Check whether text is a bare `/command` or a `/command@bot` for this bot.
+/// Commands addressed to other bots in the group are left alone.
+fn is_command_for(text: &str, bot_username: &str) -> bool {
+    let Some(command) = text.strip_prefix('/') else {
+        return false;
+    };
+    let command = command
+        .split(char::is_whitespace)
+        .next()
+        .unwrap_or_default();
+    let (name, bot) = match command.split_once('@') {
+        Some((name, bot)) => (name, Some(bot)),
+        None => (command, None),
+    };
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && bot.is_none_or(|bot| bot.eq_ignore_ascii_case(bot_username))
+}
+
+/// Full name of whoever sent a message
+fn sender_display_name(msg: &Message) -> Option<String> {
+    msg.from.as_ref().map(|u| match &u.last_name {
+        Some(last) => format!("{} {}", u.first_name, last),
+        None => u.first_name.clone(),
+    })
+}
+
+/// Turn the user's text into the prompt for the assistant: name the sender
+/// in groups and include the message being replied to.
+fn prepare_query_text(msg: &Message, me: &Me, text: &str, is_group: bool) -> String {
+    let text = if is_group {
+        let sender = sender_display_name(msg)
+            .or_else(|| msg.from.as_ref().and_then(|u| u.username.clone()))
+            .or_else(|| msg.from.as_ref().map(|u| u.id.0.to_string()))
+            .unwrap_or_default();
+        format_group_message(&sender, text)
+    } else {
+        text.to_string()
+    };
+
+    let Some(reply) = msg.reply_to_message() else {
+        return text;
+    };
+    // A manually selected quote is more precise than the whole message
+    let quoted = msg
+        .quote()
+        .map(|q| q.text.as_str())
+        .or(reply.text())
+        .or(reply.caption())
+        .unwrap_or_default();
+    let author = reply.from.as_ref().map(|from| {
+        if from.id == me.user.id {
+            "you".to_string()
+        } else {
+            sender_display_name(reply).unwrap_or_else(|| from.first_name.clone())
+        }
+    });
+    add_reply_context(&text, author.as_deref(), quoted)
+}
+
 /// Handle an incoming message
 async fn handle_message(
     bot: &Bot,
+    me: &Me,
     msg: &Message,
     task_manager: Arc<UserTaskManager>,
 ) -> Result<()> {
@@ -243,27 +851,78 @@ async fn handle_message(
     let user = msg.from.as_ref();
     let user_id = user.map(|u| u.id.0.to_string()).unwrap_or_default();
     let username = user.and_then(|u| u.username.clone());
-    let display_name = user.map(|u| match &u.last_name {
-        Some(last) => format!("{} {}", u.first_name, last),
-        None => u.first_name.clone(),
-    });
+    let display_name = sender_display_name(msg);
 
     // Get text (either from text message or photo caption)
     let text = msg.text().or(msg.caption()).unwrap_or_default();
 
+    // In groups, only respond when addressed, and treat the whole group as one user
+    let is_group = msg.chat.is_group() || msg.chat.is_supergroup();
+    if is_group && !is_addressed_to_bot(msg, me, text) {
+        return Ok(());
+    }
+    let text = if is_group {
+        strip_mention(text, &format!("@{}", me.username()))
+    } else {
+        text.to_string()
+    };
+    let text = text.as_str();
+
+    // Groups are paired, queued and given a session as a single user
+    let (pairing_id, pairing_username, pairing_name) = if is_group {
+        let title = msg.chat.title().map(|t| t.to_string());
+        (
+            pairing::group_user_id(&msg.chat.id.0.to_string()),
+            None,
+            title,
+        )
+    } else {
+        (user_id.clone(), username.clone(), display_name.clone())
+    };
+
     // Download any photos in the message
     let mut image_paths: Vec<PathBuf> = Vec::new();
     if let Some(photos) = msg.photo()
         && let Some(largest) = get_largest_photo(photos)
     {
-        match download_photo(bot, largest).await {
+        match download_file(bot, &largest.file, "jpg").await {
             Ok(path) => image_paths.push(path),
             Err(e) => warn!("Failed to download photo: {}", e),
         }
     }
 
-    // Skip if no text and no images
-    if text.is_empty() && image_paths.is_empty() {
+    // Download voice notes and audio files for transcription
+    let mut audio_paths: Vec<PathBuf> = Vec::new();
+    let audio_file = msg
+        .voice()
+        .map(|v| (&v.file, "oga"))
+        .or(msg.audio().map(|a| (&a.file, "mp3")));
+    if let Some((file, extension)) = audio_file {
+        match download_file(bot, file, extension).await {
+            Ok(path) => audio_paths.push(path),
+            Err(e) => warn!("Failed to download voice note: {}", e),
+        }
+    }
+
+    // Save documents into the user's inbox
+    let mut text = text.to_string();
+    let mut document_paths: Vec<PathBuf> = save_document(bot, msg, &pairing_id, &mut text)
+        .await
+        .into_iter()
+        .collect();
+
+    // Shared places and contact cards become structured context
+    let location = shared_location(msg);
+    let contacts: Vec<SharedContact> = msg.contact().map(shared_contact).into_iter().collect();
+    let text = add_shared_context(&text, location.as_ref(), &contacts);
+    let text = text.as_str();
+
+    // Skip if no text, images, audio or documents
+    if text.is_empty()
+        && image_paths.is_empty()
+        && audio_paths.is_empty()
+        && document_paths.is_empty()
+    {
         return Ok(());
     }
 
@@ -275,36 +934,301 @@ async fn handle_message(
             image_paths
         );
     }
+    if !document_paths.is_empty() {
+        info!(
+            "Message includes {} document(s): {:?}",
+            document_paths.len(),
+            document_paths
+        );
+    }
 
     // Create channel wrapper
-    let channel: Arc<dyn Channel> = Arc::new(TelegramChannel::new(bot.clone(), msg.chat.id));
+    let channel: Arc<dyn Channel> = Arc::new(
+        TelegramChannel::new(bot.clone(), msg.chat.id)
+            .with_member(is_group.then(|| user_id.clone())),
+    );
+
+    // Voice notes become part of the message text, or are passed on as files
+    let (text, kept_audio) =
+        add_voice_transcripts(channel.as_ref(), &pairing_id, text, &audio_paths).await;
+    document_paths.extend(kept_audio);
+
+    // In a group, the member who wrote is checked against the block list too
+    let member = SenderInfo::new(username, display_name);
+    let sender_info = if is_group {
+        SenderInfo::new(pairing_username, pairing_name).with_member(user_id.clone(), member)
+    } else {
+        member
+    };
 
     // Determine what action to take
     let mut store = PairingStore::load()?;
     let action = determine_action(
         channel.name(),
-        &user_id,
-        text,
-        &image_paths,
+        &pairing_id,
+        &text,
+        &[image_paths.as_slice(), document_paths.as_slice()].concat(),
         &mut store,
-        username,
-        display_name,
+        sender_info,
     )?;
 
     // Execute the action
-    if let Some(query_text) = execute_action(channel.as_ref(), &user_id, action).await? {
+    if let Some(query_text) =
+        execute_action(channel.as_ref(), &pairing_id, action, &task_manager).await?
+    {
+        // Only a pin the user dropped says where they are; a venue is a
+        // place they picked, which may be anywhere
+        if msg.venue().is_none()
+            && let Some(location) = &location
+        {
+            remember_location(channel.name(), &pairing_id, location);
+        }
+
         // QueryClaude action - queue with task manager for debouncing
-        let text_with_images = build_text_with_images(&query_text, &image_paths);
-        let user_key = format!("{}:{}", channel.name(), user_id);
+        let query_text = prepare_query_text(msg, me, &query_text, is_group);
+        let text_with_images = build_text_with_images(&query_text, &image_paths, &document_paths);
+        let user_key = format!("{}:{}", channel.name(), pairing_id);
         let channel_clone = channel.clone();
-        let user_id_clone = user_id.clone();
+        let user_id_clone = pairing_id.clone();
 
         task_manager
-            .process_message(user_key, text_with_images, move |messages| async move {
-                execute_claude_query(channel_clone, &user_id_clone, messages).await;
-            })
+            .process_message_with_id(
+                user_key,
+                Some(msg.id.0.to_string()),
+                text_with_images,
+                move |messages, steering| async move {
+                    execute_claude_query(
+                        channel_clone,
+                        &user_id_clone,
+                        &user_id_clone,
+                        messages,
+                        steering,
+                    )
+                    .await;
+                },
+            )
             .await;
     }
 
     Ok(())
 }
+
+/// Handle an edited message. Only messages still waiting for an answer are
+/// affected; the new text replaces the old one.
+async fn handle_edited_message(
+    bot: &Bot,
+    me: &Me,
+    msg: &Message,
+    task_manager: Arc<UserTaskManager>,
+) -> Result<()> {
+    let text = msg.text().or(msg.caption()).unwrap_or_default();
+
+    let is_group = msg.chat.is_group() || msg.chat.is_supergroup();
+    if is_group && !is_addressed_to_bot(msg, me, text) {
+        return Ok(());
+    }
+    let text = if is_group {
+        strip_mention(text, &format!("@{}", me.username()))
+    } else {
+        text.to_string()
+    };
+    // Commands have already run, there's nothing to redo
+    if text.is_empty() || text.starts_with('/') {
+        return Ok(());
+    }
+
+    let pairing_id = if is_group {
+        pairing::group_user_id(&msg.chat.id.0.to_string())
+    } else {
+        msg.from
+            .as_ref()
+            .map(|u| u.id.0.to_string())
+            .unwrap_or_default()
+    };
+
+    // An edited caption still belongs with its photo or document
+    let mut image_paths: Vec<PathBuf> = Vec::new();
+    if let Some(photos) = msg.photo()
+        && let Some(largest) = get_largest_photo(photos)
+    {
+        match download_file(bot, &largest.file, "jpg").await {
+            Ok(path) => image_paths.push(path),
+            Err(e) => warn!("Failed to download photo: {}", e),
+        }
+    }
+    let mut text = text;
+    let document_paths: Vec<PathBuf> = save_document(bot, msg, &pairing_id, &mut text)
+        .await
+        .into_iter()
+        .collect();
+
+    let query_text = prepare_query_text(msg, me, &text, is_group);
+    let text_with_images = build_text_with_images(&query_text, &image_paths, &document_paths);
+    let member = msg.from.as_ref().map(|u| u.id.0.to_string());
+    let channel: Arc<dyn Channel> = Arc::new(
+        TelegramChannel::new(bot.clone(), msg.chat.id).with_member(member.filter(|_| is_group)),
+    );
+    handle_edit(
+        channel,
+        &pairing_id,
+        &pairing_id,
+        &msg.id.0.to_string(),
+        text_with_images,
+        &task_manager,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Log emoji reactions to the bot's replies as feedback
+fn handle_reaction_update(update: &MessageReactionUpdated) {
+    // Anonymous group admins react as the chat; there's no user to credit
+    let Some(user) = &update.user else {
+        return;
+    };
+    let message_id = message_key(update.chat.id, update.message_id);
+    let user_id = user.id.0.to_string();
+
+    // Custom emoji have no text form; keep their id so they can be told apart
+    let emoji = |reaction: &ReactionType| match reaction {
+        ReactionType::Emoji { emoji } => emoji.clone(),
+        ReactionType::CustomEmoji { custom_emoji_id } => format!("custom:{}", custom_emoji_id),
+    };
+    let old: Vec<String> = update.old_reaction.iter().map(emoji).collect();
+    let new: Vec<String> = update.new_reaction.iter().map(emoji).collect();
+
+    for removed in old.iter().filter(|e| !new.contains(e)) {
+        handle_reaction("telegram", &message_id, &user_id, removed, true);
+    }
+    for added in new.iter().filter(|e| !old.contains(e)) {
+        handle_reaction("telegram", &message_id, &user_id, added, false);
+    }
+}
+
+/// Handle a tap on an inline keyboard button
+async fn handle_callback_query(
+    bot: &Bot,
+    query: CallbackQuery,
+    task_manager: Arc<UserTaskManager>,
+) -> Result<()> {
+    // Stop the client's loading spinner whatever happens next
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    let (Some(action), Some(message)) = (query.data, query.message) else {
+        return Ok(());
+    };
+    let chat = message.chat();
+    let is_group = chat.is_group() || chat.is_supergroup();
+    let tapped_by = query.from.id.0.to_string();
+
+    // Someone else's buttons stay for the member they were sent to
+    if let (_, Some(member)) = button_member(&action)
+        && member != tapped_by
+    {
+        return Ok(());
+    }
+
+    // Buttons are one-shot: remove them from the message that was tapped
+    if let Err(e) = bot.edit_message_reply_markup(chat.id, message.id()).await {
+        debug!("Failed to remove buttons: {}", e);
+    }
+
+    let member = SenderInfo::new(query.from.username.clone(), Some(query.from.full_name()));
+    let (pairing_id, sender_info) = if is_group {
+        let title = chat.title().map(|t| t.to_string());
+        (
+            pairing::group_user_id(&chat.id.0.to_string()),
+            SenderInfo::new(None, title).with_member(tapped_by.clone(), member),
+        )
+    } else {
+        (tapped_by.clone(), member)
+    };
+
+    info!("Button {} pressed by {}", action, tapped_by);
+
+    let channel: Arc<dyn Channel> = Arc::new(
+        TelegramChannel::new(bot.clone(), chat.id).with_member(is_group.then_some(tapped_by)),
+    );
+    handle_button(
+        channel,
+        &pairing_id,
+        &pairing_id,
+        &action,
+        sender_info,
+        &task_manager,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_command_for() {
+        assert!(is_command_for("/new", "cica_bot"));
+        assert!(is_command_for("/cron list", "cica_bot"));
+        assert!(is_command_for("/new@Cica_Bot", "cica_bot"));
+
+        assert!(!is_command_for("/new@other_bot", "cica_bot"));
+        assert!(!is_command_for("/usr/bin is full", "cica_bot"));
+        assert!(!is_command_for("/ hello", "cica_bot"));
+        assert!(!is_command_for("new", "cica_bot"));
+    }
+
+    fn webhook_state() -> (
+        Arc<WebhookState>,
+        mpsc::UnboundedReceiver<Result<Update, Infallible>>,
+    ) {
+        let (updates, rx) = mpsc::unbounded_channel();
+        let state = Arc::new(WebhookState {
+            path: "/telegram/hook".to_string(),
+            secret: "s3cret".to_string(),
+            updates,
+        });
+        (state, rx)
+    }
+
+    fn webhook_request(path: &str, secret: Option<&str>) -> Request<Full<Bytes>> {
+        let update = r#"{"update_id":1,"message":{"message_id":1,"date":0,"chat":{"id":1,"type":"private","first_name":"A"},"from":{"id":1,"is_bot":false,"first_name":"A"},"text":"hi"}}"#;
+        let mut req = Request::post(path);
+        if let Some(secret) = secret {
+            req = req.header(SECRET_TOKEN_HEADER, secret);
+        }
+        req.body(Full::new(Bytes::from(update))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_accepts_update_with_secret() {
+        let (state, mut rx) = webhook_state();
+        let response =
+            handle_webhook_request(webhook_request("/telegram/hook", Some("s3cret")), state)
+                .await
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(matches!(rx.try_recv(), Ok(Ok(_))));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_rejects_wrong_or_missing_secret() {
+        for secret in [Some("wrong"), None] {
+            let (state, mut rx) = webhook_state();
+            let response = handle_webhook_request(webhook_request("/telegram/hook", secret), state)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert!(rx.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_rejects_wrong_path() {
+        let (state, mut rx) = webhook_state();
+        let response = handle_webhook_request(webhook_request("/other", Some("s3cret")), state)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use tracing::info;
 
 use crate::channels;
-use crate::pairing::PairingStore;
+use crate::pairing::{self, PairingStore};
 
 /// Run the approve command
 pub fn run(code: &str) -> Result<()> {
@@ -21,7 +21,13 @@ pub fn run(code: &str) -> Result<()> {
         .map(|s| s.as_str())
         .unwrap_or(&request.user_id);
 
-    println!("Approved {} user: {}", channel_display, user_display);
+    let kind = if pairing::is_group(&request.user_id) {
+        "group"
+    } else {
+        "user"
+    };
+
+    println!("Approved {} {}: {}", channel_display, kind, user_display);
 
     info!(
         "Approved {} user {} ({})",
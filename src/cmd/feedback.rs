@@ -0,0 +1,93 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::channels;
+use crate::feedback::{self, Tally};
+
+/// How many disliked replies to list
+const RECENT_NEGATIVE: usize = 5;
+
+/// Longest prompt or reply excerpt shown in the report
+const EXCERPT_CHARS: usize = 80;
+
+/// Run the feedback command
+pub fn run() -> Result<()> {
+    let report = feedback::load_report()?;
+
+    let reactions = report.total.positive + report.total.negative + report.total.neutral;
+    if reactions == 0 {
+        println!("No feedback yet.");
+        println!();
+        println!("React to Cica's replies with 👍 or 👎 on Telegram, Signal or Slack.");
+        return Ok(());
+    }
+
+    println!(
+        "{} reaction(s) on {} logged replies: {}",
+        reactions,
+        report.replies,
+        format_tally(&report.total)
+    );
+
+    print_section("By model", &report.by_model);
+    print_section("By skill", &report.by_skill);
+
+    println!();
+    println!("Reactions:");
+    let emoji: Vec<String> = report
+        .by_emoji
+        .iter()
+        .map(|(emoji, count)| format!("{} {}", emoji, count))
+        .collect();
+    println!("  {}", emoji.join("  "));
+
+    if !report.negative.is_empty() {
+        println!();
+        println!("Recent disliked replies:");
+        for rated in report.negative.iter().take(RECENT_NEGATIVE) {
+            let channel = channels::get_channel_info(&rated.channel)
+                .map(|c| c.display_name)
+                .unwrap_or(&rated.channel);
+            println!();
+            println!(
+                "  {} {} ({})",
+                channel,
+                rated.reply.user_id,
+                rated.reactions.join(" ")
+            );
+            println!("    Asked:    {}", excerpt(&rated.reply.prompt));
+            println!("    Answered: {}", excerpt(&rated.reply.reply));
+        }
+    }
+
+    Ok(())
+}
+
+fn print_section(title: &str, tallies: &BTreeMap<String, Tally>) {
+    if tallies.is_empty() {
+        return;
+    }
+    println!();
+    println!("{}:", title);
+    let width = tallies.keys().map(|k| k.chars().count()).max().unwrap_or(0);
+    for (name, tally) in tallies {
+        println!("  {:<width$}  {}", name, format_tally(tally), width = width);
+    }
+}
+
+fn format_tally(tally: &Tally) -> String {
+    format!(
+        "👍 {}  👎 {}  other {}",
+        tally.positive, tally.negative, tally.neutral
+    )
+}
+
+/// First line of the text, shortened to fit one row
+fn excerpt(text: &str) -> String {
+    let line = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    let mut short: String = line.chars().take(EXCERPT_CHARS).collect();
+    if short.len() < line.len() || text.trim().lines().count() > 1 {
+        short.push('…');
+    }
+    short
+}
@@ -1,10 +1,12 @@
 use anyhow::{Result, bail};
-use dialoguer::{Input, Password, Select, theme::ColorfulTheme};
+use dialoguer::{Confirm, Input, Password, Select, theme::ColorfulTheme};
 use tracing::info;
 
 use crate::backends::{claude, cursor};
 use crate::channels::{self, signal, slack, telegram};
-use crate::config::{self, AiBackend, Config, SignalConfig, SlackConfig, TelegramConfig};
+use crate::config::{
+    self, AiBackend, Config, IrcConfig, SignalConfig, SlackConfig, TelegramConfig,
+};
 use crate::setup;
 
 /// Run the init command
@@ -284,6 +286,7 @@ async fn add_channel(existing_config: Option<Config>) -> Result<Config> {
         "telegram" => setup_telegram(existing_config).await,
         "signal" => setup_signal(existing_config).await,
         "slack" => setup_slack(existing_config).await,
+        "irc" => setup_irc(existing_config).await,
         _ => bail!("Channel not yet supported: {}", channel.name),
     }
 }
@@ -841,7 +844,7 @@ async fn setup_slack(existing_config: Option<Config>) -> Result<Config> {
     println!();
     println!("3. Subscribe to events:");
     println!("   Features → Event Subscriptions → Enable");
-    println!("   Subscribe to bot events: message.im");
+    println!("   Subscribe to bot events: message.im, reaction_added, reaction_removed");
     println!();
     println!("4. Add OAuth scopes:");
     println!("   Features → OAuth & Permissions → Bot Token Scopes:");
@@ -849,9 +852,20 @@ async fn setup_slack(existing_config: Option<Config>) -> Result<Config> {
     println!("   - im:history");
     println!("   - im:read");
     println!("   - im:write");
+    println!("   - reactions:read");
     println!("   - users:read");
+    println!("   - commands");
     println!();
-    println!("5. Install the app to your workspace");
+    println!("5. Add a slash command:");
+    println!("   Features → Slash Commands → Create New Command");
+    println!("   Command: {}", slack::SLASH_COMMAND);
+    println!("   Short description: Talk to Cica");
+    println!("   Usage hint: [new | skills | cron list | commands]");
+    println!();
+    println!("6. Enable buttons:");
+    println!("   Features → Interactivity & Shortcuts → Interactivity: ON");
+    println!();
+    println!("7. Install the app to your workspace");
     println!();
 
     // Get Bot Token
@@ -887,6 +901,74 @@ async fn setup_slack(existing_config: Option<Config>) -> Result<Config> {
     Ok(config)
 }
 
+/// Set up IRC
+async fn setup_irc(existing_config: Option<Config>) -> Result<Config> {
+    println!();
+    println!("IRC Setup");
+    println!("─────────");
+    println!();
+    println!("Cica will answer private messages, and messages in joined");
+    println!("channels that mention its nickname.");
+    println!();
+
+    let server: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Server hostname")
+        .interact_text()?;
+
+    let tls = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Use TLS?")
+        .default(true)
+        .interact()?;
+
+    let port: u16 = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Port")
+        .default(if tls { 6697 } else { 6667 })
+        .interact_text()?;
+
+    let nickname: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Nickname")
+        .default("cica".to_string())
+        .interact_text()?;
+
+    let mut irc_config = IrcConfig::new(server, port, tls, nickname);
+
+    let use_sasl = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Authenticate with SASL?")
+        .default(false)
+        .interact()?;
+
+    if use_sasl {
+        let account: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("SASL account")
+            .default(irc_config.nickname.clone())
+            .interact_text()?;
+        let password: String = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("SASL password")
+            .interact()?;
+        irc_config.sasl_username = Some(account);
+        irc_config.sasl_password = Some(password);
+    }
+
+    let channels: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Channels to join (comma-separated, optional)")
+        .allow_empty(true)
+        .interact_text()?;
+
+    irc_config.channels = channels
+        .split(',')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+
+    // Build config
+    let mut config = existing_config.unwrap_or_default();
+    config.channels.irc = Some(irc_config);
+    config.save()?;
+
+    info!("IRC setup complete");
+    Ok(config)
+}
+
 /// Set up Claude (Bun + Claude Code + API key)
 async fn setup_claude(existing_config: Option<Config>) -> Result<()> {
     println!();
@@ -0,0 +1,84 @@
+use anyhow::{Result, anyhow};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::channels::telegram;
+use crate::config::Config;
+use crate::cron::{format_timestamp, parse_duration};
+use crate::pairing::PairingStore;
+
+/// Create an invite and show how to use it on each channel
+pub async fn run(uses: u32, expires: &str, role: Option<String>) -> Result<()> {
+    if uses == 0 {
+        return Err(anyhow!("--uses must be at least 1"));
+    }
+
+    let config = Config::load()?;
+
+    if let Some(role) = &role
+        && config.role(role).is_none()
+    {
+        return Err(anyhow!(
+            "Unknown role {}. Available: {}",
+            role,
+            config.role_names().join(", ")
+        ));
+    }
+
+    let ttl = match expires {
+        "never" => None,
+        expires => Some(Duration::from_millis(
+            parse_duration(expires).map_err(|e| anyhow!(e))?,
+        )),
+    };
+
+    let mut store = PairingStore::load()?;
+    let invite = store.create_invite(uses, ttl, role)?;
+
+    let people = if invite.uses_left == 1 {
+        "1 person".to_string()
+    } else {
+        format!("{} people", invite.uses_left)
+    };
+    let expiry = invite
+        .expires_at
+        .map(|at| format!("valid until {}", format_timestamp(at * 1000)))
+        .unwrap_or_else(|| "with no expiry".to_string());
+    let role = invite
+        .role
+        .clone()
+        .unwrap_or_else(|| config.default_role().to_string());
+    println!("Invite for {} as {}, {}.", people, role, expiry);
+    info!("Created an invite for {} as {}", people, role);
+    println!();
+
+    let token = &invite.token;
+    let channels = &config.channels;
+    if let Some(telegram) = &channels.telegram {
+        match telegram::validate_token(&telegram.bot_token).await {
+            Ok(username) => println!("  Telegram: https://t.me/{}?start={}", username, token),
+            Err(e) => {
+                warn!("Couldn't look up the Telegram bot: {}", e);
+                println!("  Telegram: send the bot /start {}", token);
+            }
+        }
+    }
+    if let Some(signal) = &channels.signal {
+        println!("  Signal:   send {} to {}", token, signal.phone_number);
+    }
+    if channels.slack.is_some() {
+        println!("  Slack:    send {} to the app in a direct message", token);
+    }
+    if let Some(irc) = &channels.irc {
+        println!("  IRC:      /msg {} {}", irc.nickname, token);
+    }
+    for plugin in &channels.plugins {
+        let name = plugin.display_name.as_deref().unwrap_or(&plugin.name);
+        println!("  {}: send {}", name, token);
+    }
+
+    println!();
+    println!("Whoever uses it is approved right away, no pairing code needed.");
+
+    Ok(())
+}
@@ -1,4 +1,7 @@
 pub mod approve;
+pub mod feedback;
 pub mod init;
+pub mod invite;
 pub mod paths;
 pub mod run;
+pub mod users;
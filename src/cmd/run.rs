@@ -7,7 +7,10 @@ use tokio::signal;
 use tokio::sync::Mutex;
 use tracing::{error, info, warn};
 
-use crate::channels::{signal as signal_channel, slack, telegram};
+use crate::api;
+use crate::channels::outbox::{self, OutboundMessage};
+use crate::channels::registry::ChannelRegistry;
+use crate::channels::{irc, plugin, signal as signal_channel, slack, telegram};
 use crate::config::Config;
 use crate::cron::{CronConfig, CronService, SystemClock};
 use crate::memory::MemoryIndex;
@@ -26,7 +29,7 @@ pub async fn run() -> Result<()> {
     let config = Config::load()?;
     let channels = config.configured_channels();
 
-    if channels.is_empty() {
+    if channels.is_empty() && config.api.is_none() {
         println!("No channels configured.");
         println!("Run `cica init` to add a channel.");
         return Ok(());
@@ -42,36 +45,72 @@ pub async fn run() -> Result<()> {
     // Index memories for all approved users at startup
     index_all_user_memories();
 
+    // Channels register here once connected, so anything can message their users
+    let registry = ChannelRegistry::new();
+
+    // Retry messages that couldn't be delivered earlier
+    tokio::spawn(outbox::run(registry.clone()));
+
     // Start cron scheduler service
-    let cron_service = start_cron_service(&config)?;
+    let cron_service = start_cron_service(registry.clone())?;
 
     // Spawn tasks for each configured channel
     let mut handles = Vec::new();
 
     if let Some(telegram_config) = config.channels.telegram {
+        let registry = registry.clone();
         handles.push(tokio::spawn(async move {
-            if let Err(e) = telegram::run(telegram_config).await {
+            if let Err(e) = telegram::run(telegram_config, registry).await {
                 error!("Telegram channel error: {}", e);
             }
         }));
     }
 
     if let Some(signal_config) = config.channels.signal {
+        let registry = registry.clone();
         handles.push(tokio::spawn(async move {
-            if let Err(e) = signal_channel::run(signal_config).await {
+            if let Err(e) = signal_channel::run(signal_config, registry).await {
                 error!("Signal channel error: {}", e);
             }
         }));
     }
 
     if let Some(slack_config) = config.channels.slack {
+        let registry = registry.clone();
         handles.push(tokio::spawn(async move {
-            if let Err(e) = slack::run(slack_config).await {
+            if let Err(e) = slack::run(slack_config, registry).await {
                 error!("Slack channel error: {}", e);
             }
         }));
     }
 
+    if let Some(irc_config) = config.channels.irc {
+        let registry = registry.clone();
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = irc::run(irc_config, registry).await {
+                error!("IRC channel error: {}", e);
+            }
+        }));
+    }
+
+    for plugin_config in config.channels.plugins {
+        let registry = registry.clone();
+        handles.push(tokio::spawn(async move {
+            let name = plugin_config.name.clone();
+            if let Err(e) = plugin::run(plugin_config, registry).await {
+                error!("Plugin channel {} error: {}", name, e);
+            }
+        }));
+    }
+
+    if let Some(api_config) = config.api {
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = api::run(api_config).await {
+                error!("API server error: {}", e);
+            }
+        }));
+    }
+
     // Wait for Ctrl+C
     tokio::select! {
         _ = signal::ctrl_c() => {
@@ -94,7 +133,9 @@ pub async fn run() -> Result<()> {
 }
 
 /// Start the cron scheduler service
-fn start_cron_service(config: &Config) -> Result<Option<Arc<Mutex<CronService<SystemClock>>>>> {
+fn start_cron_service(
+    registry: Arc<ChannelRegistry>,
+) -> Result<Option<Arc<Mutex<CronService<SystemClock>>>>> {
     let clock = SystemClock;
     let cron_config = CronConfig::default();
 
@@ -106,49 +147,12 @@ fn start_cron_service(config: &Config) -> Result<Option<Arc<Mutex<CronService<Sy
         }
     };
 
-    // Create result sender that routes messages to the appropriate channel
-    let telegram_token = config
-        .channels
-        .telegram
-        .as_ref()
-        .map(|c| c.bot_token.clone());
-    let signal_phone = config
-        .channels
-        .signal
-        .as_ref()
-        .map(|c| c.phone_number.clone());
-    let slack_bot_token = config.channels.slack.as_ref().map(|c| c.bot_token.clone());
-
+    // Results go out through the outbox, so they're retried if the channel is down
     let result_sender: crate::cron::ResultSender = Arc::new(move |channel, user_id, message| {
-        let telegram_token = telegram_token.clone();
-        let signal_phone = signal_phone.clone();
-        let slack_bot_token = slack_bot_token.clone();
-
+        let registry = registry.clone();
         Box::pin(async move {
-            match channel.as_str() {
-                "telegram" => {
-                    if let Some(token) = telegram_token {
-                        send_telegram_message(&token, &user_id, &message).await
-                    } else {
-                        Err(anyhow::anyhow!("Telegram not configured"))
-                    }
-                }
-                "signal" => {
-                    if let Some(_phone) = signal_phone {
-                        send_signal_message(&user_id, &message).await
-                    } else {
-                        Err(anyhow::anyhow!("Signal not configured"))
-                    }
-                }
-                "slack" => {
-                    if let Some(token) = slack_bot_token {
-                        send_slack_message(&token, &user_id, &message).await
-                    } else {
-                        Err(anyhow::anyhow!("Slack not configured"))
-                    }
-                }
-                _ => Err(anyhow::anyhow!("Unknown channel: {}", channel)),
-            }
+            let message = OutboundMessage::new(&channel, &user_id, &message);
+            outbox::send(&registry, message).await
         }) as Pin<Box<dyn Future<Output = Result<()>> + Send>>
     });
 
@@ -158,52 +162,6 @@ fn start_cron_service(config: &Config) -> Result<Option<Arc<Mutex<CronService<Sy
     Ok(Some(Arc::new(Mutex::new(service))))
 }
 
-/// Send a message via Telegram
-async fn send_telegram_message(token: &str, user_id: &str, message: &str) -> Result<()> {
-    use teloxide::prelude::*;
-
-    let bot = Bot::new(token);
-    let chat_id: i64 = user_id.parse()?;
-    bot.send_message(ChatId(chat_id), message).await?;
-    Ok(())
-}
-
-/// Send a message via Signal
-async fn send_signal_message(recipient: &str, message: &str) -> Result<()> {
-    use jsonrpsee::core::client::ClientT;
-    use jsonrpsee::core::params::ObjectParams;
-    use jsonrpsee::http_client::HttpClientBuilder;
-    use serde_json::Value;
-
-    // Connect to the signal-cli daemon
-    let url = "http://127.0.0.1:18080/api/v1/rpc";
-    let client = HttpClientBuilder::default().build(url)?;
-
-    let mut params = ObjectParams::new();
-    params.insert("recipient", vec![recipient])?;
-    params.insert("message", message)?;
-
-    let _: Value = client.request("send", params).await?;
-    Ok(())
-}
-
-/// Send a message via Slack
-async fn send_slack_message(bot_token: &str, channel_id: &str, message: &str) -> Result<()> {
-    use slack_morphism::prelude::*;
-
-    let client = SlackClient::new(SlackClientHyperConnector::new()?);
-    let token = SlackApiToken::new(bot_token.into());
-    let session = client.open_session(&token);
-
-    let request = SlackApiChatPostMessageRequest::new(
-        channel_id.into(),
-        SlackMessageContent::new().with_text(message.to_string()),
-    );
-
-    session.chat_post_message(&request).await?;
-    Ok(())
-}
-
 /// Index memories for all approved users
 fn index_all_user_memories() {
     let store = match PairingStore::load() {
@@ -231,7 +189,7 @@ fn index_all_user_memories() {
         }
         let (channel, user_id) = (parts[0], parts[1]);
 
-        if let Err(e) = index.index_user_memories(channel, user_id) {
+        if let Err(e) = index.index_user_memories(&store, channel, user_id) {
             warn!(
                 "Failed to index memories for {}:{}: {}",
                 channel, user_id, e
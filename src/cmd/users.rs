@@ -0,0 +1,288 @@
+use anyhow::{Result, anyhow};
+use tracing::info;
+
+use crate::channels;
+use crate::config::Config;
+use crate::cron::{self, CronStore, format_timestamp};
+use crate::onboarding;
+use crate::pairing::{self, PairingStore};
+
+/// Split a `channel:user_id` argument
+fn parse_user(user: &str) -> Result<(&str, &str)> {
+    user.split_once(':')
+        .filter(|(channel, user_id)| !channel.is_empty() && !user_id.is_empty())
+        .ok_or_else(|| anyhow!("Expected <channel>:<user_id>, e.g. telegram:123456789"))
+}
+
+fn channel_display(channel: &str) -> &str {
+    channels::get_channel_info(channel)
+        .map(|c| c.display_name)
+        .unwrap_or(channel)
+}
+
+/// Name from the user's profile, and whether they're a group
+fn describe(store: &PairingStore, channel: &str, user_id: &str) -> String {
+    let name = onboarding::user_name_for_user(store, channel, user_id);
+    match (name, pairing::is_group(user_id)) {
+        (Some(name), true) => format!("{} (group)", name),
+        (None, true) => "(group)".to_string(),
+        (Some(name), false) => name,
+        (None, false) => String::new(),
+    }
+}
+
+/// List approved and blocked users
+pub fn list() -> Result<()> {
+    let config = Config::load()?;
+    let store = PairingStore::load()?;
+    let users = store.approved_users();
+
+    if users.is_empty() {
+        println!("No approved users yet.");
+    } else {
+        println!("Approved users:");
+        println!();
+        for (channel, user_id) in &users {
+            let key = format!("{}:{}", channel, user_id);
+            let mut line = format!(
+                "  {:<32} {:<8} {}",
+                key,
+                store.role_name(&config, channel, user_id),
+                describe(&store, channel, user_id)
+            );
+            let canonical = store.canonical_key(channel, user_id);
+            if canonical != key {
+                line.push_str(&format!(" (linked to {})", canonical));
+            }
+            println!("{}", line.trim_end());
+        }
+    }
+
+    let mut blocked: Vec<String> = store
+        .blocked
+        .iter()
+        .flat_map(|(channel, ids)| ids.iter().map(move |id| format!("{}:{}", channel, id)))
+        .collect();
+    if !blocked.is_empty() {
+        blocked.sort();
+        println!();
+        println!("Blocked:");
+        println!();
+        for key in blocked {
+            println!("  {}", key);
+        }
+    }
+
+    Ok(())
+}
+
+/// List pairing requests waiting for approval
+pub fn pending() -> Result<()> {
+    let mut store = PairingStore::load()?;
+    let requests = store.list_pending();
+
+    if requests.is_empty() {
+        println!("No pending requests.");
+        return Ok(());
+    }
+
+    println!("Pending requests:");
+    println!();
+    for request in requests {
+        let mut who = request
+            .username
+            .as_ref()
+            .map(|u| format!("@{}", u))
+            .into_iter()
+            .chain(request.display_name.clone())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if who.is_empty() {
+            who = request.user_id.clone();
+        }
+        println!(
+            "  {}  {:<8} {:<32} {}",
+            request.code,
+            channel_display(&request.channel),
+            who,
+            format_timestamp(request.created_at * 1000)
+        );
+    }
+    println!();
+    println!("Approve with: cica approve <code>");
+
+    Ok(())
+}
+
+/// Take away a user's access
+pub fn revoke(user: &str) -> Result<()> {
+    let (channel, user_id) = parse_user(user)?;
+    let mut store = PairingStore::load()?;
+
+    if !store.revoke(channel, user_id)? {
+        println!("{} was not approved.", user);
+        return Ok(());
+    }
+
+    println!("Revoked access for {}.", user);
+    report_paused_jobs(channel, user_id);
+    println!("Their files are kept; they can ask to be paired again.");
+    info!("Revoked {} user {}", channel, user_id);
+
+    Ok(())
+}
+
+/// Revoke a user's access and ignore them from now on
+pub fn block(user: &str) -> Result<()> {
+    let (channel, user_id) = parse_user(user)?;
+    let mut store = PairingStore::load()?;
+    store.block(channel, user_id)?;
+
+    println!("Blocked {}. Their messages will be ignored.", user);
+    report_paused_jobs(channel, user_id);
+    info!("Blocked {} user {}", channel, user_id);
+
+    Ok(())
+}
+
+/// Pause the cron jobs of a user who lost access
+fn report_paused_jobs(channel: &str, user_id: &str) {
+    match cron::pause_jobs_for(channel, user_id) {
+        Ok(0) => {}
+        Ok(paused) => println!("Paused their {} cron job(s).", paused),
+        Err(e) => println!("Couldn't pause their cron jobs: {}", e),
+    }
+}
+
+/// Let a blocked user ask to be paired again
+pub fn unblock(user: &str) -> Result<()> {
+    let (channel, user_id) = parse_user(user)?;
+    let mut store = PairingStore::load()?;
+
+    if store.unblock(channel, user_id)? {
+        println!("Unblocked {}. They can ask to be paired again.", user);
+    } else {
+        println!("{} was not blocked.", user);
+    }
+
+    Ok(())
+}
+
+/// Show everything Cica knows about a user's access
+pub fn info(user: &str) -> Result<()> {
+    let (channel, user_id) = parse_user(user)?;
+    let mut store = PairingStore::load()?;
+    let key = format!("{}:{}", channel, user_id);
+
+    let pending_code = store
+        .list_pending()
+        .into_iter()
+        .find(|r| r.channel == channel && r.user_id == user_id)
+        .map(|r| r.code.clone());
+    let status = if store.is_blocked(channel, user_id) {
+        "blocked".to_string()
+    } else if store.is_approved(channel, user_id) {
+        "approved".to_string()
+    } else if let Some(code) = pending_code {
+        format!("waiting for approval (code {})", code)
+    } else {
+        "unknown".to_string()
+    };
+
+    println!("{} on {}", user_id, channel_display(channel));
+    println!();
+    println!("  Status:     {}", status);
+
+    let name = describe(&store, channel, user_id);
+    if !name.is_empty() {
+        println!("  Name:       {}", name);
+    }
+
+    if store.is_approved(channel, user_id) {
+        let config = Config::load()?;
+        println!(
+            "  Role:       {}",
+            store.role_name(&config, channel, user_id)
+        );
+    }
+
+    let onboarded = onboarding::is_complete_for_user(&store, channel, user_id).unwrap_or(false);
+    println!(
+        "  Onboarding: {}",
+        if onboarded {
+            "complete"
+        } else {
+            "not finished"
+        }
+    );
+
+    let linked: Vec<String> = store
+        .linked_identities(channel, user_id)
+        .into_iter()
+        .map(|(c, u)| format!("{}:{}", c, u))
+        .filter(|k| *k != key)
+        .collect();
+    if !linked.is_empty() {
+        println!("  Linked to:  {}", linked.join(", "));
+    }
+
+    let has_session = store.sessions.contains_key(&key);
+    let has_token = store.api_tokens.values().any(|owner| *owner == key);
+    println!(
+        "  Session:    {}",
+        if has_session { "active" } else { "none" }
+    );
+    println!("  API token:  {}", if has_token { "yes" } else { "no" });
+
+    let jobs = CronStore::load()
+        .map(|s| s.list_for_user(&store, channel, user_id).len())
+        .unwrap_or(0);
+    println!("  Cron jobs:  {}", jobs);
+
+    let dir = onboarding::user_dir(&store, channel, user_id)?;
+    if dir.exists() {
+        println!("  Files:      {}", dir.display());
+    }
+
+    Ok(())
+}
+
+/// Change an approved user's role
+pub fn set_role(user: &str, role: &str) -> Result<()> {
+    let (channel, user_id) = parse_user(user)?;
+    let config = Config::load()?;
+    let mut store = PairingStore::load()?;
+
+    if config.role(role).is_none() {
+        return Err(anyhow!(
+            "Unknown role {}. Available: {}",
+            role,
+            config.role_names().join(", ")
+        ));
+    }
+    if !store.is_approved(channel, user_id) {
+        return Err(anyhow!("{} is not approved", user));
+    }
+
+    store.set_role(channel, user_id, role)?;
+    println!("{} is now a {}.", user, role);
+    info!("Set role of {} user {} to {}", channel, user_id, role);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user() {
+        assert_eq!(parse_user("telegram:123").unwrap(), ("telegram", "123"));
+        assert_eq!(
+            parse_user("slack:U1:thread").unwrap(),
+            ("slack", "U1:thread")
+        );
+        assert!(parse_user("123").is_err());
+        assert!(parse_user("telegram:").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 // ============================================================================
@@ -133,6 +134,154 @@ pub struct Config {
 
     /// Global onboarding prompt (can be overridden per channel)
     pub onboarding_prompt: Option<String>,
+
+    /// OpenAI-compatible API server (disabled when absent)
+    pub api: Option<ApiConfig>,
+
+    /// Voice note transcription (disabled when absent)
+    pub transcription: Option<TranscriptionConfig>,
+
+    /// Incoming file attachments
+    #[serde(default)]
+    pub attachments: AttachmentsConfig,
+
+    /// Reaction feedback on replies
+    #[serde(default)]
+    pub feedback: FeedbackConfig,
+
+    /// Users who are sent new pairing requests to approve, as "channel:user_id"
+    #[serde(default)]
+    pub admins: Vec<String>,
+
+    /// Identities linked with /link continue one conversation across channels
+    #[serde(default)]
+    pub share_linked_sessions: bool,
+
+    /// Keep the latest location a user shares in their USER.md
+    #[serde(default)]
+    pub remember_location: bool,
+
+    /// Custom roles, or overrides of the built-in owner, member and guest
+    #[serde(default)]
+    pub roles: HashMap<String, Role>,
+
+    /// Role of approved users who haven't been given one (default: member)
+    pub default_role: Option<String>,
+}
+
+/// Incoming attachment configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentsConfig {
+    /// Largest document accepted, in megabytes (default: 20)
+    #[serde(default = "default_max_attachment_mb")]
+    pub max_size_mb: u64,
+}
+
+impl Default for AttachmentsConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_max_attachment_mb(),
+        }
+    }
+}
+
+fn default_max_attachment_mb() -> u64 {
+    20
+}
+
+/// Reaction feedback configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackConfig {
+    /// Days replies and their reactions are kept, 0 for forever (default: 30)
+    #[serde(default = "default_feedback_keep_days")]
+    pub keep_days: u64,
+}
+
+impl Default for FeedbackConfig {
+    fn default() -> Self {
+        Self {
+            keep_days: default_feedback_keep_days(),
+        }
+    }
+}
+
+fn default_feedback_keep_days() -> u64 {
+    30
+}
+
+/// OpenAI-compatible API server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Address to bind to (default: 127.0.0.1)
+    #[serde(default = "default_api_host")]
+    pub host: String,
+    /// Port to listen on (default: 18790)
+    #[serde(default = "default_api_port")]
+    pub port: u16,
+    /// Base URL clients reach the API at, shown by /token
+    /// (e.g. "https://cica.example.com/v1")
+    pub public_url: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            host: default_api_host(),
+            port: default_api_port(),
+            public_url: None,
+        }
+    }
+}
+
+impl ApiConfig {
+    /// Base URL to give users for their clients
+    pub fn base_url(&self) -> String {
+        if let Some(url) = &self.public_url {
+            return url.trim_end_matches('/').to_string();
+        }
+        let host = match self.host.as_str() {
+            // Listening on every interface says nothing about which one to use
+            "0.0.0.0" | "::" | "[::]" => "<server address>".to_string(),
+            host if host.contains(':') && !host.starts_with('[') => format!("[{}]", host),
+            host => host.to_string(),
+        };
+        format!("http://{}:{}/v1", host, self.port)
+    }
+}
+
+fn default_api_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_api_port() -> u16 {
+    18790
+}
+
+/// Voice note transcription configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionConfig {
+    /// Hugging Face repo with a Whisper ONNX export (default: onnx-community/whisper-base)
+    #[serde(default = "default_transcription_model")]
+    pub model: String,
+    /// Spoken language as an ISO 639-1 code, e.g. "en" (default: detect)
+    pub language: Option<String>,
+    /// Reply with the transcript before answering
+    #[serde(default)]
+    pub echo_transcript: bool,
+}
+
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        Self {
+            model: default_transcription_model(),
+            language: None,
+            echo_transcript: false,
+        }
+    }
+}
+
+fn default_transcription_model() -> String {
+    "onnx-community/whisper-base".to_string()
 }
 
 /// All channel configurations
@@ -141,6 +290,10 @@ pub struct ChannelsConfig {
     pub telegram: Option<TelegramConfig>,
     pub signal: Option<SignalConfig>,
     pub slack: Option<SlackConfig>,
+    pub irc: Option<IrcConfig>,
+    /// External channel plugins (executables speaking JSON-RPC over stdio)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginChannelConfig>,
 }
 
 /// Telegram-specific configuration
@@ -153,6 +306,22 @@ pub struct TelegramConfig {
     #[serde(default)]
     pub shared_identity: bool,
     pub onboarding_prompt: Option<String>,
+    /// What happens to a running answer when another message arrives
+    #[serde(default)]
+    pub interruption: InterruptionPolicy,
+    /// How long to wait for more messages before answering (default: 200)
+    pub debounce_ms: Option<u64>,
+    /// Senders approved without a pairing code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Senders whose messages are ignored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub block: Vec<String>,
+    /// Give each approved group its own shared memory
+    #[serde(default)]
+    pub group_memory: bool,
+    /// Receive updates through a webhook instead of long polling
+    pub webhook: Option<TelegramWebhookConfig>,
 }
 
 impl TelegramConfig {
@@ -164,6 +333,34 @@ impl TelegramConfig {
     }
 }
 
+/// Telegram webhook configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramWebhookConfig {
+    /// Public HTTPS URL Telegram posts updates to (e.g. "https://bot.example.com/telegram").
+    /// Its path is the path the listener accepts updates on.
+    pub url: String,
+    /// Address to bind to (default: 127.0.0.1)
+    #[serde(default = "default_webhook_host")]
+    pub host: String,
+    /// Port to listen on (default: 18791)
+    #[serde(default = "default_webhook_port")]
+    pub port: u16,
+    /// Secret Telegram sends with every update; generated on startup when unset
+    pub secret_token: Option<String>,
+    /// Certificate chain (PEM) to serve HTTPS directly instead of behind a proxy
+    pub tls_cert: Option<String>,
+    /// Private key (PEM) for `tls_cert`
+    pub tls_key: Option<String>,
+}
+
+fn default_webhook_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_webhook_port() -> u16 {
+    18791
+}
+
 /// Signal-specific configuration
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SignalConfig {
@@ -174,6 +371,23 @@ pub struct SignalConfig {
     #[serde(default)]
     pub shared_identity: bool,
     pub onboarding_prompt: Option<String>,
+    /// What happens to a running answer when another message arrives
+    #[serde(default)]
+    pub interruption: InterruptionPolicy,
+    /// How long to wait for more messages before answering (default: 200)
+    pub debounce_ms: Option<u64>,
+    /// Senders approved without a pairing code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Senders whose messages are ignored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub block: Vec<String>,
+    /// Give each approved group its own shared memory
+    #[serde(default)]
+    pub group_memory: bool,
+    /// Where the signal-cli daemon listens, and whether Cica starts it
+    #[serde(default, skip_serializing_if = "SignalDaemonConfig::is_default")]
+    pub daemon: SignalDaemonConfig,
 }
 
 impl SignalConfig {
@@ -185,6 +399,54 @@ impl SignalConfig {
     }
 }
 
+/// signal-cli daemon connection
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SignalDaemonConfig {
+    /// Address of the daemon's HTTP server (default: 127.0.0.1)
+    #[serde(default = "default_signal_daemon_host")]
+    pub host: String,
+    /// Port of the daemon's HTTP server (default: 18080)
+    #[serde(default = "default_signal_daemon_port")]
+    pub port: u16,
+    /// Connect to a daemon started elsewhere instead of launching one
+    #[serde(default)]
+    pub external: bool,
+}
+
+impl Default for SignalDaemonConfig {
+    fn default() -> Self {
+        Self {
+            host: default_signal_daemon_host(),
+            port: default_signal_daemon_port(),
+            external: false,
+        }
+    }
+}
+
+impl SignalDaemonConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// JSON-RPC endpoint for requests
+    pub fn rpc_url(&self) -> String {
+        format!("http://{}:{}/api/v1/rpc", self.host, self.port)
+    }
+
+    /// Server-sent events endpoint that pushes incoming messages
+    pub fn events_url(&self) -> String {
+        format!("http://{}:{}/api/v1/events", self.host, self.port)
+    }
+}
+
+fn default_signal_daemon_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_signal_daemon_port() -> u16 {
+    18080
+}
+
 /// Slack-specific configuration
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SlackConfig {
@@ -197,6 +459,17 @@ pub struct SlackConfig {
     #[serde(default)]
     pub shared_identity: bool,
     pub onboarding_prompt: Option<String>,
+    /// What happens to a running answer when another message arrives
+    #[serde(default)]
+    pub interruption: InterruptionPolicy,
+    /// How long to wait for more messages before answering (default: 200)
+    pub debounce_ms: Option<u64>,
+    /// Senders approved without a pairing code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Senders whose messages are ignored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub block: Vec<String>,
 }
 
 impl SlackConfig {
@@ -209,15 +482,283 @@ impl SlackConfig {
     }
 }
 
-/// Channel settings relevant to pairing/onboarding
+/// IRC-specific configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IrcConfig {
+    pub server: String,
+    #[serde(default = "default_irc_port")]
+    pub port: u16,
+    #[serde(default = "default_true")]
+    pub tls: bool,
+    pub nickname: String,
+    /// Server password (PASS), if the server requires one
+    pub password: Option<String>,
+    /// SASL PLAIN account name (defaults to the nickname)
+    pub sasl_username: Option<String>,
+    /// SASL PLAIN password; SASL is used when this is set
+    pub sasl_password: Option<String>,
+    /// Channels to join; the bot answers there when highlighted
+    #[serde(default)]
+    pub channels: Vec<String>,
+    #[serde(default)]
+    pub auto_approve: bool,
+    #[serde(default)]
+    pub shared_identity: bool,
+    pub onboarding_prompt: Option<String>,
+    /// What happens to a running answer when another message arrives
+    #[serde(default)]
+    pub interruption: InterruptionPolicy,
+    /// How long to wait for more messages before answering (default: 200)
+    pub debounce_ms: Option<u64>,
+    /// Senders approved without a pairing code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Senders whose messages are ignored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub block: Vec<String>,
+}
+
+impl IrcConfig {
+    pub fn new(server: String, port: u16, tls: bool, nickname: String) -> Self {
+        Self {
+            server,
+            port,
+            tls,
+            nickname,
+            password: None,
+            sasl_username: None,
+            sasl_password: None,
+            channels: Vec::new(),
+            auto_approve: false,
+            shared_identity: false,
+            onboarding_prompt: None,
+            interruption: InterruptionPolicy::default(),
+            debounce_ms: None,
+            allow: Vec::new(),
+            block: Vec::new(),
+        }
+    }
+}
+
+fn default_irc_port() -> u16 {
+    6697
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// External channel plugin configuration
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PluginChannelConfig {
+    /// Channel identifier used in user keys and directories (e.g., "irc")
+    pub name: String,
+    /// Display name for user-facing messages (defaults to the name)
+    pub display_name: Option<String>,
+    /// Executable to run
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub auto_approve: bool,
+    #[serde(default)]
+    pub shared_identity: bool,
+    pub onboarding_prompt: Option<String>,
+    /// What happens to a running answer when another message arrives
+    #[serde(default)]
+    pub interruption: InterruptionPolicy,
+    /// How long to wait for more messages before answering (default: 200)
+    pub debounce_ms: Option<u64>,
+    /// Senders approved without a pairing code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Senders whose messages are ignored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub block: Vec<String>,
+}
+
+/// What to do with a message that arrives while the previous one is being answered
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InterruptionPolicy {
+    /// Stop the running answer and start over with all messages together
+    #[default]
+    Abort,
+    /// Let the running answer finish, then answer the new messages
+    Queue,
+    /// Stop the running answer and continue its conversation with the new messages
+    Steer,
+}
+
+/// Role given to approved users who haven't been given one
+pub const DEFAULT_ROLE: &str = "member";
+
+/// What users with a role may do
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Role {
+    /// Commands the role may use, without the slash (all when unset)
+    pub commands: Option<Vec<String>>,
+    /// Skills the role may use (all when unset). The backend may not read
+    /// the others' files, though a role with full access and the shell can.
+    pub skills: Option<Vec<String>>,
+    /// Let the assistant use tools without asking, e.g. run commands and
+    /// edit files on the host. Without it, tools that need permission are
+    /// refused.
+    #[serde(default = "default_true")]
+    pub full_access: bool,
+    /// Tools the assistant may not use (Claude Code tool names, e.g. "Bash")
+    #[serde(default)]
+    pub disallowed_tools: Vec<String>,
+    /// Model to use instead of the backend's
+    pub model: Option<String>,
+    /// Messages a user may send per day (unlimited when unset)
+    pub daily_messages: Option<u32>,
+    /// Can manage other users, like those listed in `admins`
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl Role {
+    /// Everything, including managing other users
+    pub fn owner() -> Self {
+        Self {
+            admin: true,
+            ..Self::member()
+        }
+    }
+
+    /// Everything except managing other users
+    pub fn member() -> Self {
+        Self {
+            commands: None,
+            skills: None,
+            full_access: true,
+            disallowed_tools: Vec::new(),
+            model: None,
+            daily_messages: None,
+            admin: false,
+        }
+    }
+
+    /// Chatting only: no cron jobs, skills or tools that touch the host
+    pub fn guest() -> Self {
+        Self {
+            commands: Some(
+                ["commands", "new", "stop", "link", "unlink"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+            skills: Some(Vec::new()),
+            full_access: false,
+            disallowed_tools: Vec::new(),
+            model: None,
+            daily_messages: Some(100),
+            admin: false,
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "owner" => Some(Self::owner()),
+            "member" => Some(Self::member()),
+            "guest" => Some(Self::guest()),
+            _ => None,
+        }
+    }
+
+    /// Check if the role may use a command, given with or without the slash
+    pub fn allows_command(&self, command: &str) -> bool {
+        let command = command.trim_start_matches('/');
+        self.commands
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|c| c.trim_start_matches('/') == command))
+    }
+
+    /// Check if the role may use a skill
+    pub fn allows_skill(&self, skill: &str) -> bool {
+        self.skills
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|s| s == skill))
+    }
+}
+
+/// Channel settings relevant to pairing/onboarding and message handling
 #[derive(Debug, Clone, Default)]
 pub struct ChannelSettings {
     pub auto_approve: bool,
     pub shared_identity: bool,
     pub onboarding_prompt: Option<String>,
+    pub group_memory: bool,
+    pub interruption: InterruptionPolicy,
+    pub debounce_ms: Option<u64>,
+    pub allow: Vec<String>,
+    pub block: Vec<String>,
+}
+
+impl ChannelSettings {
+    /// Check if a sender is on the channel's allow list
+    pub fn is_allowed(&self, identifiers: &[String]) -> bool {
+        list_matches(&self.allow, identifiers)
+    }
+
+    /// Check if a sender is on the channel's block list
+    pub fn is_blocked(&self, identifiers: &[String]) -> bool {
+        list_matches(&self.block, identifiers)
+    }
+}
+
+/// Check if any of a sender's identifiers is on a list. Entries are matched
+/// case-insensitively, and `*@example.com` matches any address at a domain.
+fn list_matches(list: &[String], identifiers: &[String]) -> bool {
+    list.iter().any(|entry| {
+        let entry = entry.trim().to_lowercase();
+        identifiers.iter().any(|id| {
+            let id = id.to_lowercase();
+            match entry.strip_prefix('*') {
+                Some(domain) => domain.starts_with('@') && id.ends_with(domain),
+                None => id == entry,
+            }
+        })
+    })
 }
 
 impl Config {
+    /// Check if a user is listed in `admins`
+    pub fn is_admin(&self, channel: &str, user_id: &str) -> bool {
+        let key = format!("{}:{}", channel, user_id);
+        self.admins.contains(&key)
+    }
+
+    /// Look up a role by name; `[roles.<name>]` takes precedence over the
+    /// built-in roles
+    pub fn role(&self, name: &str) -> Option<Role> {
+        self.roles
+            .get(name)
+            .cloned()
+            .or_else(|| Role::builtin(name))
+    }
+
+    /// Names of the built-in and configured roles
+    pub fn role_names(&self) -> Vec<&str> {
+        let mut names = vec!["owner", "member", "guest"];
+        let mut custom: Vec<&str> = self
+            .roles
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !names.contains(name))
+            .collect();
+        custom.sort();
+        names.extend(custom);
+        names
+    }
+
+    /// Role of approved users who haven't been given one
+    pub fn default_role(&self) -> &str {
+        self.default_role.as_deref().unwrap_or(DEFAULT_ROLE)
+    }
+
     pub fn channel_settings(&self, channel: &str) -> ChannelSettings {
         let global_prompt = self.onboarding_prompt.clone();
 
@@ -230,6 +771,11 @@ impl Config {
                     auto_approve: c.auto_approve,
                     shared_identity: c.shared_identity,
                     onboarding_prompt: c.onboarding_prompt.clone().or(global_prompt.clone()),
+                    interruption: c.interruption,
+                    debounce_ms: c.debounce_ms,
+                    allow: c.allow.clone(),
+                    block: c.block.clone(),
+                    group_memory: c.group_memory,
                 })
                 .unwrap_or_default(),
             "signal" => self
@@ -240,6 +786,11 @@ impl Config {
                     auto_approve: c.auto_approve,
                     shared_identity: c.shared_identity,
                     onboarding_prompt: c.onboarding_prompt.clone().or(global_prompt.clone()),
+                    interruption: c.interruption,
+                    debounce_ms: c.debounce_ms,
+                    allow: c.allow.clone(),
+                    block: c.block.clone(),
+                    group_memory: c.group_memory,
                 })
                 .unwrap_or_default(),
             "slack" => self
@@ -250,9 +801,44 @@ impl Config {
                     auto_approve: c.auto_approve,
                     shared_identity: c.shared_identity,
                     onboarding_prompt: c.onboarding_prompt.clone().or(global_prompt.clone()),
+                    interruption: c.interruption,
+                    debounce_ms: c.debounce_ms,
+                    allow: c.allow.clone(),
+                    block: c.block.clone(),
+                    ..Default::default()
+                })
+                .unwrap_or_default(),
+            "irc" => self
+                .channels
+                .irc
+                .as_ref()
+                .map(|c| ChannelSettings {
+                    auto_approve: c.auto_approve,
+                    shared_identity: c.shared_identity,
+                    onboarding_prompt: c.onboarding_prompt.clone().or(global_prompt.clone()),
+                    interruption: c.interruption,
+                    debounce_ms: c.debounce_ms,
+                    allow: c.allow.clone(),
+                    block: c.block.clone(),
+                    ..Default::default()
+                })
+                .unwrap_or_default(),
+            _ => self
+                .channels
+                .plugins
+                .iter()
+                .find(|p| p.name == channel)
+                .map(|c| ChannelSettings {
+                    auto_approve: c.auto_approve,
+                    shared_identity: c.shared_identity,
+                    onboarding_prompt: c.onboarding_prompt.clone().or(global_prompt.clone()),
+                    interruption: c.interruption,
+                    debounce_ms: c.debounce_ms,
+                    allow: c.allow.clone(),
+                    block: c.block.clone(),
+                    ..Default::default()
                 })
                 .unwrap_or_default(),
-            _ => ChannelSettings::default(),
         }
     }
 }
@@ -320,7 +906,7 @@ impl Config {
     }
 
     /// Get list of configured channel names
-    pub fn configured_channels(&self) -> Vec<&'static str> {
+    pub fn configured_channels(&self) -> Vec<&str> {
         let mut channels = Vec::new();
 
         if self.channels.telegram.is_some() {
@@ -332,6 +918,12 @@ impl Config {
         if self.channels.slack.is_some() {
             channels.push("slack");
         }
+        if self.channels.irc.is_some() {
+            channels.push("irc");
+        }
+        for plugin in &self.channels.plugins {
+            channels.push(plugin.name.as_str());
+        }
 
         channels
     }
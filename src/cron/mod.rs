@@ -5,13 +5,9 @@ mod schedule;
 pub mod store;
 
 pub use clock::{Clock, SystemClock};
-pub use schedule::CronSchedule;
+pub use schedule::{CronSchedule, parse_duration};
 pub use store::{CronJob, CronStore, JobId, JobStatus};
 
-// Re-export for tests
-#[cfg(test)]
-pub use clock::FakeClock;
-
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -25,6 +21,7 @@ use tracing::{debug, info, warn};
 use crate::backends::{self, QueryOptions};
 use crate::channels::get_channel_info;
 use crate::onboarding;
+use crate::pairing::PairingStore;
 
 /// Configuration for the cron service.
 #[derive(Clone)]
@@ -166,16 +163,18 @@ impl<C: Clock> CronService<C> {
     /// Remove a job.
     #[allow(dead_code)]
     pub async fn remove(&self, id: &str, channel: &str, user_id: &str) -> Result<Option<CronJob>> {
+        let pairing = PairingStore::load()?;
         let mut store = self.store.lock().await;
-        store.remove(id, channel, user_id)
+        store.remove(&pairing, id, channel, user_id)
     }
 
     /// List jobs for a user.
     #[allow(dead_code)]
     pub async fn list(&self, channel: &str, user_id: &str) -> Vec<CronJob> {
+        let pairing = PairingStore::load().unwrap_or_default();
         let store = self.store.lock().await;
         store
-            .list_for_user(channel, user_id)
+            .list_for_user(&pairing, channel, user_id)
             .into_iter()
             .cloned()
             .collect()
@@ -190,10 +189,11 @@ impl<C: Clock> CronService<C> {
         user_id: &str,
         result_sender: ResultSender,
     ) -> Result<()> {
+        let pairing = PairingStore::load()?;
         let job = {
             let store = self.store.lock().await;
             store
-                .get(id, channel, user_id)
+                .get(&pairing, id, channel, user_id)
                 .cloned()
                 .ok_or_else(|| anyhow::anyhow!("Job not found: {}", id))?
         };
@@ -208,18 +208,20 @@ impl<C: Clock> CronService<C> {
     /// Get job status.
     #[allow(dead_code)]
     pub async fn status(&self, id: &str, channel: &str, user_id: &str) -> Option<CronJob> {
+        let pairing = PairingStore::load().unwrap_or_default();
         let store = self.store.lock().await;
-        store.get(id, channel, user_id).cloned()
+        store.get(&pairing, id, channel, user_id).cloned()
     }
 
     /// Toggle job enabled state.
     #[allow(dead_code)]
     pub async fn toggle(&self, id: &str, channel: &str, user_id: &str) -> Result<bool> {
+        let pairing = PairingStore::load()?;
         let mut store = self.store.lock().await;
 
         // Verify ownership first
         let job = store
-            .get(id, channel, user_id)
+            .get(&pairing, id, channel, user_id)
             .ok_or_else(|| anyhow::anyhow!("Job not found: {}", id))?;
 
         let new_state = !job.enabled;
@@ -239,6 +241,17 @@ impl<C: Clock> CronService<C> {
     }
 }
 
+/// Pause the jobs of a user who lost access, so they stop running prompts.
+/// Returns how many jobs were paused.
+pub fn pause_jobs_for(channel: &str, user_id: &str) -> Result<usize> {
+    let mut store = CronStore::load()?;
+    let paused = store.pause_for_user(channel, user_id);
+    if paused > 0 {
+        store.save()?;
+    }
+    Ok(paused)
+}
+
 /// Execute a single job.
 async fn execute_job<C: Clock>(
     job: CronJob,
@@ -247,6 +260,25 @@ async fn execute_job<C: Clock>(
     clock: &C,
 ) {
     let job_id = job.id.clone();
+
+    // Jobs of users who have been revoked or blocked never run again
+    let pairing = PairingStore::load().unwrap_or_default();
+    let approved = pairing.is_approved(&job.channel, &job.user_id);
+    if !approved {
+        warn!(
+            "Pausing cron job {} of {}:{}, who no longer has access",
+            job.short_id(),
+            job.channel,
+            job.user_id
+        );
+        let mut store = store.lock().await;
+        if let Some(job) = store.get_mut(&job_id) {
+            job.enabled = false;
+            job.state.next_run_at = None;
+        }
+        let _ = store.save();
+        return;
+    }
     info!("Executing cron job: {} ({})", job.name, job.short_id());
 
     let start_time = clock.now_millis();
@@ -264,6 +296,7 @@ async fn execute_job<C: Clock>(
     // Build context prompt so the job has access to skills, configs, etc.
     let channel_display = get_channel_info(&job.channel).map(|c| c.display_name);
     let context_prompt = onboarding::build_context_prompt_for_user(
+        &pairing,
         channel_display,
         Some(&job.channel),
         Some(&job.user_id),
@@ -277,8 +310,7 @@ async fn execute_job<C: Clock>(
                 &job.prompt,
                 QueryOptions {
                     system_prompt: Some(ctx),
-                    skip_permissions: true,
-                    ..Default::default()
+                    ..QueryOptions::for_user(&job.channel, &job.user_id)
                 },
             )
             .await
@@ -80,7 +80,7 @@ impl CronSchedule {
 }
 
 /// Parse duration strings like "10s", "5m", "1h", "2d".
-fn parse_duration(s: &str) -> Result<u64, String> {
+pub fn parse_duration(s: &str) -> Result<u64, String> {
     let s = s.trim();
     if s.is_empty() {
         return Err("Empty duration string".to_string());
@@ -7,6 +7,7 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::config;
+use crate::pairing::PairingStore;
 
 use super::schedule::CronSchedule;
 
@@ -129,6 +130,13 @@ impl CronJob {
         format!("{}:{}", self.channel, self.user_id)
     }
 
+    /// Check if the job belongs to one of these (channel, user_id) identities.
+    pub fn is_owned_by(&self, owners: &[(String, String)]) -> bool {
+        owners
+            .iter()
+            .any(|(channel, user_id)| *channel == self.channel && *user_id == self.user_id)
+    }
+
     /// Calculate and update next_run_at based on given time.
     pub fn update_next_run(&mut self, now_ms: u64) {
         self.state.next_run_at = self.schedule.next_run_after(now_ms);
@@ -197,10 +205,16 @@ impl CronStore {
     }
 
     /// Remove a job by ID (only if user owns it).
-    pub fn remove(&mut self, id: &str, channel: &str, user_id: &str) -> Result<Option<CronJob>> {
+    pub fn remove(
+        &mut self,
+        pairing: &PairingStore,
+        id: &str,
+        channel: &str,
+        user_id: &str,
+    ) -> Result<Option<CronJob>> {
         // Check ownership first
         if let Some(job) = self.jobs.get(id)
-            && (job.channel != channel || job.user_id != user_id)
+            && !job.is_owned_by(&pairing.linked_identities(channel, user_id))
         {
             anyhow::bail!("You don't own this job");
         }
@@ -213,19 +227,45 @@ impl CronStore {
         Ok(removed)
     }
 
-    /// List jobs for a specific user.
-    pub fn list_for_user(&self, channel: &str, user_id: &str) -> Vec<&CronJob> {
+    /// List jobs for a specific user, including those of identities they linked.
+    pub fn list_for_user(
+        &self,
+        pairing: &PairingStore,
+        channel: &str,
+        user_id: &str,
+    ) -> Vec<&CronJob> {
+        let owners = pairing.linked_identities(channel, user_id);
         self.jobs
             .values()
-            .filter(|j| j.channel == channel && j.user_id == user_id)
+            .filter(|j| j.is_owned_by(&owners))
             .collect()
     }
 
     /// Get a job by ID (with ownership check).
-    pub fn get(&self, id: &str, channel: &str, user_id: &str) -> Option<&CronJob> {
+    pub fn get(
+        &self,
+        pairing: &PairingStore,
+        id: &str,
+        channel: &str,
+        user_id: &str,
+    ) -> Option<&CronJob> {
         self.jobs
             .get(id)
-            .filter(|j| j.channel == channel && j.user_id == user_id)
+            .filter(|j| j.is_owned_by(&pairing.linked_identities(channel, user_id)))
+    }
+
+    /// Pause every job a user created, e.g. when their access is taken away.
+    /// Returns how many jobs were paused.
+    pub fn pause_for_user(&mut self, channel: &str, user_id: &str) -> usize {
+        let mut paused = 0;
+        for job in self.jobs.values_mut() {
+            if job.channel == channel && job.user_id == user_id && job.enabled {
+                job.enabled = false;
+                job.state.next_run_at = None;
+                paused += 1;
+            }
+        }
+        paused
     }
 
     /// Get mutable reference (internal use, no ownership check).
@@ -359,4 +399,31 @@ mod tests {
 
         assert_eq!(job.user_key(), "telegram:12345");
     }
+
+    #[test]
+    fn test_pause_for_user() {
+        let mut store = CronStore::default();
+        for user_id in ["1", "1", "2"] {
+            let job = CronJob::new(
+                "Test".to_string(),
+                "Test".to_string(),
+                CronSchedule::Every(60_000),
+                "telegram".to_string(),
+                user_id.to_string(),
+            );
+            store.jobs.insert(job.id.clone(), job);
+        }
+
+        assert_eq!(store.pause_for_user("telegram", "1"), 2);
+        assert_eq!(store.pause_for_user("telegram", "1"), 0);
+        let running: Vec<_> = store.jobs.values().filter(|j| j.enabled).collect();
+        assert_eq!(running.len(), 1);
+        assert_eq!(running[0].user_id, "2");
+        assert!(
+            store
+                .get_due_jobs(u64::MAX)
+                .iter()
+                .all(|j| j.user_id == "2")
+        );
+    }
 }
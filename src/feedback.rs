@@ -0,0 +1,544 @@
+//! Reaction feedback on the assistant's replies.
+//!
+//! Every reply is logged with the ids of the chat messages it was sent as,
+//! and emoji reactions to those messages are logged next to it. Both live in
+//! `internal/transcripts/{channel}.jsonl`, one JSON record per line, and are
+//! dropped once they're older than `[feedback] keep_days`.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::{self, AiBackend, Config};
+use crate::skills;
+
+/// A line in a transcript file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Reply(ReplyRecord),
+    Reaction(ReactionRecord),
+}
+
+/// A reply the assistant sent, with what it was answering
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyRecord {
+    pub timestamp: u64,
+    pub user_id: String,
+    /// Channel message ids the reply was sent as (one per part)
+    pub message_ids: Vec<String>,
+    pub prompt: String,
+    pub reply: String,
+    pub backend: String,
+    pub model: Option<String>,
+    /// Skills whose files were read while answering
+    #[serde(default)]
+    pub skills: Vec<String>,
+}
+
+/// An emoji reaction added to (or removed from) one of the assistant's messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionRecord {
+    pub timestamp: u64,
+    pub message_id: String,
+    pub user_id: String,
+    pub emoji: String,
+    #[serde(default)]
+    pub removed: bool,
+}
+
+/// How a reaction reads as feedback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sentiment {
+    Positive,
+    Negative,
+    Neutral,
+}
+
+const POSITIVE: &[&str] = &[
+    "👍", "❤", "❤️", "🔥", "🥰", "👏", "🎉", "🤩", "🙏", "👌", "😍", "💯", "🏆", "✅", "😎", "🤝",
+];
+const NEGATIVE: &[&str] = &[
+    "👎", "🤬", "😢", "🤮", "💩", "🤡", "🥱", "💔", "😡", "😭", "❌", "🤨", "😐", "😕",
+];
+
+/// Classify an emoji as positive, negative or neutral feedback
+pub fn sentiment(emoji: &str) -> Sentiment {
+    // Skin tone modifiers don't change the meaning
+    let base: String = emoji
+        .chars()
+        .filter(|c| !('\u{1F3FB}'..='\u{1F3FF}').contains(c))
+        .collect();
+    if POSITIVE.contains(&base.as_str()) {
+        Sentiment::Positive
+    } else if NEGATIVE.contains(&base.as_str()) {
+        Sentiment::Negative
+    } else {
+        Sentiment::Neutral
+    }
+}
+
+// ============================================================================
+// Storage
+// ============================================================================
+
+/// Directory holding the transcript files
+pub fn transcripts_dir() -> Result<PathBuf> {
+    let dir = config::paths()?.internal_dir.join("transcripts");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn transcript_path(channel: &str) -> Result<PathBuf> {
+    Ok(transcripts_dir()?.join(format!("{}.jsonl", channel)))
+}
+
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// How often transcripts are checked for records past their retention
+const PRUNE_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Message ids of the replies in a channel's transcript, so reactions are
+/// matched without reading the file
+struct ChannelIndex {
+    message_ids: HashSet<String>,
+    pruned_at: u64,
+}
+
+/// Loaded per channel on first use, and held while its transcript is written
+static INDEX: Mutex<Option<HashMap<String, ChannelIndex>>> = Mutex::new(None);
+
+/// Run `f` with the channel's index, pruning the transcript first when it
+/// hasn't been for a day
+fn with_index<T>(channel: &str, f: impl FnOnce(&mut ChannelIndex) -> Result<T>) -> Result<T> {
+    let mut guard = INDEX.lock().unwrap();
+    let indexes = guard.get_or_insert_with(HashMap::new);
+
+    let now = now_timestamp();
+    let stale = indexes
+        .get(channel)
+        .is_none_or(|index| now.saturating_sub(index.pruned_at) >= PRUNE_INTERVAL_SECS);
+    if stale {
+        let keep_days = Config::load()
+            .map(|c| c.feedback.keep_days)
+            .unwrap_or_default();
+        let records = prune(channel, keep_days, now)?;
+        let message_ids = records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Reply(reply) => Some(reply.message_ids.iter().cloned()),
+                Record::Reaction(_) => None,
+            })
+            .flatten()
+            .collect();
+        indexes.insert(
+            channel.to_string(),
+            ChannelIndex {
+                message_ids,
+                pruned_at: now,
+            },
+        );
+    }
+
+    f(indexes.get_mut(channel).expect("index was just loaded"))
+}
+
+/// Drop a channel's records that are past retention, returning the rest
+fn prune(channel: &str, keep_days: u64, now: u64) -> Result<Vec<Record>> {
+    let records = load(channel)?;
+    if keep_days == 0 {
+        return Ok(records);
+    }
+
+    let count = records.len();
+    let kept = retain_recent(records, now.saturating_sub(keep_days * 24 * 60 * 60));
+    if kept.len() < count {
+        let path = transcript_path(channel)?;
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut file = open_private(&tmp, false)?;
+        for record in &kept {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        std::fs::rename(tmp, path)?;
+    }
+    Ok(kept)
+}
+
+/// Keep replies sent since `cutoff`, with their reactions
+fn retain_recent(records: Vec<Record>, cutoff: u64) -> Vec<Record> {
+    let kept_ids: HashSet<String> = records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Reply(reply) if reply.timestamp >= cutoff => Some(reply.message_ids.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    records
+        .into_iter()
+        .filter(|record| match record {
+            Record::Reply(reply) => reply.timestamp >= cutoff,
+            Record::Reaction(reaction) => kept_ids.contains(&reaction.message_id),
+        })
+        .collect()
+}
+
+/// Open a transcript file only its owner can read
+fn open_private(path: &Path, append: bool) -> Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).write(true);
+    if append {
+        options.append(true);
+    } else {
+        options.truncate(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    Ok(options.open(path)?)
+}
+
+fn append(channel: &str, record: &Record) -> Result<()> {
+    let mut file = open_private(&transcript_path(channel)?, true)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Read all records for a channel, skipping lines that don't parse
+pub fn load(channel: &str) -> Result<Vec<Record>> {
+    let path = transcript_path(channel)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Skills with a file among those the backend read
+fn skills_read(files_read: &[String]) -> Vec<String> {
+    skills::discover_skills()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|skill| {
+            let Some(dir) = skill.location.parent() else {
+                return false;
+            };
+            files_read
+                .iter()
+                .any(|file| Path::new(file).starts_with(dir))
+        })
+        .map(|skill| skill.name)
+        .collect()
+}
+
+/// Log a reply so reactions to its messages can be traced back to it.
+///
+/// `model` is the model the user's role picked, if any, and `files_read`
+/// the files the backend read while answering.
+pub fn record_reply(
+    channel: &str,
+    user_id: &str,
+    message_ids: Vec<String>,
+    prompt: &str,
+    reply: &str,
+    model: Option<&str>,
+    files_read: &[String],
+) -> Result<()> {
+    if message_ids.is_empty() {
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    let (backend, configured_model) = match config.backend {
+        AiBackend::Claude => ("claude", config.claude.model),
+        AiBackend::Cursor => ("cursor", config.cursor.model),
+    };
+
+    let record = Record::Reply(ReplyRecord {
+        timestamp: now_timestamp(),
+        user_id: user_id.to_string(),
+        message_ids: message_ids.clone(),
+        prompt: prompt.to_string(),
+        reply: reply.to_string(),
+        backend: backend.to_string(),
+        model: model.map(String::from).or(configured_model),
+        skills: skills_read(files_read),
+    });
+
+    with_index(channel, |index| {
+        append(channel, &record)?;
+        index.message_ids.extend(message_ids);
+        Ok(())
+    })
+}
+
+/// Log a reaction to one of the assistant's messages.
+/// Returns false (and stores nothing) when the message isn't a known reply.
+pub fn record_reaction(
+    channel: &str,
+    message_id: &str,
+    user_id: &str,
+    emoji: &str,
+    removed: bool,
+) -> Result<bool> {
+    with_index(channel, |index| {
+        if !index.message_ids.contains(message_id) {
+            return Ok(false);
+        }
+
+        append(
+            channel,
+            &Record::Reaction(ReactionRecord {
+                timestamp: now_timestamp(),
+                message_id: message_id.to_string(),
+                user_id: user_id.to_string(),
+                emoji: emoji.to_string(),
+                removed,
+            }),
+        )?;
+        Ok(true)
+    })
+}
+
+// ============================================================================
+// Report
+// ============================================================================
+
+/// Reaction counts for one group of replies
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tally {
+    pub positive: usize,
+    pub negative: usize,
+    pub neutral: usize,
+}
+
+impl Tally {
+    fn add(&mut self, sentiment: Sentiment) {
+        match sentiment {
+            Sentiment::Positive => self.positive += 1,
+            Sentiment::Negative => self.negative += 1,
+            Sentiment::Neutral => self.neutral += 1,
+        }
+    }
+}
+
+/// A reply together with the reactions it currently has
+#[derive(Debug, Clone)]
+pub struct RatedReply {
+    pub channel: String,
+    pub reply: ReplyRecord,
+    pub reactions: Vec<String>,
+}
+
+/// Feedback summarized across all replies
+#[derive(Debug, Default)]
+pub struct Report {
+    pub replies: usize,
+    pub total: Tally,
+    pub by_model: BTreeMap<String, Tally>,
+    pub by_skill: BTreeMap<String, Tally>,
+    pub by_emoji: BTreeMap<String, usize>,
+    /// Replies with at least one negative reaction, newest first
+    pub negative: Vec<RatedReply>,
+}
+
+/// Build a report from the records of each channel
+pub fn build_report(channels: &[(String, Vec<Record>)]) -> Report {
+    let mut report = Report::default();
+
+    for (channel, records) in channels {
+        // Replay additions and removals to get each message's current reactions
+        let mut current: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+        for record in records {
+            if let Record::Reaction(r) = record {
+                let reactions = current.entry(&r.message_id).or_default();
+                let key = (r.user_id.as_str(), r.emoji.as_str());
+                reactions.retain(|k| *k != key);
+                if !r.removed {
+                    reactions.push(key);
+                }
+            }
+        }
+
+        for record in records {
+            let Record::Reply(reply) = record else {
+                continue;
+            };
+            report.replies += 1;
+
+            let reactions: Vec<String> = reply
+                .message_ids
+                .iter()
+                .filter_map(|id| current.get(id.as_str()))
+                .flatten()
+                .map(|(_, emoji)| emoji.to_string())
+                .collect();
+            if reactions.is_empty() {
+                continue;
+            }
+
+            let model = match &reply.model {
+                Some(model) => format!("{} ({})", reply.backend, model),
+                None => reply.backend.clone(),
+            };
+            for emoji in &reactions {
+                let sentiment = sentiment(emoji);
+                report.total.add(sentiment);
+                report
+                    .by_model
+                    .entry(model.clone())
+                    .or_default()
+                    .add(sentiment);
+                for skill in &reply.skills {
+                    report
+                        .by_skill
+                        .entry(skill.clone())
+                        .or_default()
+                        .add(sentiment);
+                }
+                *report.by_emoji.entry(emoji.clone()).or_default() += 1;
+            }
+
+            if reactions
+                .iter()
+                .any(|e| sentiment(e) == Sentiment::Negative)
+            {
+                report.negative.push(RatedReply {
+                    channel: channel.clone(),
+                    reply: reply.clone(),
+                    reactions,
+                });
+            }
+        }
+    }
+
+    report
+        .negative
+        .sort_by_key(|rated| std::cmp::Reverse(rated.reply.timestamp));
+    report
+}
+
+/// Load every channel's transcript and summarize the feedback
+pub fn load_report() -> Result<Report> {
+    // Leaves the files alone, as the running service may be writing them
+    let keep_days = Config::load()?.feedback.keep_days;
+    let cutoff = match keep_days {
+        0 => 0,
+        days => now_timestamp().saturating_sub(days * 24 * 60 * 60),
+    };
+
+    let mut channels = Vec::new();
+    for entry in std::fs::read_dir(transcripts_dir()?)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Some(channel) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        channels.push((channel.to_string(), retain_recent(load(channel)?, cutoff)));
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(build_report(&channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(id: &str, model: Option<&str>, skills: &[&str]) -> Record {
+        Record::Reply(ReplyRecord {
+            timestamp: 10,
+            user_id: "1".to_string(),
+            message_ids: vec![id.to_string()],
+            prompt: "prompt".to_string(),
+            reply: "reply".to_string(),
+            backend: "claude".to_string(),
+            model: model.map(String::from),
+            skills: skills.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    fn reaction(id: &str, emoji: &str, removed: bool) -> Record {
+        Record::Reaction(ReactionRecord {
+            timestamp: 2,
+            message_id: id.to_string(),
+            user_id: "1".to_string(),
+            emoji: emoji.to_string(),
+            removed,
+        })
+    }
+
+    #[test]
+    fn test_sentiment() {
+        assert_eq!(sentiment("👍"), Sentiment::Positive);
+        assert_eq!(sentiment("👍🏽"), Sentiment::Positive);
+        assert_eq!(sentiment("👎"), Sentiment::Negative);
+        assert_eq!(sentiment("🐳"), Sentiment::Neutral);
+    }
+
+    #[test]
+    fn test_build_report() {
+        let records = vec![
+            reply("a", Some("sonnet"), &["weather"]),
+            reply("b", None, &[]),
+            reply("c", None, &[]),
+            reaction("a", "👍", false),
+            reaction("b", "👎", false),
+            reaction("c", "👎", false),
+            reaction("c", "👎", true),
+        ];
+        let report = build_report(&[("telegram".to_string(), records)]);
+
+        assert_eq!(report.replies, 3);
+        assert_eq!(
+            report.total,
+            Tally {
+                positive: 1,
+                negative: 1,
+                neutral: 0
+            }
+        );
+        assert_eq!(report.by_model["claude (sonnet)"].positive, 1);
+        assert_eq!(report.by_model["claude"].negative, 1);
+        assert_eq!(report.by_skill["weather"].positive, 1);
+        assert_eq!(report.negative.len(), 1);
+        assert_eq!(report.negative[0].reply.message_ids, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn test_retain_recent() {
+        let mut old = reply("old", None, &[]);
+        if let Record::Reply(r) = &mut old {
+            r.timestamp = 1;
+        }
+        let records = vec![
+            old,
+            reply("new", None, &[]),
+            reaction("old", "👍", false),
+            reaction("new", "👎", false),
+        ];
+
+        let kept = retain_recent(records, 5);
+        assert_eq!(kept.len(), 2);
+        assert!(kept.iter().all(|record| match record {
+            Record::Reply(r) => r.message_ids == ["new"],
+            Record::Reaction(r) => r.message_id == "new",
+        }));
+    }
+}
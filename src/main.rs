@@ -1,13 +1,16 @@
+mod api;
 mod backends;
 mod channels;
 mod cmd;
 mod config;
 mod cron;
+mod feedback;
 mod memory;
 mod onboarding;
 mod pairing;
 mod setup;
 mod skills;
+mod transcribe;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -33,8 +36,74 @@ enum Commands {
         code: String,
     },
 
+    /// Create an invite that approves people without a pairing code
+    Invite {
+        /// How many people can use it
+        #[arg(long, default_value_t = 1)]
+        uses: u32,
+
+        /// How long it stays valid (e.g. 1h, 7d, or "never")
+        #[arg(long, default_value = "7d")]
+        expires: String,
+
+        /// Role for the people who use it (defaults to default_role)
+        #[arg(long)]
+        role: Option<String>,
+    },
+
     /// Show where Cica stores its data
     Paths,
+
+    /// Summarize emoji reactions to Cica's replies
+    Feedback,
+
+    /// See and manage who has access
+    Users {
+        #[command(subcommand)]
+        command: Option<UsersCommand>,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// List approved and blocked users (the default)
+    List,
+
+    /// List pairing requests waiting for approval
+    Pending,
+
+    /// Take away a user's access
+    Revoke {
+        /// The user, as channel:user_id (e.g. telegram:123456789)
+        user: String,
+    },
+
+    /// Revoke a user's access and ignore their messages from now on
+    Block {
+        /// The user, as channel:user_id (e.g. telegram:123456789)
+        user: String,
+    },
+
+    /// Let a blocked user ask to be paired again
+    Unblock {
+        /// The user, as channel:user_id (e.g. telegram:123456789)
+        user: String,
+    },
+
+    /// Show a user's status, links, session and jobs
+    Info {
+        /// The user, as channel:user_id (e.g. telegram:123456789)
+        user: String,
+    },
+
+    /// Change a user's role (owner, member, guest or one from [roles])
+    Role {
+        /// The user, as channel:user_id (e.g. telegram:123456789)
+        user: String,
+
+        /// The role to give them
+        role: String,
+    },
 }
 
 #[tokio::main]
@@ -50,7 +119,22 @@ async fn main() -> Result<()> {
     match cli.command {
         Some(Commands::Init) => cmd::init::run().await,
         Some(Commands::Approve { code }) => cmd::approve::run(&code),
+        Some(Commands::Invite {
+            uses,
+            expires,
+            role,
+        }) => cmd::invite::run(uses, &expires, role).await,
         Some(Commands::Paths) => cmd::paths::run(),
+        Some(Commands::Feedback) => cmd::feedback::run(),
+        Some(Commands::Users { command }) => match command.unwrap_or(UsersCommand::List) {
+            UsersCommand::List => cmd::users::list(),
+            UsersCommand::Pending => cmd::users::pending(),
+            UsersCommand::Revoke { user } => cmd::users::revoke(&user),
+            UsersCommand::Block { user } => cmd::users::block(&user),
+            UsersCommand::Unblock { user } => cmd::users::unblock(&user),
+            UsersCommand::Info { user } => cmd::users::info(&user),
+            UsersCommand::Role { user, role } => cmd::users::set_role(&user, &role),
+        },
         None => cmd::run::run().await,
     }
 }
@@ -12,6 +12,7 @@ use tracing::{debug, info, warn};
 
 use crate::config;
 use crate::onboarding::user_dir;
+use crate::pairing::PairingStore;
 
 // Initialize sqlite-vec extension once
 static SQLITE_VEC_INIT: Once = Once::new();
@@ -63,8 +64,8 @@ where
 }
 
 /// Get the memories directory for a user
-pub fn memories_dir(channel: &str, user_id: &str) -> Result<PathBuf> {
-    Ok(user_dir(channel, user_id)?.join("memories"))
+pub fn memories_dir(store: &PairingStore, channel: &str, user_id: &str) -> Result<PathBuf> {
+    Ok(user_dir(store, channel, user_id)?.join("memories"))
 }
 
 /// Ensure the embedding model is downloaded (called during setup)
@@ -153,8 +154,16 @@ impl MemoryIndex {
     }
 
     /// Index all memory files for a user
-    pub fn index_user_memories(&mut self, channel: &str, user_id: &str) -> Result<()> {
-        let memories_path = memories_dir(channel, user_id)?;
+    pub fn index_user_memories(
+        &mut self,
+        store: &PairingStore,
+        channel: &str,
+        user_id: &str,
+    ) -> Result<()> {
+        // Linked identities share their memories, so index them under one owner
+        let (channel, user_id) = &store.identity(channel, user_id);
+        let (channel, user_id) = (channel.as_str(), user_id.as_str());
+        let memories_path = memories_dir(store, channel, user_id)?;
 
         if !memories_path.exists() {
             debug!("No memories directory for {}:{}", channel, user_id);
@@ -286,11 +295,15 @@ impl MemoryIndex {
     /// Search memories for a user
     pub fn search(
         &self,
+        store: &PairingStore,
         channel: &str,
         user_id: &str,
         query: &str,
         limit: usize,
     ) -> Result<Vec<MemorySearchResult>> {
+        let (channel, user_id) = &store.identity(channel, user_id);
+        let (channel, user_id) = (channel.as_str(), user_id.as_str());
+
         // Generate query embedding
         let query_bytes = with_embedding_model(|model| {
             let embeddings = model
@@ -334,13 +347,19 @@ impl MemoryIndex {
 
     /// Get all memory file paths for a user (for context building)
     #[allow(dead_code)]
-    pub fn list_memory_files(&self, channel: &str, user_id: &str) -> Result<Vec<String>> {
+    pub fn list_memory_files(
+        &self,
+        store: &PairingStore,
+        channel: &str,
+        user_id: &str,
+    ) -> Result<Vec<String>> {
+        let (channel, user_id) = store.identity(channel, user_id);
         let mut stmt = self
             .db
             .prepare("SELECT path FROM memory_files WHERE channel = ? AND user_id = ?")?;
 
         let paths = stmt
-            .query_map([channel, user_id], |row| row.get(0))?
+            .query_map([&channel, &user_id], |row| row.get(0))?
             .filter_map(|r| r.ok())
             .collect();
 
@@ -14,11 +14,12 @@
 //! - SKILLS.md - capabilities
 
 use anyhow::Result;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::warn;
 
 use crate::config;
 use crate::memory::{MemoryIndex, memories_dir};
+use crate::pairing::{self, PairingStore};
 use crate::setup;
 use crate::skills;
 
@@ -33,50 +34,147 @@ pub enum Phase {
     Complete,
 }
 
-/// Get the user directory path for a specific user
-pub fn user_dir(channel: &str, user_id: &str) -> Result<PathBuf> {
-    let dir = config::paths()?
-        .base
-        .join("users")
-        .join(format!("{}_{}", channel, user_id));
+/// Get the user directory path for a specific user.
+/// Users who linked identities across channels share one directory.
+pub fn user_dir(store: &PairingStore, channel: &str, user_id: &str) -> Result<PathBuf> {
+    let (channel, user_id) = store.identity(channel, user_id);
+    let users = config::paths()?.base.join("users");
+    let dir = users.join(user_dir_name(&channel, &user_id));
+
+    // Groups used to be stored under their raw pairing id
+    let legacy = users.join(format!("{}_{}", channel, user_id));
+    if legacy != dir
+        && legacy.exists()
+        && !dir.exists()
+        && let Err(e) = std::fs::rename(&legacy, &dir)
+    {
+        warn!(
+            "Failed to move {} to {}: {}",
+            legacy.display(),
+            dir.display(),
+            e
+        );
+    }
     Ok(dir)
 }
 
+/// Directory name for a user. A group's pairing id has a `:` and whatever
+/// characters the channel uses in group ids, so groups get a name with
+/// everything but letters, digits, `-`, `_`, `.` and `=` percent-encoded.
+fn user_dir_name(channel: &str, user_id: &str) -> String {
+    let Some(group_id) = pairing::group_id(user_id) else {
+        return format!("{}_{}", channel, user_id);
+    };
+    let mut name = format!("{}_group-", channel);
+    for byte in group_id.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.=".contains(&byte) {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+/// Move one user directory's files into another, e.g. when accounts are
+/// linked. A file both directories have is kept next to the existing one
+/// with `-{label}` added to its name.
+pub fn merge_user_dir(from: &Path, into: &Path, label: &str) -> Result<()> {
+    if !from.exists() || from == into {
+        return Ok(());
+    }
+    if !into.exists() {
+        if let Some(parent) = into.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(from, into)?;
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(from)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let mut dest = into.join(name);
+        if dest.is_dir() && path.is_dir() {
+            merge_user_dir(&path, &dest, label)?;
+            continue;
+        }
+        if dest.exists() {
+            let stem = dest
+                .file_stem()
+                .unwrap_or(name)
+                .to_string_lossy()
+                .into_owned();
+            dest.set_file_name(match dest.extension() {
+                Some(ext) => format!("{}-{}.{}", stem, label, ext.to_string_lossy()),
+                None => format!("{}-{}", stem, label),
+            });
+        }
+        if dest.exists() {
+            warn!(
+                "Leaving {} in place, {} exists",
+                path.display(),
+                dest.display()
+            );
+            continue;
+        }
+        std::fs::rename(&path, &dest)?;
+    }
+
+    // Only goes once everything has been moved out
+    let _ = std::fs::remove_dir(from);
+    Ok(())
+}
+
 /// Get the path to a user's IDENTITY.md
-pub fn identity_path_for_user(channel: &str, user_id: &str) -> Result<PathBuf> {
-    Ok(user_dir(channel, user_id)?.join("IDENTITY.md"))
+pub fn identity_path_for_user(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<PathBuf> {
+    Ok(user_dir(store, channel, user_id)?.join("IDENTITY.md"))
 }
 
 /// Get the path to a user's USER.md
-pub fn user_path_for_user(channel: &str, user_id: &str) -> Result<PathBuf> {
-    Ok(user_dir(channel, user_id)?.join("USER.md"))
+pub fn user_path_for_user(store: &PairingStore, channel: &str, user_id: &str) -> Result<PathBuf> {
+    Ok(user_dir(store, channel, user_id)?.join("USER.md"))
 }
 
 /// Check if a user's identity is configured
 #[allow(dead_code)]
-pub fn is_identity_configured_for_user(channel: &str, user_id: &str) -> Result<bool> {
-    Ok(identity_path_for_user(channel, user_id)?.exists())
+pub fn is_identity_configured_for_user(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<bool> {
+    Ok(identity_path_for_user(store, channel, user_id)?.exists())
 }
 
 /// Check if a user's profile is configured
 #[allow(dead_code)]
-pub fn is_user_configured_for_user(channel: &str, user_id: &str) -> Result<bool> {
-    Ok(user_path_for_user(channel, user_id)?.exists())
+pub fn is_user_configured_for_user(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<bool> {
+    Ok(user_path_for_user(store, channel, user_id)?.exists())
 }
 
 /// Get current onboarding phase for a specific user
-pub fn current_phase_for_user(channel: &str, user_id: &str) -> Result<Phase> {
+pub fn current_phase_for_user(store: &PairingStore, channel: &str, user_id: &str) -> Result<Phase> {
     let settings = crate::config::Config::load()
         .map(|c: crate::config::Config| c.channel_settings(channel))
         .unwrap_or_default();
 
     // If shared_identity is enabled, skip identity phase (use PERSONA.md)
-    if !settings.shared_identity && !identity_path_for_user(channel, user_id)?.exists() {
+    if !settings.shared_identity && !identity_path_for_user(store, channel, user_id)?.exists() {
         return Ok(Phase::Identity);
     }
 
     // Check if this user's profile is complete
-    if !user_path_for_user(channel, user_id)?.exists() {
+    if !user_path_for_user(store, channel, user_id)?.exists() {
         return Ok(Phase::User);
     }
 
@@ -84,22 +182,26 @@ pub fn current_phase_for_user(channel: &str, user_id: &str) -> Result<Phase> {
 }
 
 /// Check if onboarding is complete for a user
-pub fn is_complete_for_user(channel: &str, user_id: &str) -> Result<bool> {
-    Ok(current_phase_for_user(channel, user_id)? == Phase::Complete)
+pub fn is_complete_for_user(store: &PairingStore, channel: &str, user_id: &str) -> Result<bool> {
+    Ok(current_phase_for_user(store, channel, user_id)? == Phase::Complete)
 }
 
 /// Get the system prompt for a specific user's onboarding phase
-pub fn system_prompt_for_user(channel: &str, user_id: &str) -> Result<String> {
-    match current_phase_for_user(channel, user_id)? {
-        Phase::Identity => identity_system_prompt(channel, user_id),
-        Phase::User => user_system_prompt(channel, user_id),
+pub fn system_prompt_for_user(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<String> {
+    match current_phase_for_user(store, channel, user_id)? {
+        Phase::Identity => identity_system_prompt(store, channel, user_id),
+        Phase::User => user_system_prompt(store, channel, user_id),
         Phase::Complete => Ok(String::new()),
     }
 }
 
 /// System prompt for identity phase (per-user)
-fn identity_system_prompt(channel: &str, user_id: &str) -> Result<String> {
-    let path = identity_path_for_user(channel, user_id)?;
+fn identity_system_prompt(store: &PairingStore, channel: &str, user_id: &str) -> Result<String> {
+    let path = identity_path_for_user(store, channel, user_id)?;
 
     // Ensure user directory exists
     if let Some(parent) = path.parent() {
@@ -143,9 +245,9 @@ IMPORTANT: Do NOT write the file until you have all three answers."#,
 const DEFAULT_ONBOARDING_PROMPT: &str = "Tell me about yourself - the more I know about you the better I'll be able to help, so don't be shy!";
 
 /// System prompt for user profile phase (per-user)
-fn user_system_prompt(channel: &str, user_id: &str) -> Result<String> {
-    let identity_path = identity_path_for_user(channel, user_id)?;
-    let user_path = user_path_for_user(channel, user_id)?;
+fn user_system_prompt(store: &PairingStore, channel: &str, user_id: &str) -> Result<String> {
+    let identity_path = identity_path_for_user(store, channel, user_id)?;
+    let user_path = user_path_for_user(store, channel, user_id)?;
     let identity = std::fs::read_to_string(&identity_path).unwrap_or_default();
 
     let settings = config::Config::load()
@@ -189,8 +291,12 @@ IMPORTANT:
 }
 
 /// Load identity content for a specific user
-pub fn load_identity_for_user(channel: &str, user_id: &str) -> Result<Option<String>> {
-    let path = identity_path_for_user(channel, user_id)?;
+pub fn load_identity_for_user(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<Option<String>> {
+    let path = identity_path_for_user(store, channel, user_id)?;
     if !path.exists() {
         return Ok(None);
     }
@@ -198,14 +304,72 @@ pub fn load_identity_for_user(channel: &str, user_id: &str) -> Result<Option<Str
 }
 
 /// Load user profile content for a specific user
-pub fn load_user_for_user(channel: &str, user_id: &str) -> Result<Option<String>> {
-    let path = user_path_for_user(channel, user_id)?;
+pub fn load_user_for_user(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+) -> Result<Option<String>> {
+    let path = user_path_for_user(store, channel, user_id)?;
     if !path.exists() {
         return Ok(None);
     }
     Ok(Some(std::fs::read_to_string(&path)?))
 }
 
+/// The user's name from their USER.md, if they've been through onboarding
+pub fn user_name_for_user(store: &PairingStore, channel: &str, user_id: &str) -> Option<String> {
+    load_user_for_user(store, channel, user_id)
+        .ok()
+        .flatten()?
+        .lines()
+        .find_map(|l| l.strip_prefix("- Name:"))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Heading of the USER.md section holding the latest shared location
+const LOCATION_HEADING: &str = "## Last shared location";
+
+/// Record where the user last said they were in their USER.md, replacing
+/// the previous location. Does nothing before the profile exists.
+pub fn save_last_location(
+    store: &PairingStore,
+    channel: &str,
+    user_id: &str,
+    details: &str,
+) -> Result<()> {
+    let path = user_path_for_user(store, channel, user_id)?;
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let shared_at = chrono::Local::now().format("%Y-%m-%d %H:%M");
+    let section = format!("{}\n\n{}\nShared: {}", LOCATION_HEADING, details, shared_at);
+    std::fs::write(&path, replace_section(&content, LOCATION_HEADING, &section))?;
+    Ok(())
+}
+
+/// Replace a `## ` section of a markdown file, or append it if missing
+fn replace_section(content: &str, heading: &str, section: &str) -> String {
+    let mut kept: Vec<&str> = Vec::new();
+    let mut in_section = false;
+    for line in content.lines() {
+        if line.trim_end() == heading {
+            in_section = true;
+            continue;
+        }
+        if in_section && line.starts_with("## ") {
+            in_section = false;
+        }
+        if !in_section {
+            kept.push(line);
+        }
+    }
+
+    format!("{}\n\n{}\n", kept.join("\n").trim_end(), section)
+}
+
 /// Load persona content
 pub fn load_persona() -> Result<Option<String>> {
     let path = config::paths()?.base.join("PERSONA.md");
@@ -220,6 +384,7 @@ pub fn load_persona() -> Result<Option<String>> {
 /// If `user_message` is provided, it will be used to search for relevant memories
 /// to include in the context.
 pub fn build_context_prompt_for_user(
+    store: &PairingStore,
     channel_display: Option<&str>,
     channel_id: Option<&str>,
     user_id: Option<&str>,
@@ -230,7 +395,7 @@ pub fn build_context_prompt_for_user(
 
     // Load per-user identity
     let identity = if let (Some(ch), Some(uid)) = (channel_id, user_id) {
-        load_identity_for_user(ch, uid)?
+        load_identity_for_user(store, ch, uid)?
     } else {
         None
     };
@@ -247,7 +412,7 @@ pub fn build_context_prompt_for_user(
 
     // Load per-user profile
     let user_content = if let (Some(ch), Some(uid)) = (channel_id, user_id) {
-        load_user_for_user(ch, uid)?
+        load_user_for_user(store, ch, uid)?
     } else {
         None
     };
@@ -294,33 +459,67 @@ pub fn build_context_prompt_for_user(
         );
         lines.push(String::new());
 
-        // Channel-specific formatting
+        // Replies are written in Markdown and rendered for each channel
         lines.push("### Text Formatting".to_string());
         match channel_name.to_lowercase().as_str() {
-            "signal" => {
+            "irc" => {
                 lines.push(
-                    "Do NOT use any text formatting (no markdown, no asterisks, no underscores)."
+                    "IRC only displays plain text, one line at a time. Any Markdown is stripped."
                         .to_string(),
                 );
                 lines.push(
-                    "Signal requires special APIs for formatting that aren't available here."
+                    "Keep replies short and avoid long lists; every line is sent as a separate message."
                         .to_string(),
                 );
-                lines.push("Just use plain text.".to_string());
-            }
-            "telegram" => {
-                lines.push("Telegram supports standard markdown:".to_string());
-                lines.push("- **bold** or __bold__".to_string());
-                lines.push("- *italic* or _italic_".to_string());
-                lines.push("- ~strikethrough~".to_string());
-                lines.push("- `monospace` and ```code blocks```".to_string());
-                lines.push("- [links](url)".to_string());
+                lines.push("Write URLs out in full.".to_string());
             }
             _ => {
-                lines.push("Use plain text formatting.".to_string());
+                lines.push(format!(
+                    "Write in Markdown. It is converted to {}'s own formatting:",
+                    channel_name
+                ));
+                lines.push("- **bold**, *italic* and ~~strikethrough~~".to_string());
+                lines.push("- `monospace` and ```code blocks```".to_string());
+                lines.push("- [links](url), lists and > quotes".to_string());
+                lines.push("Tables are not supported; use lists instead.".to_string());
             }
         }
         lines.push(String::new());
+
+        lines.push("### Confirming Actions".to_string());
+        lines.push(
+            "Before doing something destructive or hard to undo (deleting files, overwriting data, sending messages on the user's behalf), describe what you are about to do and end your reply with [[confirm]] on its own line. Then stop and wait."
+                .to_string(),
+        );
+        lines.push(
+            "The user gets Confirm/Cancel buttons, and their answer arrives as their next message."
+                .to_string(),
+        );
+        lines.push(String::new());
+    }
+
+    // Group chats share one conversation between all members
+    let is_group = user_id.is_some_and(pairing::is_group);
+    let group_memory = is_group
+        && channel_id.is_some_and(|ch| {
+            config::Config::load()
+                .map(|c| c.channel_settings(ch).group_memory)
+                .unwrap_or(false)
+        });
+
+    if is_group {
+        lines.push("## Group Chat".to_string());
+        lines.push("You are a shared assistant in a group chat with several people.".to_string());
+        lines.push(
+            "Each message is prefixed with the sender's name, like \"Alice: ...\".".to_string(),
+        );
+        lines.push(
+            "You only see messages that mention you or reply to you, so address the sender by name when it helps."
+                .to_string(),
+        );
+        lines
+            .push("Don't reveal anything you know privately about individual members.".to_string());
+        lines.push(String::new());
     }
 
     // Skills section
@@ -331,8 +530,21 @@ pub fn build_context_prompt_for_user(
     );
     lines.push(String::new());
 
-    // Discover and list available skills
-    match skills::discover_skills() {
+    // Discover and list the skills the user's role allows
+    let role = match (channel_id, user_id) {
+        (Some(ch), Some(uid)) => match config::Config::load() {
+            Ok(config) => store.role(&config, ch, uid),
+            Err(_) => config::Role::guest(),
+        },
+        _ => config::Role::member(),
+    };
+    let discovered = skills::discover_skills().map(|found| {
+        found
+            .into_iter()
+            .filter(|skill| role.allows_skill(&skill.name))
+            .collect::<Vec<_>>()
+    });
+    match discovered {
         Ok(discovered) if !discovered.is_empty() => {
             lines.push("### Available Skills".to_string());
             lines.push("To use a skill, read its SKILL.md file at the location shown, then follow its instructions.".to_string());
@@ -349,26 +561,29 @@ pub fn build_context_prompt_for_user(
         }
     }
 
-    lines.push("### Creating Skills".to_string());
-    lines.push("When the user asks about something you can't do directly (like accessing email, calendar, APIs, etc.), offer to create a skill for it.".to_string());
-    lines.push(String::new());
-    lines.push("Each skill is a folder in skills/ containing:".to_string());
-    lines.push("1. **SKILL.md** (required) - Instructions with YAML frontmatter:".to_string());
-    lines.push("   ```".to_string());
-    lines.push("   ---".to_string());
-    lines.push("   name: my-skill".to_string());
-    lines.push("   description: What this skill does".to_string());
-    lines.push("   ---".to_string());
-    lines.push("   # My Skill".to_string());
-    lines.push("   Instructions for using this skill...".to_string());
-    lines.push("   ```".to_string());
-    lines.push("2. **index.ts** - The implementation (TypeScript/Bun preferred)".to_string());
-    lines.push(String::new());
-    lines.push(format!(
-        "Use the bundled Bun at: {}",
-        paths.bun_dir.join("bun").display()
-    ));
-    lines.push(String::new());
+    // Creating skills means writing and running code on the host
+    if role.full_access {
+        lines.push("### Creating Skills".to_string());
+        lines.push("When the user asks about something you can't do directly (like accessing email, calendar, APIs, etc.), offer to create a skill for it.".to_string());
+        lines.push(String::new());
+        lines.push("Each skill is a folder in skills/ containing:".to_string());
+        lines.push("1. **SKILL.md** (required) - Instructions with YAML frontmatter:".to_string());
+        lines.push("   ```".to_string());
+        lines.push("   ---".to_string());
+        lines.push("   name: my-skill".to_string());
+        lines.push("   description: What this skill does".to_string());
+        lines.push("   ---".to_string());
+        lines.push("   # My Skill".to_string());
+        lines.push("   Instructions for using this skill...".to_string());
+        lines.push("   ```".to_string());
+        lines.push("2. **index.ts** - The implementation (TypeScript/Bun preferred)".to_string());
+        lines.push(String::new());
+        lines.push(format!(
+            "Use the bundled Bun at: {}",
+            paths.bun_dir.join("bun").display()
+        ));
+        lines.push(String::new());
+    }
 
     // Skill configuration
     lines.push("### Skill Configuration".to_string());
@@ -489,9 +704,11 @@ pub fn build_context_prompt_for_user(
         lines.push(String::new());
     }
 
-    // Memory system
-    if let (Some(ch), Some(uid)) = (channel_id, user_id) {
-        let mem_dir = memories_dir(ch, uid)?;
+    // Memory system (groups only get one when group memory is enabled)
+    if let (Some(ch), Some(uid)) = (channel_id, user_id)
+        && (!is_group || group_memory)
+    {
+        let mem_dir = memories_dir(store, ch, uid)?;
 
         // Add memory guidance
         lines.push("## Memories".to_string());
@@ -521,7 +738,7 @@ pub fn build_context_prompt_for_user(
                     // Note: We don't call index_user_memories here because it's mutable
                     // That should be done at startup or when files change
 
-                    match index.search(ch, uid, query, 3) {
+                    match index.search(store, ch, uid, query, 3) {
                         Ok(results) if !results.is_empty() => {
                             lines.push("### Relevant Memories".to_string());
                             lines.push(
@@ -556,3 +773,38 @@ pub fn build_context_prompt_for_user(
 
     Ok(lines.join("\n"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_dir_name() {
+        assert_eq!(user_dir_name("telegram", "12345"), "telegram_12345");
+        assert_eq!(
+            user_dir_name("telegram", &pairing::group_user_id("-100123")),
+            "telegram_group--100123"
+        );
+        assert_eq!(
+            user_dir_name("irc", &pairing::group_user_id("#ops:dev")),
+            "irc_group-%23ops%3Adev"
+        );
+        assert_eq!(
+            user_dir_name("signal", &pairing::group_user_id("aB_c-d==")),
+            "signal_group-aB_c-d=="
+        );
+    }
+
+    #[test]
+    fn test_replace_section() {
+        let profile = "# USER.md\n\n- Name: Sam\n\n## Last shared location\n\nCoordinates: 1, 2\n\n## Notes\n\nLikes tea\n";
+        assert_eq!(
+            replace_section(
+                profile,
+                LOCATION_HEADING,
+                "## Last shared location\n\nCoordinates: 3, 4"
+            ),
+            "# USER.md\n\n- Name: Sam\n\n## Notes\n\nLikes tea\n\n## Last shared location\n\nCoordinates: 3, 4\n"
+        );
+    }
+}
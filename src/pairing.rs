@@ -2,8 +2,9 @@ use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::{Duration, SystemTime};
+use tracing::warn;
 
-use crate::config;
+use crate::config::{self, Config, Role};
 
 /// How long a pairing code remains valid
 const CODE_TTL: Duration = Duration::from_secs(60 * 60); // 1 hour
@@ -12,6 +13,13 @@ const CODE_TTL: Duration = Duration::from_secs(60 * 60); // 1 hour
 const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
 const CODE_LENGTH: usize = 8;
 
+/// How long a code for linking identities across channels remains valid
+const LINK_CODE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Prefix for pairing ids that stand for a whole group chat. No channel's
+/// user ids contain a colon (plugins' are checked), so it can't collide.
+const GROUP_PREFIX: &str = "group:";
+
 /// A pending pairing request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PendingRequest {
@@ -23,6 +31,32 @@ pub struct PendingRequest {
     pub created_at: u64, // Unix timestamp
 }
 
+/// A code a user created with /link, to be sent from another channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkCode {
+    pub code: String,
+    pub channel: String,
+    pub user_id: String,
+    pub created_at: u64, // Unix timestamp
+}
+
+/// An invitation that approves whoever sends its token, up to `uses_left` people
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub token: String,
+    pub uses_left: u32,
+    pub role: Option<String>,
+    pub expires_at: Option<u64>, // Unix timestamp
+    pub created_at: u64,         // Unix timestamp
+}
+
+/// Messages a user sent today, for daily quotas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub day: u64, // Days since the Unix epoch (UTC)
+    pub messages: u32,
+}
+
 /// Per-user profile data
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct UserProfile {
@@ -43,6 +77,20 @@ pub struct PairingStore {
     pub sessions: HashMap<String, String>, // "channel:user_id" -> session_id (UUID)
     #[serde(default)]
     pub user_profiles: HashMap<String, UserProfile>, // "channel:user_id" -> profile
+    #[serde(default)]
+    pub api_tokens: HashMap<String, String>, // token -> "channel:user_id"
+    #[serde(default)]
+    pub links: HashMap<String, String>, // "channel:user_id" -> "channel:user_id" whose files it shares
+    #[serde(default)]
+    pub link_codes: Vec<LinkCode>,
+    #[serde(default)]
+    pub blocked: HashMap<String, Vec<String>>, // channel -> [user_ids]
+    #[serde(default)]
+    pub roles: HashMap<String, String>, // "channel:user_id" -> role name
+    #[serde(default)]
+    pub usage: HashMap<String, DailyUsage>, // "channel:user_id" -> today's usage
+    #[serde(default)]
+    pub invites: Vec<Invite>,
 }
 
 impl PairingStore {
@@ -78,6 +126,24 @@ impl PairingStore {
         Ok(())
     }
 
+    /// Set or clear a conversation's session, saving it on top of what's on
+    /// disk. Queries run for minutes, and saving this copy whole would undo
+    /// changes made in the meantime, like a user being revoked.
+    pub fn update_session(&mut self, key: &str, session_id: Option<&str>) -> Result<()> {
+        let mut fresh = Self::load()?;
+        for store in [&mut *self, &mut fresh] {
+            match session_id {
+                Some(id) => {
+                    store.sessions.insert(key.to_string(), id.to_string());
+                }
+                None => {
+                    store.sessions.remove(key);
+                }
+            }
+        }
+        fresh.save()
+    }
+
     /// Remove expired pending requests
     pub fn prune_expired(&mut self) {
         let now = now_timestamp();
@@ -85,6 +151,8 @@ impl PairingStore {
 
         self.pending
             .retain(|req| now.saturating_sub(req.created_at) < ttl_secs);
+        self.invites
+            .retain(|invite| invite.expires_at.is_none_or(|at| now < at));
     }
 
     /// Check if a user is approved for a channel
@@ -95,6 +163,146 @@ impl PairingStore {
             .unwrap_or(false)
     }
 
+    /// Check if a user has been blocked on a channel
+    pub fn is_blocked(&self, channel: &str, user_id: &str) -> bool {
+        self.blocked
+            .get(channel)
+            .is_some_and(|ids| ids.iter().any(|id| id == user_id))
+    }
+
+    /// All approved users as (channel, user_id), sorted
+    pub fn approved_users(&self) -> Vec<(String, String)> {
+        let mut users: Vec<(String, String)> = self
+            .approved
+            .iter()
+            .flat_map(|(channel, ids)| ids.iter().map(|id| (channel.clone(), id.clone())))
+            .collect();
+        users.sort();
+        users.dedup();
+        users
+    }
+
+    /// Take away a user's access: their approval, pending requests, session
+    /// and API tokens. Their files are kept.
+    /// Returns false if they weren't approved.
+    pub fn revoke(&mut self, channel: &str, user_id: &str) -> Result<bool> {
+        let was_approved = self.remove_access(channel, user_id);
+        self.save()?;
+        Ok(was_approved)
+    }
+
+    /// Revoke a user's access and ignore their messages from now on, so
+    /// they can't ask to be paired again
+    pub fn block(&mut self, channel: &str, user_id: &str) -> Result<()> {
+        self.remove_access(channel, user_id);
+        if !self.is_blocked(channel, user_id) {
+            self.blocked
+                .entry(channel.to_string())
+                .or_default()
+                .push(user_id.to_string());
+        }
+        self.save()
+    }
+
+    /// Let a blocked user ask to be paired again.
+    /// Returns false if they weren't blocked.
+    pub fn unblock(&mut self, channel: &str, user_id: &str) -> Result<bool> {
+        let Some(ids) = self.blocked.get_mut(channel) else {
+            return Ok(false);
+        };
+        let before = ids.len();
+        ids.retain(|id| id != user_id);
+        let was_blocked = ids.len() < before;
+        if ids.is_empty() {
+            self.blocked.remove(channel);
+        }
+        self.save()?;
+        Ok(was_blocked)
+    }
+
+    /// Name of a user's role, falling back to the configured default
+    pub fn role_name(&self, config: &Config, channel: &str, user_id: &str) -> String {
+        let key = format!("{}:{}", channel, user_id);
+        self.roles
+            .get(&key)
+            .cloned()
+            .unwrap_or_else(|| config.default_role().to_string())
+    }
+
+    /// A user's role. An unknown role name grants no more than a guest.
+    pub fn role(&self, config: &Config, channel: &str, user_id: &str) -> Role {
+        let name = self.role_name(config, channel, user_id);
+        config.role(&name).unwrap_or_else(|| {
+            warn!(
+                "Unknown role {} for {}:{}, using guest",
+                name, channel, user_id
+            );
+            Role::guest()
+        })
+    }
+
+    /// Give a user a role
+    pub fn set_role(&mut self, channel: &str, user_id: &str, role: &str) -> Result<()> {
+        self.roles
+            .insert(format!("{}:{}", channel, user_id), role.to_string());
+        self.save()
+    }
+
+    /// Count a message against a user's daily limit.
+    /// Returns false, without counting it, if the limit has been reached.
+    pub fn use_message(
+        &mut self,
+        channel: &str,
+        user_id: &str,
+        limit: Option<u32>,
+    ) -> Result<bool> {
+        let Some(limit) = limit else {
+            return Ok(true);
+        };
+        if !self.count_message(channel, user_id, limit, now_timestamp() / 86_400) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn count_message(&mut self, channel: &str, user_id: &str, limit: u32, day: u64) -> bool {
+        let usage = self
+            .usage
+            .entry(format!("{}:{}", channel, user_id))
+            .or_insert(DailyUsage { day, messages: 0 });
+        if usage.day != day {
+            *usage = DailyUsage { day, messages: 0 };
+        }
+        if usage.messages >= limit {
+            return false;
+        }
+        usage.messages += 1;
+        true
+    }
+
+    fn remove_access(&mut self, channel: &str, user_id: &str) -> bool {
+        let mut was_approved = false;
+        if let Some(ids) = self.approved.get_mut(channel) {
+            let before = ids.len();
+            ids.retain(|id| id != user_id);
+            was_approved = ids.len() < before;
+            if ids.is_empty() {
+                self.approved.remove(channel);
+            }
+        }
+
+        let key = format!("{}:{}", channel, user_id);
+        self.pending
+            .retain(|r| !(r.channel == channel && r.user_id == user_id));
+        self.sessions.remove(&key);
+        self.api_tokens.retain(|_, owner| *owner != key);
+        self.roles.remove(&key);
+        self.usage.remove(&key);
+
+        was_approved
+    }
+
     /// Get or create a pending request for a user
     /// Returns (code, is_new)
     pub fn get_or_create_pending(
@@ -116,7 +324,7 @@ impl PairingStore {
         }
 
         // Generate new code
-        let code = generate_unique_code(&self.pending)?;
+        let code = generate_unique_code(self.pending.iter().map(|r| r.code.as_str()))?;
 
         let request = PendingRequest {
             code: code.clone(),
@@ -160,6 +368,24 @@ impl PairingStore {
         Ok(request)
     }
 
+    /// Deny a pending request by code, discarding it
+    /// Returns the denied request details on success
+    pub fn deny(&mut self, code: &str) -> Result<PendingRequest> {
+        self.prune_expired();
+
+        let code_upper = code.to_uppercase();
+        let idx = self
+            .pending
+            .iter()
+            .position(|r| r.code == code_upper)
+            .ok_or_else(|| anyhow!("No pending request found for code: {}", code))?;
+
+        let request = self.pending.remove(idx);
+        self.save()?;
+
+        Ok(request)
+    }
+
     /// Automatically approve a user without requiring a pairing code
     pub fn auto_approve(
         &mut self,
@@ -168,6 +394,9 @@ impl PairingStore {
         _username: Option<String>,
         _display_name: Option<String>,
     ) -> Result<()> {
+        // They may have asked to pair before being allowed
+        self.pending
+            .retain(|r| !(r.channel == channel && r.user_id == user_id));
         self.approved
             .entry(channel.to_string())
             .or_default()
@@ -175,8 +404,61 @@ impl PairingStore {
         self.save()
     }
 
+    /// Create an invitation for `uses` people, expiring after `ttl` if given
+    pub fn create_invite(
+        &mut self,
+        uses: u32,
+        ttl: Option<Duration>,
+        role: Option<String>,
+    ) -> Result<Invite> {
+        if uses == 0 {
+            return Err(anyhow!("An invite needs at least one use"));
+        }
+        self.prune_expired();
+
+        let now = now_timestamp();
+        let invite = Invite {
+            token: generate_invite_token(),
+            uses_left: uses,
+            role,
+            expires_at: ttl.map(|ttl| now + ttl.as_secs()),
+            created_at: now,
+        };
+        self.invites.push(invite.clone());
+        self.save()?;
+        Ok(invite)
+    }
+
+    /// Approve a user who sent an invite token, giving them the invite's role.
+    /// Returns false if the token is unknown, used up or expired.
+    pub fn redeem_invite(&mut self, channel: &str, user_id: &str, token: &str) -> Result<bool> {
+        let Some(invite) = self.take_invite(token, now_timestamp()) else {
+            return Ok(false);
+        };
+
+        if let Some(role) = invite.role {
+            self.roles.insert(format!("{}:{}", channel, user_id), role);
+        }
+        self.auto_approve(channel, user_id, None, None)?;
+        Ok(true)
+    }
+
+    /// Use up one place on a valid invite. Expired invites are dropped on the way.
+    fn take_invite(&mut self, token: &str, now: u64) -> Option<Invite> {
+        self.invites
+            .retain(|invite| invite.expires_at.is_none_or(|at| now < at));
+        let invite = self
+            .invites
+            .iter_mut()
+            .find(|invite| invite.token.eq_ignore_ascii_case(token))?;
+        invite.uses_left = invite.uses_left.saturating_sub(1);
+        let invite = invite.clone();
+
+        self.invites.retain(|invite| invite.uses_left > 0);
+        Some(invite)
+    }
+
     /// List all pending requests
-    #[allow(dead_code)]
     pub fn list_pending(&mut self) -> Vec<&PendingRequest> {
         self.prune_expired();
         self.pending.iter().collect()
@@ -234,6 +516,179 @@ impl PairingStore {
         self.save()
     }
 
+    /// Create an API token for a user, replacing any token they already had
+    pub fn create_api_token(&mut self, channel: &str, user_id: &str) -> Result<String> {
+        let key = format!("{}:{}", channel, user_id);
+        self.api_tokens.retain(|_, owner| *owner != key);
+
+        let token = format!("cica-{}", uuid::Uuid::new_v4().simple());
+        self.api_tokens.insert(token.clone(), key);
+        self.save()?;
+
+        Ok(token)
+    }
+
+    /// Resolve an API token to the (channel, user_id) it belongs to.
+    /// Tokens of users who are no longer approved are rejected.
+    pub fn user_for_api_token(&self, token: &str) -> Option<(String, String)> {
+        let key = self.api_tokens.get(token)?;
+        let (channel, user_id) = key.split_once(':')?;
+
+        if !self.is_approved(channel, user_id) {
+            return None;
+        }
+
+        Some((channel.to_string(), user_id.to_string()))
+    }
+
+    /// The "channel:user_id" whose files and jobs this user shares.
+    /// Users who haven't linked another identity are their own.
+    pub fn canonical_key(&self, channel: &str, user_id: &str) -> String {
+        let key = format!("{}:{}", channel, user_id);
+        self.links.get(&key).cloned().unwrap_or(key)
+    }
+
+    /// Resolve a user to the (channel, user_id) whose files they share
+    pub fn identity(&self, channel: &str, user_id: &str) -> (String, String) {
+        let key = self.canonical_key(channel, user_id);
+        match key.split_once(':') {
+            Some((channel, user_id)) => (channel.to_string(), user_id.to_string()),
+            None => (channel.to_string(), user_id.to_string()),
+        }
+    }
+
+    /// Every (channel, user_id) linked to the same person, including this one
+    pub fn linked_identities(&self, channel: &str, user_id: &str) -> Vec<(String, String)> {
+        let canonical = self.canonical_key(channel, user_id);
+        let mut keys: Vec<&str> = self
+            .links
+            .iter()
+            .filter(|(_, target)| **target == canonical)
+            .map(|(key, _)| key.as_str())
+            .collect();
+        keys.push(&canonical);
+        keys.sort();
+
+        keys.into_iter()
+            .filter_map(|key| key.split_once(':'))
+            .map(|(channel, user_id)| (channel.to_string(), user_id.to_string()))
+            .collect()
+    }
+
+    /// Create a code that links another channel's identity to this user,
+    /// replacing any code they already had
+    pub fn create_link_code(&mut self, channel: &str, user_id: &str) -> Result<String> {
+        let now = now_timestamp();
+        self.link_codes.retain(|c| {
+            now.saturating_sub(c.created_at) < LINK_CODE_TTL.as_secs()
+                && !(c.channel == channel && c.user_id == user_id)
+        });
+
+        let code = generate_unique_code(self.link_codes.iter().map(|c| c.code.as_str()))?;
+        self.link_codes.push(LinkCode {
+            code: code.clone(),
+            channel: channel.to_string(),
+            user_id: user_id.to_string(),
+            created_at: now,
+        });
+        self.save()?;
+
+        Ok(code)
+    }
+
+    /// Link this user to whoever created the code. From then on they share
+    /// that person's files, memories and cron jobs.
+    /// Returns the (channel, user_id) of the identity the code came from.
+    pub fn redeem_link_code(
+        &mut self,
+        code: &str,
+        channel: &str,
+        user_id: &str,
+    ) -> Result<(String, String)> {
+        let now = now_timestamp();
+        let code_upper = code.trim().to_uppercase();
+        let idx = self
+            .link_codes
+            .iter()
+            .position(|c| {
+                c.code == code_upper && now.saturating_sub(c.created_at) < LINK_CODE_TTL.as_secs()
+            })
+            .ok_or_else(|| anyhow!("No link code found for: {}", code))?;
+
+        let (from_channel, from_user_id) = {
+            let c = &self.link_codes[idx];
+            (c.channel.clone(), c.user_id.clone())
+        };
+        if from_channel == channel {
+            anyhow::bail!("Link codes have to be sent from a different channel");
+        }
+
+        let target = self.canonical_key(&from_channel, &from_user_id);
+        if self.canonical_key(channel, user_id) == target {
+            anyhow::bail!("These accounts are already linked");
+        }
+        self.link_to(channel, user_id, target);
+
+        self.link_codes.remove(idx);
+        self.save()?;
+
+        Ok((from_channel, from_user_id))
+    }
+
+    /// Point a user, and everyone already linked to them, at another identity
+    fn link_to(&mut self, channel: &str, user_id: &str, target: String) {
+        let key = format!("{}:{}", channel, user_id);
+        let previous = self.canonical_key(channel, user_id);
+
+        for linked in self.links.values_mut() {
+            if *linked == previous {
+                *linked = target.clone();
+            }
+        }
+        self.links.insert(previous, target.clone());
+        self.links.insert(key, target);
+        self.links.retain(|key, target| key != target);
+    }
+
+    /// Undo a user's link so they get their own files again. Anyone linked
+    /// to them stays linked to each other; when the shared files were kept
+    /// under this user, returns the (channel, user_id) they should move to.
+    pub fn unlink(&mut self, channel: &str, user_id: &str) -> Result<Option<(String, String)>> {
+        let heir = self.remove_link(channel, user_id)?;
+        self.save()?;
+
+        Ok(heir
+            .as_deref()
+            .and_then(|key| key.split_once(':'))
+            .map(|(channel, user_id)| (channel.to_string(), user_id.to_string())))
+    }
+
+    /// Take a user out of their linked group, handing the group to the first
+    /// of the others if it was named after them
+    fn remove_link(&mut self, channel: &str, user_id: &str) -> Result<Option<String>> {
+        let key = format!("{}:{}", channel, user_id);
+        if self.links.remove(&key).is_some() {
+            return Ok(None);
+        }
+
+        let mut others: Vec<String> = self
+            .links
+            .iter()
+            .filter(|(_, target)| **target == key)
+            .map(|(other, _)| other.clone())
+            .collect();
+        others.sort();
+        let Some(heir) = others.first().cloned() else {
+            anyhow::bail!("This account isn't linked to any other");
+        };
+
+        self.links.remove(&heir);
+        for other in &others[1..] {
+            self.links.insert(other.clone(), heir.clone());
+        }
+        Ok(Some(heir))
+    }
+
     /// Check if a user's onboarding is complete
     #[allow(dead_code)]
     pub fn is_user_onboarded(&self, channel: &str, user_id: &str) -> bool {
@@ -243,11 +698,38 @@ impl PairingStore {
     }
 }
 
+/// Look up a user's role. Falls back to guest when the config or pairing
+/// store can't be read.
+pub fn role_for(channel: &str, user_id: &str) -> Role {
+    match (Config::load(), PairingStore::load()) {
+        (Ok(config), Ok(store)) => store.role(&config, channel, user_id),
+        _ => Role::guest(),
+    }
+}
+
+/// Pairing id for a group chat.
+///
+/// Groups are approved as a whole: every member shares this id for approval,
+/// sessions, cron jobs and (optionally) memory.
+pub fn group_user_id(group_id: &str) -> String {
+    format!("{}{}", GROUP_PREFIX, group_id)
+}
+
+/// Get the channel's group id back from a pairing id, if it names a group
+pub fn group_id(user_id: &str) -> Option<&str> {
+    user_id.strip_prefix(GROUP_PREFIX)
+}
+
+/// Check if a pairing id names a group chat
+pub fn is_group(user_id: &str) -> bool {
+    group_id(user_id).is_some()
+}
+
 /// Generate a unique pairing code
-fn generate_unique_code(existing: &[PendingRequest]) -> Result<String> {
+fn generate_unique_code<'a>(existing: impl Iterator<Item = &'a str>) -> Result<String> {
     use std::collections::HashSet;
 
-    let existing_codes: HashSet<&str> = existing.iter().map(|r| r.code.as_str()).collect();
+    let existing_codes: HashSet<&str> = existing.collect();
 
     for _ in 0..100 {
         let code = generate_code();
@@ -280,6 +762,13 @@ fn generate_code() -> String {
         .collect()
 }
 
+/// Generate an invite token. Unlike pairing codes these let someone in on
+/// their own, so they come from a real random source. They also have to fit
+/// in a Telegram deep link (up to 64 of A-Z, a-z, 0-9, _ and -).
+fn generate_invite_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..20].to_string()
+}
+
 /// Simple PRNG for code generation (no external deps)
 struct SimpleRng(u64);
 
@@ -338,3 +827,168 @@ fn generate_uuid() -> String {
         bytes[15]
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_user_id() {
+        let id = group_user_id("-1001234");
+        assert_eq!(id, "group:-1001234");
+        assert!(is_group(&id));
+        assert_eq!(group_id(&id), Some("-1001234"));
+
+        assert!(!is_group("123456"));
+        assert!(!is_group("group-chat")); // an IRC account
+        assert_eq!(group_id("+15551234567"), None);
+    }
+
+    #[test]
+    fn test_remove_access() {
+        let mut store = PairingStore::default();
+        store.approved.insert(
+            "telegram".to_string(),
+            vec!["1".to_string(), "2".to_string()],
+        );
+        store
+            .sessions
+            .insert("telegram:1".to_string(), "session".to_string());
+        store
+            .api_tokens
+            .insert("cica-token".to_string(), "telegram:1".to_string());
+
+        assert!(store.remove_access("telegram", "1"));
+        assert!(!store.is_approved("telegram", "1"));
+        assert!(store.is_approved("telegram", "2"));
+        assert!(store.sessions.is_empty());
+        assert!(store.user_for_api_token("cica-token").is_none());
+        assert!(store.api_tokens.is_empty());
+        assert!(!store.remove_access("telegram", "1"));
+
+        assert_eq!(
+            store.approved_users(),
+            vec![("telegram".to_string(), "2".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut store = PairingStore::default();
+        let mut config = Config::default();
+        store
+            .roles
+            .insert("telegram:1".to_string(), "guest".to_string());
+        store
+            .roles
+            .insert("telegram:2".to_string(), "intern".to_string());
+
+        assert_eq!(store.role(&config, "telegram", "1"), Role::guest());
+        assert_eq!(store.role(&config, "telegram", "2"), Role::guest());
+        assert_eq!(store.role(&config, "telegram", "3"), Role::member());
+
+        config.default_role = Some("guest".to_string());
+        config.roles.insert(
+            "intern".to_string(),
+            Role {
+                commands: Some(vec!["cron".to_string()]),
+                ..Role::member()
+            },
+        );
+        assert_eq!(store.role(&config, "telegram", "3"), Role::guest());
+        let intern = store.role(&config, "telegram", "2");
+        assert!(intern.allows_command("/cron"));
+        assert!(!intern.allows_command("/token"));
+        assert!(!Role::guest().allows_command("/cron"));
+        assert!(!Role::guest().allows_skill("email"));
+    }
+
+    #[test]
+    fn test_take_invite() {
+        let mut store = PairingStore::default();
+        store.invites.push(Invite {
+            token: "abc123".to_string(),
+            uses_left: 2,
+            role: Some("guest".to_string()),
+            expires_at: Some(1_000),
+            created_at: 0,
+        });
+
+        store.invites.push(Invite {
+            token: "def456".to_string(),
+            uses_left: 1,
+            role: None,
+            expires_at: None,
+            created_at: 0,
+        });
+
+        assert!(store.take_invite("nope", 10).is_none());
+
+        let invite = store.take_invite("ABC123", 10).unwrap();
+        assert_eq!(invite.role.as_deref(), Some("guest"));
+        assert_eq!(store.invites[0].uses_left, 1);
+
+        // Used up
+        assert!(store.take_invite("def456", 20).is_some());
+        assert!(store.take_invite("def456", 30).is_none());
+
+        // Expired, and dropped even though another token was asked for
+        assert!(store.take_invite("nope", 1_000).is_none());
+        assert!(store.invites.is_empty());
+        assert!(store.take_invite("abc123", 1_000).is_none());
+    }
+
+    #[test]
+    fn test_count_message() {
+        let mut store = PairingStore::default();
+        assert!(store.count_message("signal", "+1", 2, 100));
+        assert!(store.count_message("signal", "+1", 2, 100));
+        assert!(!store.count_message("signal", "+1", 2, 100));
+        assert!(store.count_message("signal", "+2", 2, 100));
+        assert!(store.count_message("signal", "+1", 2, 101));
+    }
+
+    #[test]
+    fn test_linked_identities() {
+        let mut store = PairingStore::default();
+        store.link_to("signal", "+1555", "telegram:1".to_string());
+        store.link_to("signal", "+1555", "slack:U1".to_string());
+
+        // Linking the signal account again pulls the telegram account along
+        assert_eq!(store.canonical_key("telegram", "1"), "slack:U1");
+        assert_eq!(store.canonical_key("signal", "+1555"), "slack:U1");
+        assert_eq!(store.canonical_key("irc", "nick"), "irc:nick");
+        assert_eq!(
+            store.linked_identities("telegram", "1"),
+            vec![
+                ("signal".to_string(), "+1555".to_string()),
+                ("slack".to_string(), "U1".to_string()),
+                ("telegram".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_link() {
+        let mut store = PairingStore::default();
+        store.link_to("signal", "+1555", "slack:U1".to_string());
+        store.link_to("telegram", "1", "slack:U1".to_string());
+
+        // A linked account just leaves
+        assert_eq!(store.remove_link("signal", "+1555").unwrap(), None);
+        assert_eq!(store.canonical_key("signal", "+1555"), "signal:+1555");
+        assert_eq!(store.canonical_key("telegram", "1"), "slack:U1");
+
+        // The account the others were linked to hands the files on
+        store.link_to("signal", "+1555", "slack:U1".to_string());
+        assert_eq!(
+            store.remove_link("slack", "U1").unwrap(),
+            Some("signal:+1555".to_string())
+        );
+        assert_eq!(store.canonical_key("slack", "U1"), "slack:U1");
+        assert_eq!(store.canonical_key("telegram", "1"), "signal:+1555");
+        assert_eq!(store.canonical_key("signal", "+1555"), "signal:+1555");
+
+        assert!(store.remove_link("irc", "nick").is_err());
+    }
+}
@@ -1,11 +1,12 @@
-//! Setup utilities for downloading and configuring Bun, Claude Code, Java, signal-cli, and embedding models.
+//! Setup utilities for downloading and configuring Bun, Claude Code, Java, signal-cli, and local models.
 
 use anyhow::{Context, Result, anyhow, bail};
 use std::path::{Path, PathBuf};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::config;
 use crate::memory;
+use crate::transcribe;
 
 // ============================================================================
 // Pinned Versions
@@ -642,6 +643,23 @@ pub fn ensure_embedding_model() -> Result<()> {
     memory::ensure_model_downloaded()
 }
 
+// ============================================================================
+// Speech-to-text (for voice notes)
+// ============================================================================
+
+/// Check if ffmpeg is available (system install, used to decode voice notes)
+pub fn find_ffmpeg() -> Option<PathBuf> {
+    which::which("ffmpeg").ok()
+}
+
+/// Ensure the speech-to-text model is downloaded and ffmpeg is installed
+pub fn ensure_transcription(config: &crate::config::TranscriptionConfig) -> Result<()> {
+    if find_ffmpeg().is_none() {
+        warn!("ffmpeg not found; voice notes can't be transcribed until it is installed");
+    }
+    transcribe::ensure_model_downloaded(config)
+}
+
 // ============================================================================
 // Startup Dependency Check
 // ============================================================================
@@ -668,5 +686,10 @@ pub async fn ensure_deps(config: &crate::config::Config) -> Result<()> {
     }
 
     ensure_embedding_model()?;
+
+    if let Some(transcription) = &config.transcription {
+        ensure_transcription(transcription)?;
+    }
+
     Ok(())
 }
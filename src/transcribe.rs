@@ -0,0 +1,469 @@
+//! Speech-to-text for voice notes.
+//!
+//! Audio is decoded to 16 kHz mono with ffmpeg and transcribed locally with a
+//! Whisper model (ONNX export), downloaded into internal/models on first use
+//! alongside the embedding model.
+
+use anyhow::{Context, Result, anyhow, bail};
+use hf_hub::api::sync::ApiBuilder;
+use ort::session::Session;
+use ort::value::Tensor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokenizers::Tokenizer;
+use tracing::{debug, info};
+
+use crate::config::{self, TranscriptionConfig};
+use crate::setup;
+
+/// Sample rate Whisper expects
+const SAMPLE_RATE: usize = 16_000;
+/// STFT window size
+const N_FFT: usize = 400;
+/// STFT hop length
+const HOP_LENGTH: usize = 160;
+/// Whisper processes audio in 30 second windows
+const CHUNK_SAMPLES: usize = SAMPLE_RATE * 30;
+/// Mel frames per window
+const N_FRAMES: usize = CHUNK_SAMPLES / HOP_LENGTH;
+/// Maximum tokens generated per window
+const MAX_TOKENS: usize = 224;
+/// Longer recordings are truncated
+const MAX_AUDIO_SECS: usize = 10 * 60;
+
+// ============================================================================
+// Model
+// ============================================================================
+
+/// A loaded Whisper model
+struct Whisper {
+    repo: String,
+    encoder: Session,
+    decoder: Session,
+    tokenizer: Tokenizer,
+    n_mels: usize,
+    multilingual: bool,
+    mel_filters: Vec<f32>,
+}
+
+// Whisper model - loaded lazily on first use
+static WHISPER: Mutex<Option<Whisper>> = Mutex::new(None);
+
+/// Get the cache directory for models (shared with the embedding model)
+fn models_cache_dir() -> Result<PathBuf> {
+    Ok(config::paths()?.internal_dir.join("models"))
+}
+
+impl Whisper {
+    /// Download (if needed) and load a Whisper ONNX export from Hugging Face
+    fn load(repo_id: &str) -> Result<Self> {
+        let api = ApiBuilder::new()
+            .with_cache_dir(models_cache_dir()?)
+            .with_progress(false)
+            .build()?;
+        let repo = api.model(repo_id.to_string());
+
+        let fetch = |file: &str| {
+            repo.get(file)
+                .with_context(|| format!("Failed to download {} from {}", file, repo_id))
+        };
+
+        let model_config: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(fetch("config.json")?)?)?;
+        let n_mels = model_config["num_mel_bins"].as_u64().unwrap_or(80) as usize;
+        // English-only checkpoints have a smaller vocabulary without language tokens
+        let multilingual = model_config["vocab_size"].as_u64().unwrap_or(51865) >= 51865;
+
+        let tokenizer = Tokenizer::from_file(fetch("tokenizer.json")?)
+            .map_err(|e| anyhow!("Failed to load Whisper tokenizer: {}", e))?;
+        let encoder = Session::builder()?.commit_from_file(fetch("onnx/encoder_model.onnx")?)?;
+        let decoder = Session::builder()?.commit_from_file(fetch("onnx/decoder_model.onnx")?)?;
+
+        Ok(Self {
+            repo: repo_id.to_string(),
+            encoder,
+            decoder,
+            tokenizer,
+            n_mels,
+            multilingual,
+            mel_filters: mel_filters(n_mels),
+        })
+    }
+
+    fn token_id(&self, token: &str) -> Result<u32> {
+        self.tokenizer
+            .token_to_id(token)
+            .ok_or_else(|| anyhow!("Whisper tokenizer has no {} token", token))
+    }
+
+    /// Transcribe up to 30 seconds of 16 kHz mono audio
+    fn transcribe_window(&mut self, samples: &[f32], language: Option<&str>) -> Result<String> {
+        let features = log_mel_spectrogram(samples, &self.mel_filters, self.n_mels);
+        let input =
+            Tensor::from_array((vec![1i64, self.n_mels as i64, N_FRAMES as i64], features))?;
+
+        let outputs = self.encoder.run(ort::inputs!["input_features" => input])?;
+        let (shape, hidden) = outputs["last_hidden_state"].try_extract_tensor::<f32>()?;
+        let hidden_shape = shape.to_vec();
+        let hidden = hidden.to_vec();
+        drop(outputs);
+
+        let start = self.token_id("<|startoftranscript|>")?;
+        let end = self.token_id("<|endoftext|>")?;
+        let no_timestamps = self.token_id("<|notimestamps|>")?;
+
+        let mut tokens = vec![start];
+        if self.multilingual {
+            let language_token = match language {
+                Some(lang) => self.token_id(&format!("<|{}|>", lang))?,
+                None => {
+                    // Language tokens sit between <|startoftranscript|> and <|translate|>
+                    let translate = self.token_id("<|translate|>")?;
+                    let logits = self.next_logits(&tokens, &hidden_shape, &hidden)?;
+                    argmax(&logits[(start + 1) as usize..translate as usize])
+                        .map(|i| start + 1 + i as u32)
+                        .ok_or_else(|| anyhow!("Failed to detect language"))?
+                }
+            };
+            tokens.push(language_token);
+            tokens.push(self.token_id("<|transcribe|>")?);
+        }
+        tokens.push(no_timestamps);
+        let prompt_len = tokens.len();
+
+        // Greedy decoding
+        while tokens.len() - prompt_len < MAX_TOKENS {
+            let logits = self.next_logits(&tokens, &hidden_shape, &hidden)?;
+            let next = argmax(&logits).ok_or_else(|| anyhow!("Empty decoder output"))? as u32;
+            if next == end {
+                break;
+            }
+            tokens.push(next);
+        }
+
+        let text = self
+            .tokenizer
+            .decode(&tokens[prompt_len..], true)
+            .map_err(|e| anyhow!("Failed to decode transcript: {}", e))?;
+
+        Ok(text.trim().to_string())
+    }
+
+    /// Run the decoder and return the logits for the next token
+    fn next_logits(
+        &mut self,
+        tokens: &[u32],
+        hidden_shape: &[i64],
+        hidden: &[f32],
+    ) -> Result<Vec<f32>> {
+        let ids = Tensor::from_array((
+            vec![1i64, tokens.len() as i64],
+            tokens.iter().map(|&t| t as i64).collect::<Vec<_>>(),
+        ))?;
+        let states = Tensor::from_array((hidden_shape.to_vec(), hidden.to_vec()))?;
+
+        let outputs = self.decoder.run(ort::inputs![
+            "input_ids" => ids,
+            "encoder_hidden_states" => states
+        ])?;
+        let (shape, logits) = outputs["logits"].try_extract_tensor::<f32>()?;
+        let vocab = *shape
+            .last()
+            .ok_or_else(|| anyhow!("Invalid logits shape"))? as usize;
+
+        Ok(logits[logits.len() - vocab..].to_vec())
+    }
+}
+
+/// Get or initialize the Whisper model
+fn with_whisper_model<F, R>(repo_id: &str, f: F) -> Result<R>
+where
+    F: FnOnce(&mut Whisper) -> Result<R>,
+{
+    let mut guard = WHISPER
+        .lock()
+        .map_err(|e| anyhow!("Lock poisoned: {}", e))?;
+
+    if guard.as_ref().is_none_or(|w| w.repo != repo_id) {
+        info!("Loading speech-to-text model {}...", repo_id);
+        let model = Whisper::load(repo_id).context("Failed to initialize speech-to-text model")?;
+        info!("Speech-to-text model ready");
+        *guard = Some(model);
+    }
+
+    f(guard.as_mut().unwrap())
+}
+
+/// Ensure the speech-to-text model is downloaded (called during setup)
+pub fn ensure_model_downloaded(config: &TranscriptionConfig) -> Result<()> {
+    with_whisper_model(&config.model, |_| Ok(()))
+}
+
+// ============================================================================
+// Public API
+// ============================================================================
+
+/// Transcribe an audio file (any format ffmpeg can read)
+pub async fn transcribe(path: &Path, config: &TranscriptionConfig) -> Result<String> {
+    let path = path.to_path_buf();
+    let config = config.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut samples = decode_audio(&path)?;
+        if samples.len() > MAX_AUDIO_SECS * SAMPLE_RATE {
+            info!(
+                "Audio longer than {} minutes, transcribing the start only",
+                MAX_AUDIO_SECS / 60
+            );
+            samples.truncate(MAX_AUDIO_SECS * SAMPLE_RATE);
+        }
+
+        debug!(
+            "Transcribing {:.1}s of audio from {:?}",
+            samples.len() as f32 / SAMPLE_RATE as f32,
+            path
+        );
+
+        with_whisper_model(&config.model, |whisper| {
+            let mut parts = Vec::new();
+            for window in samples.chunks(CHUNK_SAMPLES) {
+                let text = whisper.transcribe_window(window, config.language.as_deref())?;
+                if !text.is_empty() {
+                    parts.push(text);
+                }
+            }
+            Ok(parts.join(" "))
+        })
+    })
+    .await?
+}
+
+// ============================================================================
+// Audio Processing
+// ============================================================================
+
+/// Decode an audio file to 16 kHz mono samples using ffmpeg
+fn decode_audio(path: &Path) -> Result<Vec<f32>> {
+    let ffmpeg = setup::find_ffmpeg()
+        .ok_or_else(|| anyhow!("ffmpeg not found. Install ffmpeg to transcribe voice notes."))?;
+
+    let output = std::process::Command::new(ffmpeg)
+        .args(["-nostdin", "-loglevel", "error", "-i"])
+        .arg(path)
+        .args(["-f", "f32le", "-ac", "1", "-ar"])
+        .arg(SAMPLE_RATE.to_string())
+        .arg("-")
+        .output()
+        .context("Failed to run ffmpeg")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffmpeg failed to decode {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(samples_from_f32le(&output.stdout))
+}
+
+/// Convert raw little-endian f32 PCM to samples
+fn samples_from_f32le(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Compute Whisper's log-mel spectrogram for one 30 second window.
+///
+/// Returns `n_mels * N_FRAMES` values in row-major (mel, frame) order.
+fn log_mel_spectrogram(samples: &[f32], filters: &[f32], n_mels: usize) -> Vec<f32> {
+    let n_freqs = N_FFT / 2 + 1;
+    let pad = N_FFT / 2;
+
+    // Pad or trim to exactly 30 seconds, then reflect-pad for centered frames
+    let mut audio = samples[..samples.len().min(CHUNK_SAMPLES)].to_vec();
+    audio.resize(CHUNK_SAMPLES, 0.0);
+
+    let mut padded = Vec::with_capacity(CHUNK_SAMPLES + 2 * pad);
+    padded.extend((1..=pad).rev().map(|i| audio[i]));
+    padded.extend_from_slice(&audio);
+    padded.extend((0..pad).map(|i| audio[CHUNK_SAMPLES - 2 - i]));
+
+    // Periodic Hann window and DFT tables
+    let window: Vec<f32> = (0..N_FFT)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / N_FFT as f32).cos())
+        .collect();
+    let mut cos_table = vec![0.0f32; n_freqs * N_FFT];
+    let mut sin_table = vec![0.0f32; n_freqs * N_FFT];
+    for k in 0..n_freqs {
+        for n in 0..N_FFT {
+            let angle = 2.0 * std::f32::consts::PI * ((k * n) % N_FFT) as f32 / N_FFT as f32;
+            cos_table[k * N_FFT + n] = angle.cos();
+            sin_table[k * N_FFT + n] = angle.sin();
+        }
+    }
+
+    let mut mel = vec![0.0f32; n_mels * N_FRAMES];
+    let mut frame = vec![0.0f32; N_FFT];
+    let mut power = vec![0.0f32; n_freqs];
+
+    for t in 0..N_FRAMES {
+        let offset = t * HOP_LENGTH;
+        for n in 0..N_FFT {
+            frame[n] = padded[offset + n] * window[n];
+        }
+
+        for k in 0..n_freqs {
+            let (cos_row, sin_row) = (
+                &cos_table[k * N_FFT..(k + 1) * N_FFT],
+                &sin_table[k * N_FFT..(k + 1) * N_FFT],
+            );
+            let (mut re, mut im) = (0.0f32, 0.0f32);
+            for n in 0..N_FFT {
+                re += frame[n] * cos_row[n];
+                im -= frame[n] * sin_row[n];
+            }
+            power[k] = re * re + im * im;
+        }
+
+        for m in 0..n_mels {
+            let row = &filters[m * n_freqs..(m + 1) * n_freqs];
+            let energy: f32 = row.iter().zip(&power).map(|(w, p)| w * p).sum();
+            mel[m * N_FRAMES + t] = energy.max(1e-10).log10();
+        }
+    }
+
+    // Dynamic range compression and scaling, as in the reference implementation
+    let max = mel.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    for value in &mut mel {
+        *value = ((*value).max(max - 8.0) + 4.0) / 4.0;
+    }
+
+    mel
+}
+
+/// Build a Slaney-style mel filterbank (`n_mels * (N_FFT / 2 + 1)`, row-major)
+fn mel_filters(n_mels: usize) -> Vec<f32> {
+    let n_freqs = N_FFT / 2 + 1;
+
+    let fft_freqs: Vec<f64> = (0..n_freqs)
+        .map(|k| k as f64 * SAMPLE_RATE as f64 / N_FFT as f64)
+        .collect();
+
+    let mel_min = hz_to_mel(0.0);
+    let mel_max = hz_to_mel(SAMPLE_RATE as f64 / 2.0);
+    let points: Vec<f64> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(mel_min + (mel_max - mel_min) * i as f64 / (n_mels + 1) as f64))
+        .collect();
+
+    let mut filters = vec![0.0f32; n_mels * n_freqs];
+    for m in 0..n_mels {
+        let (left, center, right) = (points[m], points[m + 1], points[m + 2]);
+        let norm = 2.0 / (right - left);
+        for (k, &f) in fft_freqs.iter().enumerate() {
+            let lower = (f - left) / (center - left);
+            let upper = (right - f) / (right - center);
+            filters[m * n_freqs + k] = (lower.min(upper).max(0.0) * norm) as f32;
+        }
+    }
+
+    filters
+}
+
+const MEL_F_SP: f64 = 200.0 / 3.0;
+const MEL_MIN_LOG_HZ: f64 = 1000.0;
+const MEL_MIN_LOG_MEL: f64 = MEL_MIN_LOG_HZ / MEL_F_SP;
+
+fn mel_log_step() -> f64 {
+    6.4f64.ln() / 27.0
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    if hz >= MEL_MIN_LOG_HZ {
+        MEL_MIN_LOG_MEL + (hz / MEL_MIN_LOG_HZ).ln() / mel_log_step()
+    } else {
+        hz / MEL_F_SP
+    }
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    if mel >= MEL_MIN_LOG_MEL {
+        MEL_MIN_LOG_HZ * (mel_log_step() * (mel - MEL_MIN_LOG_MEL)).exp()
+    } else {
+        MEL_F_SP * mel
+    }
+}
+
+/// Index of the largest value
+fn argmax(values: &[f32]) -> Option<usize> {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_from_f32le() {
+        let bytes: Vec<u8> = [0.5f32, -1.0, 0.25]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .chain([0u8, 1]) // trailing partial sample is ignored
+            .collect();
+        assert_eq!(samples_from_f32le(&bytes), vec![0.5, -1.0, 0.25]);
+    }
+
+    #[test]
+    fn test_mel_scale_roundtrip() {
+        for hz in [0.0, 440.0, 1000.0, 4000.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_mel_filters() {
+        let filters = mel_filters(80);
+        let n_freqs = N_FFT / 2 + 1;
+        assert_eq!(filters.len(), 80 * n_freqs);
+        assert!(filters.iter().all(|&w| w >= 0.0));
+        // Every filter covers at least one FFT bin
+        for m in 0..80 {
+            assert!(
+                filters[m * n_freqs..(m + 1) * n_freqs]
+                    .iter()
+                    .any(|&w| w > 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_log_mel_spectrogram_tone() {
+        // One second of a 1 kHz tone, padded to a full window
+        let samples: Vec<f32> = (0..SAMPLE_RATE)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let mel = log_mel_spectrogram(&samples, &mel_filters(80), 80);
+
+        assert_eq!(mel.len(), 80 * N_FRAMES);
+        assert!(mel.iter().all(|v| v.is_finite()));
+
+        // Loudest band in the middle of the tone sits near 1 kHz, not at the edges
+        let frame = 50;
+        let band = (0..80)
+            .max_by(|&a, &b| mel[a * N_FRAMES + frame].total_cmp(&mel[b * N_FRAMES + frame]))
+            .unwrap();
+        assert!((20..40).contains(&band), "peak in band {}", band);
+    }
+
+    #[test]
+    fn test_argmax() {
+        assert_eq!(argmax(&[0.1, 0.7, 0.2]), Some(1));
+        assert_eq!(argmax(&[]), None);
+    }
+}